@@ -0,0 +1,535 @@
+//! Command line shared by the simulations, each adding the way it simulates a model and the
+//! commands only it has. Nothing here prints or exits: the binaries print the warnings,
+//! reports and errors it gives them, and pick the exit code.
+
+use std::ffi::OsString;
+use std::fmt::{Debug, Display};
+use std::fs;
+
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use simulation_parser::brenda::{provenances, Database};
+use simulation_parser::check::check as check_model;
+use simulation_parser::diagnostic::{Diagnostic, Severity};
+use simulation_parser::formats::{self, Format};
+use simulation_parser::formula::parse_assignment;
+use simulation_parser::include::Sources;
+use simulation_parser::keywords::{check_language, Language};
+use simulation_parser::printer::{format, print};
+use simulation_parser::settings::{parse_distance, parse_duration, Settings};
+use simulation_parser::sweep::{self, parse_sweep, SweepDeclaration};
+use simulation_parser::Ast;
+
+/// What a simulation adds to the command line.
+pub trait Engine {
+    /// Options it adds to `convert`, [`NoOptions`] when it has none.
+    type ConvertOptions: clap::Args + Debug;
+    /// Subcommands only it has, [`NoCommand`] when it has none.
+    type Command: Subcommand + Debug;
+
+    /// Simulates `ast`, writing its results to `output` and the events fired next to them.
+    fn simulate(ast: Ast, settings: Settings, output: &str) -> anyhow::Result<()>;
+    /// The model converted as `options` ask, or `None` when they ask for no more than the
+    /// format the extension of `output` tells.
+    fn convert(
+        _options: &Self::ConvertOptions,
+        _ast: &Ast,
+        _settings: &Settings,
+        _output: &str,
+    ) -> Option<anyhow::Result<String>> {
+        None
+    }
+    /// Runs one of its subcommands on a model without errors.
+    fn run(command: &Self::Command, ast: &Ast, settings: &Settings) -> anyhow::Result<()>;
+}
+
+#[derive(Debug, clap::Args)]
+pub struct NoOptions {}
+
+#[derive(Debug, Subcommand)]
+pub enum NoCommand {}
+
+/// Why a command failed.
+#[derive(Debug)]
+pub enum Error {
+    /// The model has errors. `rendered` shows them, along with its warnings, in the files
+    /// they are in.
+    Model {
+        diagnostics: Vec<Diagnostic>,
+        rendered: String,
+    },
+    /// `fmt --check` found the file at this path not formatted.
+    Unformatted(String),
+    Other(anyhow::Error),
+}
+
+impl Error {
+    fn model(diagnostics: Vec<Diagnostic>, sources: &Sources) -> Self {
+        let rendered = render(&diagnostics, sources);
+        Error::Model {
+            diagnostics,
+            rendered,
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Model { rendered, .. } => write!(f, "{rendered}"),
+            Error::Unformatted(source) => write!(f, "{source} is not formatted"),
+            Error::Other(error) => write!(f, "error: {error:#}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<anyhow::Error> for Error {
+    fn from(error: anyhow::Error) -> Self {
+        Error::Other(error)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(error: std::io::Error) -> Self {
+        Error::Other(error.into())
+    }
+}
+
+fn render(diagnostics: &[Diagnostic], sources: &Sources) -> String {
+    diagnostics
+        .iter()
+        .map(|d| sources.render(d))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn errors(diagnostics: &[Diagnostic]) -> usize {
+    diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count()
+}
+
+#[derive(Debug, Parser)]
+pub struct Args<E: Engine> {
+    #[command(subcommand)]
+    pub command: Option<Command<E>>,
+
+    /// Source file of the reaction, or an SBML Level 3 model when it ends in `.xml` or
+    /// `.sbml`, or JSON, TOML or YAML data when it ends in `.json`, `.toml` or `.yaml`
+    #[arg(short, long, default_value = "reaction.txt", global = true)]
+    pub source: String,
+
+    /// Output file of the result of the simulation. The events fired are written next to
+    /// it, in `results.events.csv` for `results.csv`
+    #[arg(short, long, default_value = "results.csv")]
+    pub output: String,
+
+    /// Overrides a parameter of the model, e.g. `--set km1=150`
+    #[arg(long = "set", value_name = "NAME=VALUE", value_parser = parse_assignment, global = true)]
+    pub set: Vec<(String, f64)>,
+
+    /// Simulated duration, e.g. `10s`
+    #[arg(long, value_parser = parse_duration, global = true)]
+    pub end_time: Option<f32>,
+
+    /// Interval between two rows of the results, e.g. `50ms`
+    #[arg(long, value_parser = parse_duration, global = true)]
+    pub sample_every: Option<f32>,
+
+    /// Seed of the random number generator
+    #[arg(long, global = true)]
+    pub seed: Option<u64>,
+
+    /// Duration of a step, e.g. `100us`
+    #[arg(long, value_parser = parse_duration, global = true)]
+    pub dt: Option<f32>,
+
+    /// Radius of the simulated sphere, e.g. `500nm`
+    #[arg(long, value_parser = parse_distance, global = true)]
+    pub radius: Option<f32>,
+
+    /// Rejects keywords not spelt in this language, `french` or `english`, which is also
+    /// the one models are converted to text in
+    #[arg(long, value_name = "LANGUAGE", global = true)]
+    pub strict: Option<Language>,
+
+    /// BRENDA text file the Km and kcat of reactions written `brenda(EC, substrate)` are
+    /// looked up in
+    #[arg(long, value_name = "FILE", global = true)]
+    pub brenda: Option<String>,
+
+    /// Organism the BRENDA measurements are restricted to, for reactions not naming one;
+    /// the median of every organism is used otherwise
+    #[arg(long, requires = "brenda", global = true)]
+    pub organism: Option<String>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command<E: Engine> {
+    /// Reports the mistakes found in the model without simulating it
+    Check,
+    /// Formats the model file in place
+    Fmt {
+        /// Only report whether the file is formatted, failing when it is not
+        #[arg(long)]
+        check: bool,
+        /// Language the keywords are spelt in, `french` or `english`
+        #[arg(long, default_value = "french")]
+        language: Language,
+    },
+    /// Writes the model in the format the extension of the output tells: JSON, TOML, YAML
+    /// or the model language
+    Convert {
+        /// Output file of the model
+        output: String,
+        #[command(flatten)]
+        options: E::ConvertOptions,
+    },
+    /// Simulates the model for each combination of the values of the parameters it sweeps,
+    /// writing the results of each next to the output, `results.3.csv` for the fourth, and
+    /// which values each is for in `results.index.csv`
+    Sweep {
+        /// Further sweeps, e.g. `"km1 in 10..200 step 10"` or `"kcat in [50, 100, 200]"`
+        #[arg(value_parser = parse_sweep)]
+        sweeps: Vec<SweepDeclaration>,
+    },
+    #[command(flatten)]
+    Engine(E::Command),
+}
+
+impl<E: Engine> Args<E> {
+    /// Parses `args`, the command line of the binary `name` at `version`.
+    pub fn try_parse_as(
+        name: &'static str,
+        version: &'static str,
+        args: impl IntoIterator<Item = impl Into<OsString> + Clone>,
+    ) -> Result<Self, clap::Error> {
+        let matches = Self::command()
+            .name(name)
+            .version(version)
+            .try_get_matches_from(args)?;
+        Self::from_arg_matches(&matches)
+    }
+    /// The settings of the model, overridden by those given on the command line.
+    pub fn settings(&self, ast: &Ast) -> Settings {
+        let settings = ast.settings();
+        Settings {
+            end_time: self.end_time.unwrap_or(settings.end_time),
+            sample_every: self.sample_every.unwrap_or(settings.sample_every),
+            seed: self.seed.or(settings.seed),
+            dt: self.dt.unwrap_or(settings.dt),
+            radius: self.radius.unwrap_or(settings.radius),
+        }
+    }
+}
+
+/// Formats the model at `source` in place, or only checks that it is formatted.
+pub fn fmt(source: &str, check: bool, language: Language) -> Result<(), Error> {
+    let text = fs::read_to_string(source)?;
+    let formatted = format(&text, language).map_err(|error| Error::Model {
+        rendered: error.render(&text, source),
+        diagnostics: vec![error],
+    })?;
+    if formatted == text {
+        return Ok(());
+    }
+    if check {
+        return Err(Error::Unformatted(source.to_string()));
+    }
+    fs::write(source, formatted)?;
+    Ok(())
+}
+
+/// Reads the model at `arg.source`, its parameters overridden and its reactions filled from
+/// the BRENDA file `arg` gives, with the warnings of reading it and the files it was read
+/// from.
+pub fn load<E: Engine>(arg: &Args<E>) -> Result<(Ast, Vec<Diagnostic>, Sources), Error> {
+    let text = fs::read_to_string(&arg.source)?;
+    let mut sources = Sources::default();
+    let (mut ast, diagnostics) = formats::load(&arg.source, text, &mut sources)
+        .map_err(|error| Error::model(vec![error], &sources))?;
+    ast.resolve(&arg.set)
+        .map_err(|error| Error::model(vec![error], &sources))?;
+    if let Some(path) = &arg.brenda {
+        let database = Database::parse(&fs::read_to_string(path)?)
+            .map_err(|error| anyhow::anyhow!("{path}: {error}"))?;
+        let errors = database.fill(&mut ast, arg.organism.as_deref());
+        if !errors.is_empty() {
+            return Err(Error::model(errors, &sources));
+        }
+    }
+    Ok((ast, diagnostics, sources))
+}
+
+/// The mistakes found in the model with the settings of the command line, after
+/// `diagnostics`.
+pub fn check<E: Engine>(
+    arg: &Args<E>,
+    ast: &Ast,
+    settings: &Settings,
+    mut diagnostics: Vec<Diagnostic>,
+    sources: &Sources,
+) -> Vec<Diagnostic> {
+    diagnostics.extend(check_model(ast, settings));
+    if let Some(language) = arg.strict {
+        diagnostics.extend(check_language(ast, sources, language));
+    }
+    diagnostics
+}
+
+/// What `check` reports besides the mistakes: where the numbers looked up in BRENDA come
+/// from, and how many errors and warnings `diagnostics` has.
+fn report<E: Engine>(
+    arg: &Args<E>,
+    ast: &Ast,
+    diagnostics: &[Diagnostic],
+    sources: &Sources,
+) -> String {
+    let mut report = String::new();
+    for (reaction, provenance) in provenances(ast) {
+        report.push_str(&format!(
+            "{}: `{}` from BRENDA\n{provenance}\n",
+            sources.location(reaction.span),
+            reaction.enzhym
+        ));
+    }
+    let errors = errors(diagnostics);
+    report.push_str(&format!(
+        "{}: {} error(s), {} warning(s)\n",
+        arg.source,
+        errors,
+        diagnostics.len() - errors
+    ));
+    report
+}
+
+/// Simulates the model with `simulate` at each point of its sweeps and of `sweeps`, once
+/// the values of every point are checked to give a model without errors.
+pub fn sweep<E: Engine>(
+    arg: &Args<E>,
+    sweeps: &[SweepDeclaration],
+    mut ast: Ast,
+    sources: &Sources,
+    simulate: impl Fn(Ast, Settings, &str) -> anyhow::Result<()>,
+) -> Result<(), Error> {
+    let grid = sweep::grid(ast.sweeps().chain(sweeps));
+    let mut runs = vec![];
+    for point in &grid {
+        let overrides = arg.set.iter().chain(point).cloned().collect::<Vec<_>>();
+        ast.resolve(&overrides)
+            .map_err(|error| Error::model(vec![error], sources))?;
+        let settings = arg.settings(&ast);
+        let errors = check_model(&ast, &settings)
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .collect::<Vec<_>>();
+        if !errors.is_empty() {
+            let values = point
+                .iter()
+                .map(|(name, x)| format!("{name} = {x}"))
+                .collect::<Vec<_>>();
+            let rendered = format!("with {}:\n{}", values.join(", "), render(&errors, sources));
+            return Err(Error::Model {
+                diagnostics: errors,
+                rendered,
+            });
+        }
+        runs.push((ast.clone(), settings));
+    }
+    let files = (0..grid.len())
+        .map(|i| sweep::point_path(&arg.output, i))
+        .collect::<Vec<_>>();
+    for ((ast, settings), output) in runs.into_iter().zip(&files) {
+        simulate(ast, settings, output)?;
+    }
+    fs::write(
+        sweep::index_path(&arg.output),
+        sweep::index_csv(&grid, &files),
+    )?;
+    Ok(())
+}
+
+/// Runs the command `arg` gives, `E` simulating the model. The warnings found in the model
+/// are given to `warn` before the command runs, and what it reports is returned: what
+/// `check` found, nothing for the other commands.
+pub fn run<E: Engine>(arg: &Args<E>, mut warn: impl FnMut(&str)) -> Result<String, Error> {
+    if let Some(Command::Fmt { check, language }) = arg.command {
+        fmt(&arg.source, check, language)?;
+        return Ok(String::new());
+    }
+    let (ast, diagnostics, sources) = load(arg)?;
+    let settings = arg.settings(&ast);
+    let diagnostics = check(arg, &ast, &settings, diagnostics, &sources);
+    if errors(&diagnostics) > 0 {
+        let mut error = Error::model(diagnostics, &sources);
+        if let (
+            Some(Command::Check),
+            Error::Model {
+                diagnostics,
+                rendered,
+            },
+        ) = (&arg.command, &mut error)
+        {
+            rendered.push('\n');
+            rendered.push_str(report(arg, &ast, diagnostics, &sources).trim_end());
+        }
+        return Err(error);
+    }
+    for diagnostic in &diagnostics {
+        warn(&sources.render(diagnostic));
+    }
+    match &arg.command {
+        Some(Command::Check) => return Ok(report(arg, &ast, &diagnostics, &sources)),
+        Some(Command::Convert { output, options }) => {
+            let converted = match E::convert(options, &ast, &settings, output) {
+                Some(converted) => converted?,
+                None => match Format::of(output) {
+                    Some(format) => format.write(&ast).map_err(anyhow::Error::msg)?,
+                    None => print(&ast, arg.strict.unwrap_or_default()),
+                },
+            };
+            fs::write(output, converted)?;
+        }
+        Some(Command::Sweep { sweeps }) => sweep(arg, sweeps, ast, &sources, E::simulate)?,
+        Some(Command::Engine(command)) => E::run(command, &ast, &settings)?,
+        Some(Command::Fmt { .. }) | None => E::simulate(ast, settings, &arg.output)?,
+    }
+    Ok(String::new())
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::PathBuf;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use simulation_parser::Parsable;
+
+    use super::*;
+
+    /// An engine simulating nothing, to test the command line alone.
+    struct Simulation;
+
+    impl Engine for Simulation {
+        type ConvertOptions = NoOptions;
+        type Command = NoCommand;
+
+        fn simulate(_: Ast, _: Settings, _: &str) -> anyhow::Result<()> {
+            Ok(())
+        }
+
+        fn run(command: &NoCommand, _: &Ast, _: &Settings) -> anyhow::Result<()> {
+            match *command {}
+        }
+    }
+
+    fn parse(args: &[&str]) -> Result<Args<Simulation>, clap::Error> {
+        Args::try_parse_as("simulation", "0.1.0", args)
+    }
+
+    /// A directory of its own for each test, removed when it is dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let name = format!(
+                "cli_test_{}_{}",
+                std::process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed)
+            );
+            let dir = std::env::temp_dir().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+        fn write(&self, name: &str, text: &str) -> String {
+            let path = self.0.join(name);
+            fs::write(&path, text).unwrap();
+            path.display().to_string()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn t_args() {
+        Args::<Simulation>::command().debug_assert();
+        let arg = parse(&[
+            "simulation",
+            "sweep",
+            "km in [1, 2]",
+            "--set",
+            "k=1",
+            "--dt",
+            "1ms",
+        ])
+        .unwrap();
+        let Some(Command::Sweep { sweeps }) = &arg.command else {
+            panic!("expected a sweep")
+        };
+        assert_eq!("km", sweeps[0].identifier);
+        assert_eq!(vec![("k".to_string(), 1.)], arg.set);
+        let ast = Ast::parse("simulation { dt = 10 us; end_time = 2 s; }".into())
+            .unwrap()
+            .content;
+        let settings = arg.settings(&ast);
+        assert_eq!((1e-3, 2.), (settings.dt, settings.end_time));
+
+        assert!(parse(&["simulation", "--organism", "Homo sapiens"]).is_err());
+        assert!(parse(&["simulation", "convert", "model.json", "--expanded"]).is_err());
+        let version = parse(&["simulation", "--version"]).err().unwrap();
+        assert_eq!("simulation 0.1.0\n", version.to_string());
+    }
+
+    #[test]
+    fn t_run() {
+        let dir = TempDir::new();
+        let model = dir.write("model.txt", "E1 : s -> p | 100 - 50;\ninit(E1) = 30;\n");
+        let mut warnings = vec![];
+        let report = run(
+            &parse(&["simulation", "check", "-s", &model]).unwrap(),
+            |w| warnings.push(w.to_string()),
+        )
+        .unwrap();
+        assert!(report.ends_with("0 error(s), 1 warning(s)\n"));
+        assert_eq!(1, warnings.len());
+        assert!(warnings[0].contains("`s`"));
+
+        let broken = dir.write("broken.txt", "init(s) = 10;\nE1 : s -> p | -100 - 50;\n");
+        let Err(Error::Model {
+            diagnostics,
+            rendered,
+        }) = run(&parse(&["simulation", "-s", &broken]).unwrap(), |_| ())
+        else {
+            panic!("expected the errors of the model")
+        };
+        assert!(diagnostics.iter().any(|d| d.severity == Severity::Error));
+        assert!(rendered.contains("broken.txt:2:"));
+
+        let unformatted = dir.write("unformatted.txt", "init(s)=10;");
+        let error = run(
+            &parse(&["simulation", "fmt", "--check", "-s", &unformatted]).unwrap(),
+            |_| (),
+        )
+        .unwrap_err();
+        assert!(matches!(error, Error::Unformatted(_)));
+
+        let error = run(
+            &parse(&["simulation", "--set", "k=1", "-s", &model]).unwrap(),
+            |_| (),
+        )
+        .unwrap_err();
+        let Error::Model { diagnostics, .. } = error else {
+            panic!("expected the override to be rejected")
+        };
+        assert_eq!(
+            "the model declares no parameter `k`",
+            diagnostics[0].message
+        );
+    }
+}
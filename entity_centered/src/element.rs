@@ -1,10 +1,14 @@
 use std::hash::Hash;
 
+use crate::region::Region;
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Element {
     pub uuid: u64,
     pub radius: f32,
     pub speed: f32,
+    /// Where its molecules move, that of its compartment.
+    pub region: Region,
 }
 
 impl Eq for Element {}
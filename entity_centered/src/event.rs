@@ -0,0 +1,88 @@
+use simulation_parser::events::{EventDeclaration, Trigger};
+
+use crate::element::Element;
+use crate::molecule::Molecule;
+use crate::vector::generate_random_position;
+
+/// An event of the model, its species resolved to elements.
+#[derive(Debug)]
+pub struct Event {
+    pub declaration: EventDeclaration,
+    /// The element a `when` event watches.
+    pub watched: Option<Element>,
+    /// The element each action changes.
+    pub changed: Vec<Element>,
+    /// Whether the event may fire: a timed event until it has, a conditional one while its
+    /// condition does not hold.
+    pub armed: bool,
+}
+
+fn count(molecules: &[Molecule], element: Element) -> u64 {
+    molecules
+        .iter()
+        .filter(|m| m.kind.uuid == element.uuid)
+        .count() as u64
+}
+
+impl Event {
+    /// Whether the condition of a `when` event holds, `None` for a timed event.
+    pub fn holds(&self, molecules: &[Molecule]) -> Option<bool> {
+        match (&self.declaration.trigger, self.watched) {
+            (Trigger::When(c), Some(e)) => Some(c.holds(count(molecules, e))),
+            _ => None,
+        }
+    }
+    /// Adds or removes molecules until each species has the number its action gives, the
+    /// new ones at random positions of their region.
+    fn fire(&self, molecules: &mut Vec<Molecule>) {
+        for (action, &e) in self.declaration.actions.iter().zip(&self.changed) {
+            let before = count(molecules, e);
+            let after = action.apply(before);
+            if after > before {
+                molecules.extend((before..after).map(|_| Molecule {
+                    kind: e,
+                    position: generate_random_position(e.region),
+                }));
+            } else {
+                let mut removed = before - after;
+                molecules.retain(|m| {
+                    let remove = removed > 0 && m.kind.uuid == e.uuid;
+                    removed -= u64::from(remove);
+                    !remove
+                });
+            }
+        }
+    }
+}
+
+/// Fires the timed events happening at the start of `step`, steps lasting `dt` s, then the
+/// conditional events whose condition has become true, and returns those fired.
+pub fn fire_events(
+    events: &mut [Event],
+    molecules: &mut Vec<Molecule>,
+    step: u64,
+    dt: f32,
+) -> Vec<usize> {
+    let mut fired = vec![];
+    for (i, event) in events.iter_mut().enumerate() {
+        if let Trigger::At(time) = &event.declaration.trigger {
+            if event.armed && (time.value / dt).round() as u64 <= step {
+                event.armed = false;
+                event.fire(molecules);
+                fired.push(i);
+            }
+        }
+    }
+    for (i, event) in events.iter_mut().enumerate() {
+        let Some(holds) = event.holds(molecules) else {
+            continue;
+        };
+        let fires = holds && event.armed;
+        event.armed = !holds;
+        if fires {
+            event.fire(molecules);
+            fired.push(i);
+        }
+    }
+    fired
+}
@@ -3,47 +3,65 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 
 mod collided_molecule;
 mod element;
+mod event;
 mod molecule;
 mod moved_molecule;
 mod probability;
 mod reaction_registry;
+mod region;
 pub mod simulation;
 mod value_board;
 mod vector;
 
-use clap::Parser;
+use cli::{Args, Engine, NoCommand, NoOptions};
 use element::Element;
+use event::Event;
 use itertools::Itertools;
 use molecule::Molecule;
 use probability::Probability;
 use reaction_registry::ReactionRegistry;
+use region::Regions;
 use rustc_hash::FxHashMap;
 use simulation::run;
-use simulation_parser::{Ast, DiameterDeclaration, Parsable, SpeedDeclaration};
-use std::{fs, time::Instant};
+use simulation_parser::{
+    compartments::Compartments,
+    events::{self, Trigger},
+    settings::Settings,
+    Ast, Binding, DiameterDeclaration, Reaction, SpeedDeclaration,
+};
+use std::{env, fs, process::ExitCode};
 use value_board::ValueBoard;
 use vector::generate_random_position;
 
-#[derive(Debug, Default)]
+#[derive(Debug)]
 struct IdElementTable {
     table: FxHashMap<String, Element>,
     last_id: u64,
+    regions: Regions,
 }
 
 impl IdElementTable {
-    fn insert_by_speed(&mut self, element: SpeedDeclaration) -> Element {
+    fn new(regions: Regions) -> Self {
+        Self {
+            table: FxHashMap::default(),
+            last_id: 0,
+            regions,
+        }
+    }
+    fn insert_by_speed(&mut self, element: SpeedDeclaration, dt: f32) -> Element {
         self.table
             .entry(element.identifier.clone())
             .or_insert_with(|| {
                 let elem = Element {
                     uuid: self.last_id,
                     radius: 1.,
-                    speed: element.speed,
+                    speed: element.speed.per_step(dt),
+                    region: self.regions.of(&element.identifier),
                 };
                 self.last_id += 1;
                 elem
             })
-            .speed = element.speed;
+            .speed = element.speed.per_step(dt);
         *self.table.get(&element.identifier).unwrap()
     }
     fn insert_by_diameter(&mut self, element: DiameterDeclaration) -> Element {
@@ -52,13 +70,14 @@ impl IdElementTable {
             .or_insert_with(|| {
                 let elem = Element {
                     uuid: self.last_id,
-                    radius: element.diameter / 2.0,
+                    radius: element.diameter.value / 2.0,
                     speed: 1.,
+                    region: self.regions.of(&element.identifier),
                 };
                 self.last_id += 1;
                 elem
             })
-            .radius = element.diameter / 2.0;
+            .radius = element.diameter.value / 2.0;
         *self.table.get(&element.identifier).unwrap()
     }
     fn insert_by_name(&mut self, element: String) -> Element {
@@ -67,6 +86,7 @@ impl IdElementTable {
                 uuid: self.last_id,
                 radius: 1.,
                 speed: 1.,
+                region: self.regions.of(&element),
             };
             self.last_id += 1;
             elem
@@ -80,99 +100,303 @@ pub struct Environment {
     pub board: ValueBoard,
     pub registry: ReactionRegistry,
     pub molecules: Vec<Molecule>,
+    pub settings: Settings,
+    pub events: Vec<Event>,
+}
+
+/// Registers the collisions of an enzymatic reaction: each substrate molecule binds the
+/// enzyme in turn and may unbind, and the full complex may also release the enzyme and
+/// products.
+fn insert_reaction(
+    r: &Reaction,
+    id_element_table: &mut IdElementTable,
+    registry: &mut ReactionRegistry,
+    settings: &Settings,
+) {
+    let (p1, p2, p3) = Probability::calc_probability(r.km.value, r.kcat.value, settings.dt);
+    let enzhym = id_element_table.insert_by_name(r.enzhym.clone());
+    let mut complex = enzhym;
+    for (i, solube) in r.solube_units().enumerate() {
+        let solube = id_element_table.insert_by_name(solube.clone());
+        let next_complex = id_element_table.insert_by_name(r.complex(i + 1));
+        registry.insert(
+            reaction_registry::CollidedElements::Bi(complex, solube),
+            reaction_registry::Outcome(vec![next_complex], p1),
+        );
+        registry.insert(
+            reaction_registry::CollidedElements::Mono(next_complex),
+            reaction_registry::Outcome(vec![complex, solube], p2),
+        );
+        complex = next_complex;
+    }
+    let mut outcome = vec![enzhym];
+    outcome.extend(
+        r.result_units()
+            .map(|result| id_element_table.insert_by_name(result.clone())),
+    );
+    registry.insert(
+        reaction_registry::CollidedElements::Mono(complex),
+        reaction_registry::Outcome(outcome, p3),
+    );
+}
+
+/// Registers the collisions of an inhibitor or activator with the form of the enzyme of
+/// `r` it binds, and the complex they form coming apart, so that it competes for the
+/// enzyme with the substrates.
+fn insert_binding(
+    r: &Reaction,
+    b: &Binding,
+    id_element_table: &mut IdElementTable,
+    registry: &mut ReactionRegistry,
+    settings: &Settings,
+) {
+    let (p_on, p_off) = Probability::binding_probabilities(b.k, r.km.value, r.kcat.value, settings);
+    let site = id_element_table.insert_by_name(b.site.clone());
+    let modifier = id_element_table.insert_by_name(b.modifier.clone());
+    let complex = id_element_table.insert_by_name(b.complex.clone());
+    registry.insert(
+        reaction_registry::CollidedElements::Bi(site, modifier),
+        reaction_registry::Outcome(vec![complex], p_on),
+    );
+    registry.insert(
+        reaction_registry::CollidedElements::Mono(complex),
+        reaction_registry::Outcome(vec![site, modifier], p_off),
+    );
 }
 
-impl From<Ast> for Environment {
-    fn from(Ast(expressions): Ast) -> Self {
-        let mut id_element_table = IdElementTable::default();
+impl Environment {
+    /// The simulation of `ast`, its compartments nested in the simulated sphere, which
+    /// fails when they cannot be.
+    fn new(ast: Ast, settings: Settings) -> anyhow::Result<Self> {
+        let compartments = Compartments::new(&ast, &settings);
+        let regions = Regions::new(&compartments, settings.radius).map_err(anyhow::Error::msg)?;
+        let mut id_element_table = IdElementTable::new(regions);
         let mut registry = ReactionRegistry::new();
         let mut molecules: Vec<Molecule> = vec![];
+        let mut events = vec![];
 
-        for expr in expressions.into_iter().sorted_by(|a, b| match (a, b) {
-            (_, simulation_parser::Expression::InitDeclaration(_)) => std::cmp::Ordering::Greater,
-            (simulation_parser::Expression::InitDeclaration(_), _) => std::cmp::Ordering::Less,
-            _ => std::cmp::Ordering::Equal,
+        // Elements are copied into the registry and into molecules, so their speed and
+        // diameter must be known before reactions and initial molecules are created.
+        for expr in ast.0.into_iter().sorted_by_key(|e| match e {
+            simulation_parser::Expression::SpeedDeclaration(_)
+            | simulation_parser::Expression::DiameterDeclaration(_) => 0,
+            simulation_parser::Expression::Reaction(_)
+            | simulation_parser::Expression::MassAction(_) => 1,
+            simulation_parser::Expression::InitDeclaration(_)
+            | simulation_parser::Expression::Event(_) => 2,
+            simulation_parser::Expression::Param(_)
+            | simulation_parser::Expression::Sweep(_)
+            | simulation_parser::Expression::Simulation(_)
+            | simulation_parser::Expression::Compartment(_)
+            | simulation_parser::Expression::Include(_) => 3,
         }) {
             match expr {
                 simulation_parser::Expression::Reaction(r) => {
-                    let enzhym = id_element_table.insert_by_name(r.enzhym.clone());
-                    let solube = id_element_table.insert_by_name(r.solubes.clone());
-                    let result = id_element_table.insert_by_name(r.results.clone());
-                    let amalgam = format!("{}--{}", r.enzhym.clone(), r.solubes.clone());
-                    let enzhym_solube = id_element_table.insert_by_name(amalgam.clone());
-                    let (p1, p2, p3) = Probability::calc_probability(r.km, r.kcat);
-                    registry.insert(
-                        reaction_registry::CollidedElements::Bi(enzhym, solube),
-                        reaction_registry::Outcome::One(vec![enzhym_solube], p1),
-                    );
-                    registry.insert(
-                        reaction_registry::CollidedElements::Mono(enzhym_solube),
-                        reaction_registry::Outcome::Two(
-                            (vec![enzhym, solube], p2),
-                            (vec![enzhym, result], p3),
+                    for catalysed in r.catalysed() {
+                        insert_reaction(
+                            &catalysed,
+                            &mut id_element_table,
+                            &mut registry,
+                            &settings,
+                        );
+                    }
+                    for binding in r.bindings() {
+                        insert_binding(
+                            &r,
+                            &binding,
+                            &mut id_element_table,
+                            &mut registry,
+                            &settings,
+                        );
+                    }
+                }
+                simulation_parser::Expression::MassAction(r) => {
+                    let reactants = r
+                        .reactant_units()
+                        .map(|reactant| id_element_table.insert_by_name(reactant.clone()))
+                        .collect_vec();
+                    let products = r
+                        .product_units()
+                        .map(|product| id_element_table.insert_by_name(product.clone()))
+                        .collect_vec();
+                    match reactants.as_slice() {
+                        [] => registry.insert_source(
+                            products,
+                            r.k.value,
+                            compartments.volume(r.compartment()),
+                            settings.dt,
+                        ),
+                        [e] => registry.insert(
+                            reaction_registry::CollidedElements::Mono(*e),
+                            reaction_registry::Outcome(
+                                products,
+                                Probability::first_order(r.k.value, settings.dt),
+                            ),
                         ),
-                    );
+                        [e1, e2] => registry.insert(
+                            reaction_registry::CollidedElements::Bi(*e1, *e2),
+                            reaction_registry::Outcome(
+                                products,
+                                Probability::bimolecular(r.k.value, e1, e2, settings.dt),
+                            ),
+                        ),
+                        _ => unreachable!("the parser rejects reactions of order above two"),
+                    }
                 }
                 simulation_parser::Expression::SpeedDeclaration(s) => {
-                    id_element_table.insert_by_speed(s);
+                    id_element_table.insert_by_speed(s, settings.dt);
                 }
                 simulation_parser::Expression::DiameterDeclaration(d) => {
                     id_element_table.insert_by_diameter(d);
                 }
+                simulation_parser::Expression::Param(_)
+                | simulation_parser::Expression::Sweep(_)
+                | simulation_parser::Expression::Simulation(_)
+                | simulation_parser::Expression::Compartment(_)
+                | simulation_parser::Expression::Include(_) => (),
                 simulation_parser::Expression::InitDeclaration(init) => {
                     let elem = id_element_table.insert_by_name(init.identifier);
                     for _ in 0..init.number {
                         molecules.push(Molecule {
                             kind: elem,
-                            position: generate_random_position(),
+                            position: generate_random_position(elem.region),
                         })
                     }
                 }
+                simulation_parser::Expression::Event(declaration) => {
+                    let watched = match &declaration.trigger {
+                        Trigger::When(c) => {
+                            Some(id_element_table.insert_by_name(c.species.clone()))
+                        }
+                        Trigger::At(_) => None,
+                    };
+                    let changed = declaration
+                        .actions
+                        .iter()
+                        .map(|a| id_element_table.insert_by_name(a.species().to_string()))
+                        .collect();
+                    events.push(Event {
+                        declaration,
+                        watched,
+                        changed,
+                        armed: true,
+                    });
+                }
+            }
+        }
+        for event in &mut events {
+            if let Some(holds) = event.holds(&molecules) {
+                event.armed = !holds;
             }
         }
 
         let board = ValueBoard {
             rows: vec![],
+            // Counts are those of each element in turn, so columns are sorted the same way.
             columns: {
-                let mut cols = id_element_table.table.keys().cloned().collect_vec();
+                let mut cols = id_element_table
+                    .table
+                    .iter()
+                    .sorted_by_key(|(_, e)| e.uuid)
+                    .map(|(k, _)| k.clone())
+                    .collect_vec();
                 cols.push("time".to_string());
                 cols
             },
         };
-        Self {
+        Ok(Self {
             board,
             registry,
             molecules,
-        }
+            settings,
+            events,
+        })
     }
 }
 
-#[derive(Debug, Parser)]
-#[command(version, about)]
-struct Args {
-    /// Source file of the reaction
-    #[arg(short, long, default_value = "reaction.txt")]
-    source: String,
+struct EntityCentered;
+
+impl Engine for EntityCentered {
+    type ConvertOptions = NoOptions;
+    type Command = NoCommand;
 
-    /// Output file of the result of the simulation
-    #[arg(short, long, default_value = "results.csv")]
-    output: String,
+    fn simulate(ast: Ast, settings: Settings, output: &str) -> anyhow::Result<()> {
+        if let Some(seed) = settings.seed {
+            fastrand::seed(seed);
+        }
+        let environment = Environment::new(ast, settings)?;
+        let has_events = !environment.events.is_empty();
+        let (results, fired) = run(environment);
+        fs::write(output, results)?;
+        if has_events {
+            fs::write(events::log_path(output), fired)?;
+        }
+        Ok(())
+    }
+
+    fn run(command: &NoCommand, _: &Ast, _: &Settings) -> anyhow::Result<()> {
+        match *command {}
+    }
 }
 
-fn main() -> anyhow::Result<()> {
-    let arg = Args::parse();
-    let text = fs::read_to_string(arg.source).unwrap();
-    let environment = Environment::from(
-        Ast::parse(text.as_str().into())
-            .expect("Parsing Error")
-            .content,
-    );
-    let now = Instant::now();
-    fs::write(arg.output, run(environment)).unwrap();
-    println!(
-        "simulation took: {} milis | {} seconds ",
-        now.elapsed().as_millis(),
-        now.elapsed().as_secs()
-    );
-    Ok(())
+fn main() -> ExitCode {
+    let arg = Args::<EntityCentered>::try_parse_as(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        env::args_os(),
+    )
+    .unwrap_or_else(|error| error.exit());
+    match cli::run(&arg, |warning| eprintln!("{warning}")) {
+        Ok(report) => {
+            print!("{report}");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use simulation_parser::include::Sources;
+
+    use super::*;
+
+    /// Runs the model, returning the molecules of each species at the end.
+    fn final_counts(text: &str) -> FxHashMap<String, usize> {
+        let ast = Ast::load("model.txt", text.to_string(), &mut Sources::default()).unwrap();
+        let settings = ast.settings();
+        fastrand::seed(1);
+        let (results, _) = run(Environment::new(ast, settings).unwrap());
+        let mut lines = results.lines();
+        let columns = lines.next().unwrap().split(", ").collect_vec();
+        let last = lines.last().unwrap().split(", ").collect_vec();
+        columns
+            .into_iter()
+            .zip(last)
+            .map(|(column, n)| (column.to_string(), n.parse().unwrap_or_default()))
+            .collect()
+    }
+
+    /// Both decays of `A` happen, as in the Gillespie simulation: `A` is left with
+    /// `2000 e^-1` molecules after 0.1 s, half of the others becoming `B` and half `C`.
+    #[test]
+    fn t_branching_decay() {
+        let counts = final_counts(
+            "A -> B @ k = 5 /s;
+            A -> C @ k = 5 /s;
+            init(A) = 2000;
+            simulation { end_time = 0.1 s; sample_every = 0.1 s; }",
+        );
+        let a = 2000. * (-1f64).exp();
+        for (species, expected) in [("A", a), ("B", (2000. - a) / 2.), ("C", (2000. - a) / 2.)] {
+            let n = counts[species] as f64;
+            assert!(
+                (n - expected).abs() < 0.15 * expected,
+                "{n} molecules of {species} instead of {expected}"
+            );
+        }
+    }
 }
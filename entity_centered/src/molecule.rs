@@ -1,6 +1,6 @@
 use crate::element::Element;
 use crate::moved_molecule::MovedMolecule;
-use crate::vector::{self, Vector3d};
+use crate::vector::Vector3d;
 use std::hash::Hash;
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Molecule {
@@ -17,10 +17,12 @@ impl Hash for Molecule {
 impl Eq for Molecule {}
 
 impl Molecule {
+    /// Moves the molecule along `direction`, unless it would leave the region of its
+    /// compartment.
     pub fn apply_movement(self, direction: Vector3d) -> MovedMolecule {
         let dv = direction * self.kind.speed;
         let next_position = self.position + dv;
-        if next_position.distance(&vector::VECTOR_ZERO) > 500. {
+        if !self.kind.region.contains(next_position) {
             MovedMolecule {
                 next_position: self.position,
                 molecule: self,
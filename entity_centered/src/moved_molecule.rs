@@ -40,23 +40,17 @@ impl MovedMolecule {
         T: Iterator<Item = (usize, &'a MovedMolecule)>,
     {
         for (i, m) in others {
-            match self.test_collision(m) {
-                col @ CollidedMolecules::Bi(_, _) => {
-                    match reaction_registry.decide_collision(col) {
-                        (mols, true) => {
-                            return (mols, Some(i));
-                        }
-                        _ => (),
-                    }
+            if let col @ CollidedMolecules::Bi(_, _) = self.test_collision(m) {
+                if let (mols, true) = reaction_registry.decide_collision(col) {
+                    return (mols, Some(i));
                 }
-                _ => (),
             }
         }
-        return (
+        (
             reaction_registry
                 .decide_collision(CollidedMolecules::Mono(self))
                 .0,
             None,
-        );
+        )
     }
 }
@@ -1,31 +1,56 @@
-#[derive(Debug)]
+use simulation_parser::kinetics;
+use simulation_parser::settings::Settings;
+
+use crate::element::Element;
+use crate::simulation::MOLECULES_PER_NM3_PER_MICROMOLAR;
+
+#[derive(Debug, Clone, Copy)]
 pub struct Probability(f64);
 
 impl Probability {
     pub fn new(x: f64) -> Option<Self> {
-        if x >= 0. && x <= 1. {
+        if (0. ..=1.).contains(&x) {
             Some(Probability(x))
         } else {
             None
         }
     }
-    // Needs better error result with kcat and km
-    // Should be on parser directly
-    pub fn calc_probability(km: f32, kcat: f32) -> (Self, Self, Self) {
-        let p3 = kcat / 10000.;
-        let p2 = p3 / 10.;
-        let p1 = if kcat >= 300. && km <= 80. {
-            1.
-        } else {
-            (p2 + p3) / (0.448 * (1. + (p2 + p3).powi(2)) * km)
-        };
+    /// See [`kinetics::calc_probability`].
+    pub fn calc_probability(km: f32, kcat: f32, dt: f32) -> (Self, Self, Self) {
+        let (p1, p2, p3) = kinetics::calc_probability(km, kcat, dt);
         (
-            Probability(p3 as f64),
+            Probability(p1 as f64),
             Probability(p2 as f64),
             Probability(p3 as f64),
         )
     }
 
+    /// See [`kinetics::binding_probabilities`].
+    pub fn binding_probabilities(k: f32, km: f32, kcat: f32, settings: &Settings) -> (Self, Self) {
+        let (p_on, p_off) = kinetics::binding_probabilities(k, km, kcat, settings);
+        (Probability(p_on as f64), Probability(p_off as f64))
+    }
+
+    /// Probability for a molecule to react during one step of `dt` s, given a first order
+    /// rate constant in s⁻¹.
+    pub fn first_order(k: f32, dt: f32) -> Self {
+        Probability(1. - (-(k as f64) * dt as f64).exp())
+    }
+
+    /// Probability for a collision between `a` and `b` to react, given a second order rate
+    /// constant in µM⁻¹·s⁻¹. Collisions happen at the Smoluchowski rate
+    /// `4π (D_a + D_b) (r_a + r_b)`, so rate constants above it are diffusion limited and
+    /// every collision reacts.
+    pub fn bimolecular(k: f32, a: &Element, b: &Element, dt: f32) -> Self {
+        let diffusion = |e: &Element| e.speed.powi(2) / (6. * dt);
+        let collision_rate = 4.
+            * std::f32::consts::PI
+            * (diffusion(a) + diffusion(b))
+            * (a.radius + b.radius)
+            * MOLECULES_PER_NM3_PER_MICROMOLAR;
+        Probability((k / collision_rate).min(1.) as f64)
+    }
+
     pub fn get(&self) -> f64 {
         self.0
     }
@@ -2,6 +2,8 @@ use crate::collided_molecule::CollidedMolecules;
 use crate::element::Element;
 use crate::molecule::Molecule;
 use crate::probability::Probability;
+use crate::simulation::MOLECULES_PER_NM3_PER_MICROMOLAR;
+use crate::vector::{generate_random_position, VECTOR_ZERO};
 // use rustc_hash::FxHashMap;
 use hashbrown::HashMap;
 
@@ -11,77 +13,98 @@ pub enum CollidedElements {
     Bi(Element, Element),
 }
 
+/// Molecules replacing those that collided, and the probability for the collision to give
+/// them.
 #[derive(Debug)]
-pub enum Outcome {
-    One(Vec<Element>, Probability),
-    Two((Vec<Element>, Probability), (Vec<Element>, Probability)),
-}
+pub struct Outcome(pub Vec<Element>, pub Probability);
 
 #[derive(Debug)]
 pub struct ReactionRegistry {
-    register: HashMap<CollidedElements, Outcome>,
+    /// The outcomes of each collision, several when reactions share their reactants.
+    register: HashMap<CollidedElements, Vec<Outcome>>,
+    /// Zeroth order reactions, with the mean number of times they happen per step.
+    sources: Vec<(Vec<Element>, f64)>,
 }
 
 impl ReactionRegistry {
     pub fn new() -> Self {
         Self {
             register: HashMap::default(),
+            sources: vec![],
         }
     }
+    /// Registers a synthesis `0 -> products` happening at `k` µM/s in a compartment of
+    /// `volume` nm³, for steps of `dt` s.
+    pub fn insert_source(&mut self, products: Vec<Element>, k: f32, volume: f32, dt: f32) {
+        let per_step = k * volume * MOLECULES_PER_NM3_PER_MICROMOLAR * dt;
+        self.sources.push((products, per_step as f64));
+    }
+    /// Molecules synthesised during one step, placed at random in the region of the first
+    /// product.
+    pub fn spawn_sources(&self) -> Vec<Molecule> {
+        self.sources
+            .iter()
+            .flat_map(|(products, per_step)| {
+                let times =
+                    per_step.floor() as usize + (fastrand::f64() < per_step.fract()) as usize;
+                (0..times).flat_map(move |_| {
+                    let position = products
+                        .first()
+                        .map_or(VECTOR_ZERO, |e| generate_random_position(e.region));
+                    products.iter().map(move |e| Molecule {
+                        kind: *e,
+                        position: e.region.clamp(position),
+                    })
+                })
+            })
+            .collect()
+    }
+    /// Adds an outcome to the collision `k`, after those it already has.
     pub fn insert(&mut self, k: CollidedElements, v: Outcome) {
-        self.register.insert(k, v);
+        let k = match k {
+            CollidedElements::Bi(e1, e2)
+                if !self.register.contains_key(&k)
+                    && self.register.contains_key(&CollidedElements::Bi(e2, e1)) =>
+            {
+                CollidedElements::Bi(e2, e1)
+            }
+            k => k,
+        };
+        self.register.entry(k).or_default().push(v);
     }
-    pub fn get(&self, k: &CollidedElements) -> Option<&Outcome> {
-        self.register.get(k)
+    pub fn get(&self, k: &CollidedElements) -> Option<&[Outcome]> {
+        match (self.register.get(k), k) {
+            (None, CollidedElements::Bi(e1, e2)) => {
+                self.register.get(&CollidedElements::Bi(*e2, *e1))
+            }
+            (outcomes, _) => outcomes,
+        }
+        .map(Vec::as_slice)
     }
+    /// Draws the outcome of a collision: each outcome in turn takes the next slice of
+    /// probability, and the molecules go on unchanged past the last one.
     pub fn decide_collision(&self, collided_molecules: CollidedMolecules) -> (Vec<Molecule>, bool) {
         let roll = fastrand::f64();
-        match self.get(&collided_molecules.get_elements()) {
-            Some(Outcome::One(r, p)) if roll < p.get() => {
-                println!("Important collision found and executed");
-                (
-                    r.into_iter()
-                        .map(|e| Molecule {
-                            kind: *e,
-                            position: collided_molecules.get_position(),
-                        })
-                        .collect(),
-                    true,
-                )
-            }
-            Some(Outcome::Two(_, (r, p))) if roll < p.get() => {
-                println!("Important collision found and executed");
-                (
-                    r.into_iter()
-                        .map(|e| Molecule {
-                            kind: *e,
-                            position: collided_molecules.get_position(),
-                        })
-                        .collect(),
-                    true,
-                )
-            }
-            Some(Outcome::Two((r, p1), (_, p2))) if roll < p1.get() + p2.get() => {
-                // println!("Important collision found and executed");
-                (
-                    r.into_iter()
-                        .map(|e| Molecule {
-                            kind: *e,
-                            position: collided_molecules.get_position(),
-                        })
-                        .collect(),
-                    true,
-                )
-            }
-            // Some(Outcome::One(_, p)) => {
-            //     println!(
-            //         "Collision not followed through because rolled a {} not under {}",
-            //         roll,
-            //         p.get()
-            //     );
-            //     (collided_molecules.roll_back(), false)
-            // }
-            _ => (collided_molecules.roll_back(), false),
+        let mut cumulated = 0.;
+        let outcome = self
+            .get(&collided_molecules.get_elements())
+            .unwrap_or_default()
+            .iter()
+            .find(|Outcome(_, p)| {
+                cumulated += p.get();
+                roll < cumulated
+            });
+        match outcome {
+            Some(Outcome(r, _)) => (
+                r.iter()
+                    .map(|e| Molecule {
+                        kind: *e,
+                        position: e.region.clamp(collided_molecules.get_position()),
+                    })
+                    .collect(),
+                true,
+            ),
+            None => (collided_molecules.roll_back(), false),
         }
     }
 }
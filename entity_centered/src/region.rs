@@ -0,0 +1,132 @@
+use rustc_hash::FxHashMap;
+use simulation_parser::compartments::{compartment_of, Compartments};
+
+use crate::vector::{Vector3d, VECTOR_ZERO};
+
+/// Spherical shell of the simulated sphere the molecules of a species move in: that of
+/// their compartment, between its membrane and the compartment nested in it, or for the
+/// species outside every compartment, between the outermost compartment and the edge of
+/// the sphere.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Region {
+    /// Distance to the centre of the sphere, in nm.
+    pub inner: f32,
+    /// Distance to the centre of the sphere, in nm.
+    pub outer: f32,
+}
+
+impl Region {
+    pub fn contains(&self, position: Vector3d) -> bool {
+        let distance = position.distance(&VECTOR_ZERO);
+        self.inner <= distance && distance <= self.outer
+    }
+    /// The position of the region closest to `position`, where the products of a
+    /// reaction go when they are in another compartment than its reactants.
+    pub fn clamp(&self, position: Vector3d) -> Vector3d {
+        if self.contains(position) {
+            return position;
+        }
+        let distance = position.distance(&VECTOR_ZERO);
+        let direction = if distance > 0. {
+            position * (1. / distance)
+        } else {
+            Vector3d::get_random_unitary()
+        };
+        direction * distance.clamp(self.inner, self.outer)
+    }
+}
+
+/// The regions of the compartments, and that of the species outside every compartment.
+#[derive(Debug)]
+pub struct Regions(FxHashMap<Option<String>, Region>);
+
+impl Regions {
+    /// Nests the compartments in the sphere of `radius` nm as concentric spheres, each as
+    /// large as the volume it encloses. A compartment may thus only have one compartment
+    /// directly inside it.
+    pub fn new(compartments: &Compartments, radius: f32) -> Result<Self, String> {
+        let radius_of = |identifier: &str| {
+            (3. * compartments.enclosed(identifier) / (4. * std::f32::consts::PI)).cbrt()
+        };
+        let mut regions = FxHashMap::default();
+        let mut parent: Option<String> = None;
+        let mut outer = radius;
+        loop {
+            let nested = match compartments.nested_in(parent.as_deref()).as_slice() {
+                [] => None,
+                [nested] => Some(nested.identifier.clone()),
+                [a, b, ..] => {
+                    return Err(format!(
+                        "compartments `{}` and `{}` are both directly inside {}, but the entity-centered simulation only nests compartments one inside the other",
+                        a.identifier,
+                        b.identifier,
+                        parent.map_or("the simulated sphere".to_string(), |p| format!("`{p}`"))
+                    ))
+                }
+            };
+            let inner = nested.as_deref().map_or(0., radius_of);
+            regions.insert(parent, Region { inner, outer });
+            match nested {
+                Some(nested) => {
+                    parent = Some(nested);
+                    outer = inner;
+                }
+                None => return Ok(Self(regions)),
+            }
+        }
+    }
+    /// The region the molecules of `species` move in.
+    pub fn of(&self, species: &str) -> Region {
+        self.0
+            .get(&compartment_of(species).map(str::to_string))
+            .or_else(|| self.0.get(&None))
+            .copied()
+            .expect("the space outside the compartments has a region")
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use glam::Vec3A;
+    use simulation_parser::{settings::Settings, Ast, Parsable};
+
+    use super::*;
+
+    fn nest(model: &str) -> Result<Regions, String> {
+        let ast = Ast::parse(model.into()).unwrap().content;
+        Regions::new(&Compartments::new(&ast, &Settings::default()), 500.)
+    }
+
+    #[test]
+    fn t_regions() {
+        // Spheres of 200 and 100 nm.
+        let regions = nest(
+            "compartment cytosol { volume = 0.029321532 fL; }
+             compartment nucleus { volume = 0.0041887902 fL; inside = cytosol; }",
+        )
+        .unwrap();
+        let close = |region: Region, inner: f32, outer: f32| {
+            (region.inner - inner).abs() < 0.01 && (region.outer - outer).abs() < 0.01
+        };
+        assert!(close(regions.of("s"), 200., 500.));
+        assert!(close(regions.of("s[golgi]"), 200., 500.));
+        assert!(close(regions.of("s[cytosol]"), 100., 200.));
+        assert!(close(regions.of("E1[nucleus]--s[nucleus]"), 0., 100.));
+
+        let nucleus = regions.of("s[nucleus]");
+        let outside = Vector3d {
+            data: Vec3A::new(0., 300., 0.),
+        };
+        assert!(!nucleus.contains(outside));
+        assert!(nucleus.clamp(outside).distance(&VECTOR_ZERO) <= nucleus.outer);
+        assert_eq!(VECTOR_ZERO, nucleus.clamp(VECTOR_ZERO));
+
+        let error = nest(
+            "compartment a { volume = 0.01 fL; }
+             compartment b { volume = 0.01 fL; }",
+        )
+        .unwrap_err();
+        assert!(error
+            .starts_with("compartments `a` and `b` are both directly inside the simulated sphere"));
+    }
+}
@@ -1,5 +1,4 @@
-use std::time::{Duration, Instant};
-
+use crate::event::fire_events;
 use crate::moved_molecule::MovedMolecule;
 use crate::reaction_registry::ReactionRegistry;
 use crate::vector::VectorInt3d;
@@ -8,7 +7,10 @@ use crate::{molecule::Molecule, vector::Vector3d};
 use hashbrown::HashMap;
 use indicatif::ProgressBar;
 use itertools::Itertools;
-use rayon::prelude::*;
+use simulation_parser::events::log_csv;
+
+/// Number of molecules per nm³ in a 1 µM solution.
+pub const MOLECULES_PER_NM3_PER_MICROMOLAR: f32 = 6.022e-7;
 
 fn detect_collision(
     moved_molecules: HashMap<VectorInt3d, Vec<MovedMolecule>>,
@@ -21,7 +23,7 @@ fn detect_collision(
             .flat_map(|e| {
                 let mut ignored = Vec::with_capacity(e.len());
                 let res = e
-                    .into_iter()
+                    .iter()
                     .enumerate()
                     .filter_map(move |(i, m1)| {
                         if ignored.contains(&i) {
@@ -60,33 +62,50 @@ where
 fn simulation(reg: &ReactionRegistry, molecules: &mut Vec<Molecule>) {
     let movedmols = group(
         molecules
-            .into_iter()
+            .iter_mut()
             .map(|m| m.apply_movement(Vector3d::get_random_unitary()))
             .map(|m| (m.next_position.into_vectorint(), m)),
     );
     molecules.clear();
 
     detect_collision(movedmols, reg, molecules);
+    molecules.extend(reg.spawn_sources());
 }
 
-pub fn run(environment: Environment) -> String {
+/// Simulates the environment, and returns its results and the log of the events fired, as
+/// CSV.
+pub fn run(environment: Environment) -> (String, String) {
     let Environment {
         mut board,
         registry,
         mut molecules,
+        settings,
+        mut events,
     } = environment;
 
-    let iterations = 9_000_000 * 8;
-    let bar = ProgressBar::new(iterations as u64);
+    let iterations = settings.steps();
+    let every = settings.steps_per_sample();
+    let bar = ProgressBar::new(iterations);
+    let mut fired = vec![];
     for t in 1..=iterations {
+        // Events fire at the start of the step their time falls in.
+        let step = t - 1;
+        for i in fire_events(&mut events, &mut molecules, step, settings.dt) {
+            fired.push((step as f32 * settings.dt, i));
+        }
         molecules.reverse();
         simulation(&registry, &mut molecules);
-        if t % 500 == 0 {
-            board.add_entry(&molecules, t);
+        if t % every == 0 {
+            board.add_entry(&molecules, t as f32 * settings.dt);
         }
         bar.inc(1);
     }
     bar.finish();
 
-    board.convert_to_csv()
+    let log = log_csv(
+        fired
+            .iter()
+            .map(|(time, i)| (*time, &events[*i].declaration)),
+    );
+    (board.convert_to_csv(), log)
 }
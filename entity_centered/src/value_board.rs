@@ -3,23 +3,24 @@ use itertools::Itertools;
 
 #[derive(Debug, Clone)]
 pub struct ValueBoard {
-    pub rows: Vec<Vec<usize>>,
+    /// Molecule counts, followed by the time in s.
+    pub rows: Vec<(Vec<usize>, f32)>,
     pub columns: Vec<String>,
 }
 
 impl ValueBoard {
-    pub fn add_entry(&mut self, mols: &[Molecule], time: usize) {
-        let mut map = (0..(self.columns.len() - 1))
+    pub fn add_entry(&mut self, mols: &[Molecule], time: f32) {
+        let map = (0..(self.columns.len() - 1))
             .map(|i| mols.iter().filter(|m| m.kind.uuid == i as u64).count())
             .collect::<Vec<_>>();
-        map.push(time);
-        self.rows.push(map);
+        self.rows.push((map, time));
     }
     pub fn convert_to_csv(&self) -> String {
         let mut csv = self.columns.join(", ");
-        for v in &self.rows {
+        for (v, t) in &self.rows {
             csv.push('\n');
-            csv.push_str(&v.into_iter().join(", "));
+            csv.push_str(&v.iter().join(", "));
+            csv.push_str(&format!(", {t}"));
         }
         csv
     }
@@ -4,6 +4,10 @@ use std::{
 };
 
 use glam::{IVec3, Vec3A};
+use simulation_parser::settings::HASH_CELL_SIZE;
+
+use crate::region::Region;
+
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub struct Vector3d {
     pub data: Vec3A,
@@ -84,17 +88,19 @@ impl Vector3d {
     }
 
     pub fn into_vectorint(self) -> VectorInt3d {
-        let data = (self.data / 10.).as_ivec3();
+        let data = (self.data / HASH_CELL_SIZE).as_ivec3();
         VectorInt3d { data }
     }
 }
 
 pub const VECTOR_ZERO: Vector3d = Vector3d { data: Vec3A::ZERO };
 
-pub fn generate_random_position() -> Vector3d {
+/// Random position in the inner 80% of the thickness of `region`, the whole of a sphere
+/// of radius 0.8 times its own for the innermost region.
+pub fn generate_random_position(region: Region) -> Vector3d {
     let vec = Vector3d::get_random_unitary();
-    let d = fastrand::f32() * 400.0;
-    let random_pos = d * vec;
-    assert!(random_pos.distance(&VECTOR_ZERO) <= 500.);
-    random_pos
+    let d = region.inner + fastrand::f32() * (region.outer - region.inner) * 0.8;
+    // The direction is only of unit length up to rounding, which may put a position at the
+    // inner edge of a shell just inside it.
+    region.clamp(d * vec)
 }
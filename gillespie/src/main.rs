@@ -1,10 +1,19 @@
-use std::{collections::HashMap, fs};
+use std::{collections::HashMap, env, fs, process::ExitCode};
 
-use clap::{arg, Parser};
+use clap::Subcommand;
+use cli::{Args, Engine};
 use itertools::Itertools;
 use probability::Probability;
 use reaction_registry::{Element, ReactionRegistry};
-use simulation_parser::{Ast, InitDeclaration, Parsable};
+use simulation_parser::{
+    compartments::Compartments,
+    events::{self, EventDeclaration, Trigger},
+    expanded::ExpandedModel,
+    formats::Format,
+    sbml,
+    settings::Settings,
+    Ast, Binding, InitDeclaration, Reaction,
+};
 use value_board::ValueBoard;
 
 mod probability;
@@ -13,7 +22,7 @@ mod value_board;
 
 #[derive(Debug, Default)]
 struct IdElementTable {
-    table: HashMap<String, (Element, i32)>,
+    table: HashMap<String, (Element, u64)>,
     last_id: u64,
 }
 
@@ -30,55 +39,193 @@ impl IdElementTable {
         self.table
             .entry(init.identifier.clone())
             .and_modify(|(_, n)| {
-                let _ = std::mem::replace(n, init.number as i32);
+                let _ = std::mem::replace(n, init.number);
             })
             .or_insert_with(|| {
                 let elem = Element { uuid: self.last_id };
                 self.last_id += 1;
-                (elem, init.number as i32)
+                (elem, init.number)
             });
         self.table.get(&init.identifier).unwrap().0
     }
 }
 
+/// An event of the model, its species resolved to elements.
+#[derive(Debug)]
+struct Event {
+    declaration: EventDeclaration,
+    /// The element a `when` event watches.
+    watched: Option<Element>,
+    /// The element each action changes.
+    changed: Vec<Element>,
+    /// Whether the event may fire: a timed event until it has, a conditional one while its
+    /// condition does not hold.
+    armed: bool,
+}
+
 #[derive(Debug)]
 pub struct Environment {
     pub board: ValueBoard,
-    pub last_state: Vec<i32>,
+    pub last_state: Vec<u64>,
     pub registry: ReactionRegistry,
-    pub time: f32,
+    /// In steps, in `f64` so that the waiting times added to it are not rounded away.
+    pub time: f64,
+    pub settings: Settings,
+    events: Vec<Event>,
+    /// Each event fired, with the time it fired at in s.
+    fired: Vec<(f32, usize)>,
+}
+
+/// Expands an enzymatic reaction into its elementary steps: each substrate molecule binds
+/// the enzyme in turn and may unbind, and the full complex releases the enzyme and products.
+/// They happen in the compartment of the enzyme, of `volume` nm³.
+fn insert_reaction(
+    r: &Reaction,
+    id_element_table: &mut IdElementTable,
+    registry: &mut ReactionRegistry,
+    settings: &Settings,
+    volume: f32,
+) {
+    let (p1, p2, p3) = Probability::calc_probability(r.km.value, r.kcat.value, settings.dt);
+    let enzhym = id_element_table.insert_by_name(r.enzhym.clone());
+    let mut complex = enzhym;
+    for (i, solube) in r.solube_units().enumerate() {
+        let solube = id_element_table.insert_by_name(solube.clone());
+        let next_complex = id_element_table.insert_by_name(r.complex(i + 1));
+        registry.insert(
+            reaction_registry::CollidedElements::Bi(complex, solube),
+            (vec![next_complex], p1),
+            volume,
+        );
+        registry.insert(
+            reaction_registry::CollidedElements::Mono(next_complex),
+            (vec![complex, solube], p2),
+            volume,
+        );
+        complex = next_complex;
+    }
+    let mut outcome = vec![enzhym];
+    outcome.extend(
+        r.result_units()
+            .map(|result| id_element_table.insert_by_name(result.clone())),
+    );
+    registry.insert(
+        reaction_registry::CollidedElements::Mono(complex),
+        (outcome, p3),
+        volume,
+    );
 }
 
-impl From<Ast> for Environment {
-    fn from(Ast(expressions): Ast) -> Self {
+/// Registers an inhibitor or activator binding a form of the enzyme of `r`, and leaving
+/// it, with the probabilities of [`Probability::binding_probabilities`], in the
+/// compartment of the enzyme of `volume` nm³.
+fn insert_binding(
+    r: &Reaction,
+    b: &Binding,
+    id_element_table: &mut IdElementTable,
+    registry: &mut ReactionRegistry,
+    settings: &Settings,
+    volume: f32,
+) {
+    let (p_on, p_off) = Probability::binding_probabilities(b.k, r.km.value, r.kcat.value, settings);
+    let site = id_element_table.insert_by_name(b.site.clone());
+    let modifier = id_element_table.insert_by_name(b.modifier.clone());
+    let complex = id_element_table.insert_by_name(b.complex.clone());
+    registry.insert(
+        reaction_registry::CollidedElements::Bi(site, modifier),
+        (vec![complex], p_on),
+        volume,
+    );
+    registry.insert(
+        reaction_registry::CollidedElements::Mono(complex),
+        (vec![site, modifier], p_off),
+        volume,
+    );
+}
+
+impl Environment {
+    fn new(ast: Ast, settings: Settings) -> Self {
         let mut id_element_table = IdElementTable::default();
-        let mut registry = ReactionRegistry::new();
+        let mut registry = ReactionRegistry::default();
+        let compartments = Compartments::new(&ast, &settings);
+        let mut events = vec![];
 
-        for expr in expressions {
+        for expr in ast.0 {
             match expr {
                 simulation_parser::Expression::Reaction(r) => {
-                    let enzhym = id_element_table.insert_by_name(r.enzhym.clone());
-                    let solube = id_element_table.insert_by_name(r.solubes.clone());
-                    let result = id_element_table.insert_by_name(r.results.clone());
-                    let amalgam = format!("{}--{}", r.enzhym.clone(), r.solubes.clone());
-                    let enzhym_solube = id_element_table.insert_by_name(amalgam.clone());
-                    let (p1, p2, p3) = Probability::calc_probability(r.km, r.kcat);
-                    registry.insert(
-                        reaction_registry::CollidedElements::Bi(enzhym, solube),
-                        (vec![enzhym_solube], p1),
-                    );
-                    registry.insert(
-                        reaction_registry::CollidedElements::Mono(enzhym_solube),
-                        (vec![enzhym, solube], p2),
-                    );
+                    let volume = compartments.volume(r.compartment());
+                    for catalysed in r.catalysed() {
+                        insert_reaction(
+                            &catalysed,
+                            &mut id_element_table,
+                            &mut registry,
+                            &settings,
+                            volume,
+                        );
+                    }
+                    for binding in r.bindings() {
+                        insert_binding(
+                            &r,
+                            &binding,
+                            &mut id_element_table,
+                            &mut registry,
+                            &settings,
+                            volume,
+                        );
+                    }
+                }
+                simulation_parser::Expression::MassAction(r) => {
+                    let reactants = r
+                        .reactant_units()
+                        .map(|reactant| id_element_table.insert_by_name(reactant.clone()))
+                        .collect_vec();
+                    let products = r
+                        .product_units()
+                        .map(|product| id_element_table.insert_by_name(product.clone()))
+                        .collect_vec();
+                    let collision = match reactants.as_slice() {
+                        [] => reaction_registry::CollidedElements::Source,
+                        [e] => reaction_registry::CollidedElements::Mono(*e),
+                        [e1, e2] => reaction_registry::CollidedElements::Bi(*e1, *e2),
+                        _ => unreachable!("the parser rejects reactions of order above two"),
+                    };
+                    let volume = compartments.volume(r.compartment());
                     registry.insert(
-                        reaction_registry::CollidedElements::Mono(enzhym_solube),
-                        (vec![enzhym, result], p3),
+                        collision,
+                        (
+                            products,
+                            Probability::from_rate_constant(
+                                r.k.value,
+                                reactants.len(),
+                                volume,
+                                settings.dt,
+                            ),
+                        ),
+                        volume,
                     );
                 }
                 simulation_parser::Expression::InitDeclaration(init) => {
                     id_element_table.insert_by_init(init);
                 }
+                simulation_parser::Expression::Event(declaration) => {
+                    let watched = match &declaration.trigger {
+                        Trigger::When(c) => {
+                            Some(id_element_table.insert_by_name(c.species.clone()))
+                        }
+                        Trigger::At(_) => None,
+                    };
+                    let changed = declaration
+                        .actions
+                        .iter()
+                        .map(|a| id_element_table.insert_by_name(a.species().to_string()))
+                        .collect();
+                    events.push(Event {
+                        declaration,
+                        watched,
+                        changed,
+                        armed: true,
+                    });
+                }
                 _ => (),
             }
         }
@@ -104,57 +251,283 @@ impl From<Ast> for Environment {
                 .collect(),
             0.,
         );
+        let last_state = board.rows.last().unwrap().clone().0;
+        for event in &mut events {
+            if let (Trigger::When(c), Some(e)) = (&event.declaration.trigger, event.watched) {
+                event.armed = !c.holds(last_state[e.uuid as usize]);
+            }
+        }
         Self {
-            last_state: board.rows.last().unwrap().clone().0,
+            last_state,
             board,
             registry,
             time: 0.,
+            settings,
+            events,
+            fired: vec![],
         }
     }
-}
-
-impl Environment {
-    fn update(&mut self, save: bool) {
-        let current_state = &self.last_state;
-        let (update_vector, tau) = self.registry.calc_update_vector_and_tau(&current_state);
-        let updated_state = current_state
+    /// The timed event to fire first, and its time in steps.
+    fn next_timed_event(&self) -> Option<(usize, f64)> {
+        self.events
             .iter()
-            .zip(update_vector)
-            .map(|(x, update)| x + update)
-            .collect::<Vec<_>>();
-        self.time += tau;
-        self.last_state = updated_state;
-        if save {
-            self.board.add_entry(self.last_state.clone(), self.time);
+            .enumerate()
+            .filter(|(_, e)| e.armed)
+            .filter_map(|(i, e)| match &e.declaration.trigger {
+                Trigger::At(time) => Some((i, time.value as f64 / self.settings.dt as f64)),
+                Trigger::When(_) => None,
+            })
+            .min_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+    fn fire(&mut self, i: usize) {
+        let event = &self.events[i];
+        for (action, e) in event.declaration.actions.iter().zip(&event.changed) {
+            let count = &mut self.last_state[e.uuid as usize];
+            *count = action.apply(*count);
+        }
+        self.fired
+            .push(((self.time * self.settings.dt as f64) as f32, i));
+    }
+    /// Fires the conditional events whose condition has become true since the last state.
+    fn watch(&mut self) {
+        for i in 0..self.events.len() {
+            let event = &self.events[i];
+            let (Trigger::When(c), Some(e)) = (&event.declaration.trigger, event.watched) else {
+                continue;
+            };
+            let holds = c.holds(self.last_state[e.uuid as usize]);
+            let fires = holds && event.armed;
+            self.events[i].armed = !holds;
+            if fires {
+                self.fire(i);
+            }
+        }
+    }
+    /// Runs reactions until the end time, recording the state at every sampling time, and
+    /// fires the events when they happen.
+    fn run(&mut self) {
+        let end = self.settings.steps() as f64;
+        let every = self.settings.steps_per_sample() as f64;
+        let dt = self.settings.dt as f64;
+        let mut next_sample = every;
+        while self.time < end {
+            let (update_vector, tau) = self.registry.calc_update_vector_and_tau(&self.last_state);
+            let time = self.time + tau;
+            // The waiting time stops at the event, and the reaction is drawn again from the
+            // state it leaves: waiting times are exponential, so the time already waited
+            // does not change the next one.
+            if let Some((i, at)) = self
+                .next_timed_event()
+                .filter(|(_, at)| *at < time.min(end))
+            {
+                while next_sample <= at {
+                    self.board
+                        .add_entry(self.last_state.clone(), (next_sample * dt) as f32);
+                    next_sample += every;
+                }
+                self.time = at;
+                self.events[i].armed = false;
+                self.fire(i);
+                self.watch();
+                continue;
+            }
+            // The state holds until the reaction happens.
+            while next_sample <= time.min(end) {
+                self.board
+                    .add_entry(self.last_state.clone(), (next_sample * dt) as f32);
+                next_sample += every;
+            }
+            self.last_state = self
+                .last_state
+                .iter()
+                .zip(update_vector)
+                .map(|(x, update)| {
+                    x.checked_add_signed(update)
+                        .expect("reactions only consume molecules that are present")
+                })
+                .collect();
+            self.time = time;
+            if self.time < end {
+                self.watch();
+            }
         }
     }
     fn get_csv(&self) -> String {
         self.board.convert_to_csv()
     }
+    fn get_events_csv(&self) -> String {
+        events::log_csv(
+            self.fired
+                .iter()
+                .map(|(time, i)| (*time, &self.events[*i].declaration)),
+        )
+    }
 }
 
-#[derive(Debug, Parser)]
-#[command(version, about)]
-struct Args {
-    /// Source file of the reaction
-    #[arg(short, long, default_value = "reaction.txt")]
-    source: String,
+/// Options `convert` has in this simulation.
+#[derive(Debug, clap::Args)]
+struct ConvertOptions {
+    /// Writes the model the simulation runs instead, enzymatic reactions expanded into
+    /// their elementary reactions
+    #[arg(long)]
+    expanded: bool,
+}
 
-    /// Output file of the result of the simulation
-    #[arg(short, long, default_value = "results.csv")]
-    output: String,
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Writes the model as an SBML Level 3 document, its enzymatic reactions expanded into
+    /// the elementary reactions simulated with the given settings
+    Sbml {
+        /// Output file of the SBML document
+        output: String,
+    },
 }
 
-fn main() {
-    let arg = Args::parse();
-    let text = fs::read_to_string(arg.source).unwrap();
-    let mut environment = Environment::from(
-        Ast::parse(text.as_str().into())
-            .expect("Parsing Error")
-            .content,
-    );
-    for i in 0..500_000 {
-        environment.update(i % 10 == 0);
+struct Gillespie;
+
+impl Engine for Gillespie {
+    type ConvertOptions = ConvertOptions;
+    type Command = Command;
+
+    fn simulate(ast: Ast, settings: Settings, output: &str) -> anyhow::Result<()> {
+        if let Some(seed) = settings.seed {
+            fastrand::seed(seed);
+        }
+        let mut environment = Environment::new(ast, settings);
+        environment.run();
+        fs::write(output, environment.get_csv())?;
+        if !environment.events.is_empty() {
+            fs::write(events::log_path(output), environment.get_events_csv())?;
+        }
+        Ok(())
+    }
+
+    fn convert(
+        options: &ConvertOptions,
+        ast: &Ast,
+        settings: &Settings,
+        output: &str,
+    ) -> Option<anyhow::Result<String>> {
+        if !options.expanded {
+            return None;
+        }
+        Some(match Format::of(output) {
+            Some(format) => format
+                .write(&ExpandedModel::new(ast, settings))
+                .map_err(anyhow::Error::msg),
+            None => Err(anyhow::anyhow!(
+                "the expanded model can only be written in JSON, TOML or YAML"
+            )),
+        })
+    }
+
+    fn run(command: &Command, ast: &Ast, settings: &Settings) -> anyhow::Result<()> {
+        match command {
+            Command::Sbml { output } => fs::write(output, sbml::export(ast, settings))?,
+        }
+        Ok(())
+    }
+}
+
+fn main() -> ExitCode {
+    let arg = Args::<Gillespie>::try_parse_as(
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        env::args_os(),
+    )
+    .unwrap_or_else(|error| error.exit());
+    match cli::run(&arg, |warning| eprintln!("{warning}")) {
+        Ok(report) => {
+            print!("{report}");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("{error}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use simulation_parser::include::Sources;
+
+    use super::*;
+
+    fn environment(text: &str) -> Environment {
+        let ast = Ast::load("model.txt", text.to_string(), &mut Sources::default()).unwrap();
+        let settings = ast.settings();
+        Environment::new(ast, settings)
+    }
+
+    /// Molecules of the first species added between each two rows of the results.
+    fn increments(environment: &Environment) -> Vec<u64> {
+        environment
+            .board
+            .rows
+            .iter()
+            .tuple_windows()
+            .map(|((before, _), (after, _))| after[0] - before[0])
+            .collect()
+    }
+
+    #[test]
+    fn t_source_keeps_producing() {
+        assert!(reaction_registry::waiting_time(1e6, 0.).is_finite());
+        let mut environment = environment(
+            "0 -> A @ k = 1000 uM/s;
+            simulation { end_time = 1 s; sample_every = 50 ms; }",
+        );
+        fastrand::seed(1);
+        environment.run();
+        let increments = increments(&environment);
+        assert_eq!(20, increments.len());
+        assert!(increments.iter().all(|n| *n > 0));
+    }
+
+    #[test]
+    fn t_source_grows_linearly() {
+        let mut environment = environment(
+            "0 -> A @ k = 317000 uM/s;
+            simulation { end_time = 0.2 s; sample_every = 50 ms; }",
+        );
+        fastrand::seed(1);
+        let expected = environment
+            .registry
+            .get_rate_of_all_reaction(&environment.last_state) as f64
+            * environment.settings.steps_per_sample() as f64;
+        environment.run();
+        let increments = increments(&environment);
+        assert_eq!(4, increments.len());
+        for n in increments {
+            assert!(
+                (n as f64 - expected).abs() < 0.002 * expected,
+                "{n} molecules were added instead of {expected}"
+            );
+        }
+    }
+
+    /// `A` is left with `2000 e^-1` molecules after 0.1 s, half of the others becoming `B`
+    /// and half `C`, as in the entity-centered simulation.
+    #[test]
+    fn t_branching_decay() {
+        let mut environment = environment(
+            "A -> B @ k = 5 /s;
+            A -> C @ k = 5 /s;
+            init(A) = 2000;
+            simulation { end_time = 0.1 s; sample_every = 0.1 s; }",
+        );
+        fastrand::seed(1);
+        environment.run();
+        let (last, _) = environment.board.rows.last().unwrap();
+        let a = 2000. * (-1f64).exp();
+        for (species, expected) in [("A", a), ("B", (2000. - a) / 2.), ("C", (2000. - a) / 2.)] {
+            let column = environment.board.columns.iter().position(|c| c == species);
+            let n = last[column.unwrap()] as f64;
+            assert!(
+                (n - expected).abs() < 0.15 * expected,
+                "{n} molecules of {species} instead of {expected}"
+            );
+        }
     }
-    fs::write(arg.output, environment.get_csv()).unwrap();
 }
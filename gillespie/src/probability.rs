@@ -1,25 +1,39 @@
-#[derive(Debug)]
+use simulation_parser::kinetics::{self, alpha_in, molecules_per_micromolar_in};
+use simulation_parser::settings::Settings;
+
+#[derive(Debug, Clone, Copy)]
 pub struct Probability(f32);
 
 impl Probability {
     pub fn new(x: f32) -> Option<Self> {
-        if x >= 0. && x <= 1. {
+        if (0. ..=1.).contains(&x) {
             Some(Probability(x))
         } else {
             None
         }
     }
-    // Needs better error result with kcat and km
-    // Should be on parser directly
-    pub fn calc_probability(km: f32, kcat: f32) -> (Self, Self, Self) {
-        let p3 = kcat / 10000.;
-        let p2 = p3 / 10.;
-        let p1 = if kcat >= 300. && km <= 80. {
-            1.
-        } else {
-            (p2 + p3) / (0.448 * (1. + (p2 + p3).powi(2)) * km)
-        };
-        (Probability(p3), Probability(p2), Probability(p1))
+    /// See [`kinetics::calc_probability`].
+    pub fn calc_probability(km: f32, kcat: f32, dt: f32) -> (Self, Self, Self) {
+        let (p1, p2, p3) = kinetics::calc_probability(km, kcat, dt);
+        (Probability(p1), Probability(p2), Probability(p3))
+    }
+
+    /// See [`kinetics::binding_probabilities`].
+    pub fn binding_probabilities(k: f32, km: f32, kcat: f32, settings: &Settings) -> (Self, Self) {
+        let (p_on, p_off) = kinetics::binding_probabilities(k, km, kcat, settings);
+        (Probability(p_on), Probability(p_off))
+    }
+
+    /// Per step propensity factor of a mass-action reaction with `order` reactant molecules
+    /// and rate constant `k` (µM/s, s⁻¹ or µM⁻¹·s⁻¹), happening in a compartment of
+    /// `volume` nm³. Zeroth order reactions may produce several molecules per step, so the
+    /// result is not bounded by 1.
+    pub fn from_rate_constant(k: f32, order: usize, volume: f32, dt: f32) -> Self {
+        match order {
+            0 => Probability(k * molecules_per_micromolar_in(volume) * dt),
+            1 => Probability(k * dt),
+            _ => Probability(k * dt / (molecules_per_micromolar_in(volume) * alpha_in(volume))),
+        }
     }
 
     pub fn get(&self) -> f32 {
@@ -1,10 +1,8 @@
-use rand::{distributions::Uniform, Rng};
+use simulation_parser::kinetics::alpha_in;
 
 use crate::probability::Probability;
 use std::hash::Hash;
 
-const ALPHA: f32 = 7.4e-7;
-
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Element {
     pub uuid: u64,
@@ -20,60 +18,76 @@ impl Hash for Element {
 
 #[derive(Debug, Hash, PartialEq, Eq)]
 pub enum CollidedElements {
+    /// Nothing is consumed, as in a synthesis `0 -> A`.
+    Source,
     Mono(Element),
     Bi(Element, Element),
 }
 
 impl CollidedElements {
-    fn calculate_consontration(&self, state: &[i32]) -> f32 {
+    fn calculate_consontration(&self, state: &[u64], alpha: f32) -> f32 {
         match self {
+            CollidedElements::Source => 1.,
             CollidedElements::Mono(e) => state[e.uuid as usize] as f32,
+            // Two molecules of the same species: n(n-1)/2 distinct pairs can collide.
+            CollidedElements::Bi(e1, e2) if e1 == e2 => {
+                let n = state[e1.uuid as usize] as f32;
+                alpha * n * (n - 1.) / 2.
+            }
             CollidedElements::Bi(e1, e2) => {
-                ALPHA * (state[e1.uuid as usize] * state[e2.uuid as usize]) as f32
+                alpha * state[e1.uuid as usize] as f32 * state[e2.uuid as usize] as f32
             }
         }
     }
 }
 
-#[derive(Debug)]
+/// Exponentially distributed waiting time of a reaction happening `rate` times per step,
+/// from a `draw` uniform in [0, 1). It is drawn from (0, 1] instead, since a draw of 0
+/// would give an infinite waiting time, as if the reaction could no longer happen.
+pub fn waiting_time(rate: f32, draw: f64) -> f64 {
+    if rate != 0. {
+        -(1. - draw).ln() / rate as f64
+    } else {
+        f64::INFINITY
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct ReactionRegistry {
-    register: Vec<(CollidedElements, (Vec<Element>, Probability))>,
+    /// Each reaction, with the collision factor of the compartment it happens in.
+    register: Vec<(CollidedElements, (Vec<Element>, Probability), f32)>,
 }
 
 impl ReactionRegistry {
-    pub fn new() -> Self {
-        Self {
-            register: Vec::default(),
-        }
-    }
-    pub fn insert(&mut self, k: CollidedElements, v: (Vec<Element>, Probability)) {
-        self.register.push((k, v));
+    /// Registers a reaction happening in a compartment of `volume` nm³, whose molecules
+    /// collide less often the larger it is.
+    pub fn insert(&mut self, k: CollidedElements, v: (Vec<Element>, Probability), volume: f32) {
+        self.register.push((k, v, alpha_in(volume)));
     }
-    pub fn get_rate_of_all_reaction(&self, state: &[i32]) -> f32 {
-        self.register.iter().fold(0., |r, (collision, (_, p))| {
-            collision.calculate_consontration(state) * p.get() + r
-        })
+    pub fn get_rate_of_all_reaction(&self, state: &[u64]) -> f32 {
+        self.register
+            .iter()
+            .fold(0., |r, (collision, (_, p), alpha)| {
+                collision.calculate_consontration(state, *alpha) * p.get() + r
+            })
     }
-    pub fn get_rate_vector(&self, state: &[i32]) -> Vec<f32> {
+    pub fn get_rate_vector(&self, state: &[u64]) -> Vec<f32> {
         self.register
             .iter()
-            .map(|(collision, (_, p))| collision.calculate_consontration(state) * p.get())
+            .map(|(collision, (_, p), alpha)| {
+                collision.calculate_consontration(state, *alpha) * p.get()
+            })
             .collect()
     }
-    pub fn calc_tau_vector(&self, state: &[i32]) -> Vec<f32> {
+    /// Waiting time of each reaction, in steps. They are kept in `f64`, as they become much
+    /// smaller than the time they are added to when many molecules react.
+    pub fn calc_tau_vector(&self, state: &[u64]) -> Vec<f64> {
         self.get_rate_vector(state)
             .iter()
-            .map(|&rate| {
-                let dist: Uniform<f32> = rand::distributions::Uniform::new(0., 1.);
-                if rate != 0. {
-                    -rand::thread_rng().sample(dist).ln() / rate
-                } else {
-                    f32::INFINITY
-                }
-            })
+            .map(|&rate| waiting_time(rate, fastrand::f64()))
             .collect()
     }
-    pub fn calc_update_vector_and_tau(&self, state: &[i32]) -> (Vec<i32>, f32) {
+    pub fn calc_update_vector_and_tau(&self, state: &[u64]) -> (Vec<i64>, f64) {
         let mut v = vec![0; state.len()];
         match self
             .register
@@ -81,20 +95,22 @@ impl ReactionRegistry {
             .zip(self.calc_tau_vector(state))
             .min_by(|(_, t1), (_, t2)| t1.total_cmp(t2))
         {
-            Some(((collision, outcome), t)) if t.is_finite() => {
+            Some(((collision, outcome, _), t)) if t.is_finite() => {
                 match collision {
-                    CollidedElements::Mono(e) => v[e.uuid as usize] = -1,
+                    CollidedElements::Source => (),
+                    CollidedElements::Mono(e) => v[e.uuid as usize] -= 1,
                     CollidedElements::Bi(e1, e2) => {
-                        v[e1.uuid as usize] = -1;
-                        v[e2.uuid as usize] = -1;
+                        v[e1.uuid as usize] -= 1;
+                        v[e2.uuid as usize] -= 1;
                     }
                 }
                 for e in &outcome.0 {
-                    v[e.uuid as usize] = 1;
+                    v[e.uuid as usize] += 1;
                 }
                 (v, t)
             }
-            _ => (v, 0.),
+            // Nothing can happen anymore.
+            _ => (v, f64::INFINITY),
         }
     }
 }
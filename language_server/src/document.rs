@@ -0,0 +1,321 @@
+use std::collections::BTreeSet;
+
+use lsp_types::{
+    CompletionItem, CompletionItemKind, DiagnosticSeverity, Location, Position, Range, TextEdit,
+    Url,
+};
+use simulation_parser::check::check;
+use simulation_parser::diagnostic::{Diagnostic, Severity, Span};
+use simulation_parser::formats::{self, Format};
+use simulation_parser::include::Sources;
+use simulation_parser::keywords::{check_language, Language, ALIASES};
+use simulation_parser::kinetics::calc_probability;
+use simulation_parser::printer::{format, species_identifier};
+use simulation_parser::{sbml, Ast, Expression};
+
+/// Words starting the declarations and clauses whose spelling does not depend on the
+/// language, completed along with those of `keywords::ALIASES`.
+const KEYWORDS: &[&str] = &[
+    "param",
+    "sweep",
+    "simulation",
+    "compartment",
+    "include",
+    "at",
+    "when",
+    "add",
+    "set",
+    "brenda",
+    "inhibited_by",
+    "activated_by",
+];
+
+/// An open model, as last sent by the editor.
+pub struct Document {
+    pub uri: Url,
+    pub text: String,
+}
+
+/// Position of the byte `offset` of `text`, its characters counted in UTF-16 code units as
+/// the protocol does.
+pub fn position(text: &str, offset: usize) -> Position {
+    let before = &text[..offset.min(text.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    Position {
+        line: before.matches('\n').count() as u32,
+        character: before[line_start..].encode_utf16().count() as u32,
+    }
+}
+
+/// Byte offset of `position` in `text`, the end of its line when it is past it.
+pub fn offset(text: &str, position: Position) -> usize {
+    let mut line_start = 0;
+    for _ in 0..position.line {
+        match text[line_start..].find('\n') {
+            Some(i) => line_start += i + 1,
+            None => return text.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in text[line_start..].char_indices() {
+        if c == '\n' || units >= position.character as usize {
+            return line_start + i;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+fn range(text: &str, span: Span) -> Range {
+    Range {
+        start: position(text, span.start),
+        end: position(text, span.end),
+    }
+}
+
+/// The species whose name is under the byte `offset` of `text`, with the compartment
+/// following it: `s`, `s[nucleus]` or the content of a quoted name.
+fn species_at(text: &str, offset: usize) -> Option<String> {
+    let line_start = text[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[offset..].find('\n').map_or(text.len(), |i| offset + i);
+    let line = &text[line_start..line_end];
+    let column = offset - line_start;
+    let (name, end) = if line[..column].matches('"').count() % 2 == 1 {
+        let start = line[..column].rfind('"')? + 1;
+        let end = column + line[column..].find('"')?;
+        (&line[start..end], end + 1)
+    } else {
+        let start = line[..column]
+            .rfind(|c: char| !c.is_alphanumeric())
+            .map_or(0, |i| i + 1);
+        let end = line[column..]
+            .find(|c: char| !c.is_alphanumeric())
+            .map_or(line.len(), |i| column + i);
+        (&line[start..end], end)
+    };
+    if name.is_empty() {
+        return None;
+    }
+    let compartment = line[end..]
+        .strip_prefix('[')
+        .and_then(|rest| rest.split_once(']'))
+        .map(|(compartment, _)| compartment.trim());
+    Some(match compartment {
+        Some(compartment) => format!("{name}[{compartment}]"),
+        None => name.to_string(),
+    })
+}
+
+/// A side of a reaction as it is written: `2 A + B`, or `0` when it is empty.
+fn side(species: &[(String, u32)]) -> String {
+    if species.is_empty() {
+        return "0".to_string();
+    }
+    species
+        .iter()
+        .map(|(name, n)| match n {
+            1 => species_identifier(name),
+            n => format!("{n} {}", species_identifier(name)),
+        })
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+impl Document {
+    /// Path of the document, which the files it includes are relative to.
+    fn path(&self) -> String {
+        match self.uri.to_file_path() {
+            Ok(path) => path.display().to_string(),
+            Err(()) => self.uri.path().to_string(),
+        }
+    }
+
+    fn load(&self) -> (Result<(Ast, Vec<Diagnostic>), Diagnostic>, Sources) {
+        let mut sources = Sources::default();
+        let loaded = formats::load(&self.path(), self.text.clone(), &mut sources);
+        (loaded, sources)
+    }
+
+    /// The errors and warnings `check` reports on the model, those in the files it includes
+    /// shown at its start.
+    pub fn diagnostics(&self) -> Vec<lsp_types::Diagnostic> {
+        let (loaded, sources) = self.load();
+        let diagnostics = match loaded {
+            Ok((ast, mut diagnostics)) => {
+                diagnostics.extend(check(&ast, &ast.settings()));
+                diagnostics
+            }
+            Err(error) => vec![error],
+        };
+        diagnostics
+            .into_iter()
+            .map(|d| {
+                let (range, mut message) = if d.span.file == 0 {
+                    (range(&self.text, d.span), d.message)
+                } else {
+                    let location = sources.location(d.span);
+                    (Range::default(), format!("{location}: {}", d.message))
+                };
+                for note in &d.notes {
+                    message.push_str(&format!("\nnote: {note}"));
+                }
+                lsp_types::Diagnostic {
+                    range,
+                    severity: Some(match d.severity {
+                        Severity::Error => DiagnosticSeverity::ERROR,
+                        Severity::Warning => DiagnosticSeverity::WARNING,
+                    }),
+                    source: Some("simulation_parser".to_string()),
+                    message,
+                    ..Default::default()
+                }
+            })
+            .collect()
+    }
+
+    /// The probabilities P1, P2 and P3 both simulations give the enzymatic reaction at the
+    /// byte `offset`, for each reaction its enzyme catalyses, as Markdown.
+    pub fn hover(&self, offset: usize) -> Option<String> {
+        let (Ok((ast, _)), _) = self.load() else {
+            return None;
+        };
+        let reaction = ast.0.iter().find_map(|e| match e {
+            Expression::Reaction(r)
+                if r.span.file == 0 && r.span.start <= offset && offset < r.span.end =>
+            {
+                Some(r)
+            }
+            _ => None,
+        })?;
+        if reaction.awaits_brenda() {
+            return Some(format!(
+                "The Km and kcat of `{}` are looked up in BRENDA when simulating.",
+                reaction.enzhym
+            ));
+        }
+        let dt = ast.settings().dt;
+        let mut hover = format!("Probabilities for steps of {dt} s:\n");
+        for r in reaction.catalysed() {
+            let (p1, p2, p3) = calc_probability(r.km.value, r.kcat.value, dt);
+            hover.push_str(&format!(
+                "\n- `{} : {} -> {}`: P1 = {p1}, P2 = {p2}, P3 = {p3}",
+                species_identifier(&r.enzhym),
+                side(&r.solubes),
+                side(&r.results)
+            ));
+        }
+        Some(hover)
+    }
+
+    /// The `init` declaration of the species at the byte `offset`, which may be in a file
+    /// the model includes.
+    pub fn definition(&self, offset: usize) -> Option<Location> {
+        let species = species_at(&self.text, offset)?;
+        let (Ok((ast, _)), sources) = self.load() else {
+            return None;
+        };
+        let span = ast.0.iter().find_map(|e| match e {
+            Expression::InitDeclaration(init) if init.identifier == species => Some(init.span),
+            _ => None,
+        })?;
+        let uri = if span.file == 0 {
+            self.uri.clone()
+        } else {
+            Url::from_file_path(sources.path(span.file)).ok()?
+        };
+        Some(Location {
+            uri,
+            range: range(sources.text(span.file), span),
+        })
+    }
+
+    /// The keywords, then the species and parameters the model declares.
+    pub fn completions(&self) -> Vec<CompletionItem> {
+        let keywords = ALIASES
+            .iter()
+            .map(|a| a.spelling)
+            .chain(KEYWORDS.iter().copied())
+            .collect::<BTreeSet<_>>();
+        let mut items = keywords
+            .into_iter()
+            .map(|keyword| CompletionItem {
+                label: keyword.to_string(),
+                kind: Some(CompletionItemKind::KEYWORD),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        let (Ok((ast, _)), _) = self.load() else {
+            return items;
+        };
+        let mut species = BTreeSet::new();
+        let mut params = BTreeSet::new();
+        for expression in &ast.0 {
+            match expression {
+                Expression::Reaction(r) => {
+                    species.insert(&r.enzhym);
+                    species.extend(r.solubes.iter().chain(&r.results).map(|(s, _)| s));
+                    species.extend(r.modifiers.iter().map(|m| &m.species));
+                }
+                Expression::MassAction(r) => {
+                    species.extend(r.reactants.iter().chain(&r.products).map(|(s, _)| s));
+                }
+                Expression::InitDeclaration(init) => {
+                    species.insert(&init.identifier);
+                }
+                Expression::Event(event) => species.extend(event.species()),
+                Expression::Param(param) => {
+                    params.insert(&param.identifier);
+                }
+                _ => (),
+            }
+        }
+        items.extend(species.into_iter().map(|s| CompletionItem {
+            label: species_identifier(s),
+            kind: Some(CompletionItemKind::VARIABLE),
+            detail: Some("species".to_string()),
+            ..Default::default()
+        }));
+        items.extend(params.into_iter().map(|p| CompletionItem {
+            label: p.clone(),
+            kind: Some(CompletionItemKind::CONSTANT),
+            detail: Some("parameter".to_string()),
+            ..Default::default()
+        }));
+        items
+    }
+
+    /// The edit formatting the model canonically, in the language its keywords are spelt in,
+    /// or `None` when it cannot be parsed or is not written in the model language.
+    pub fn formatting(&self) -> Option<Vec<TextEdit>> {
+        let path = self.path();
+        if Format::of(&path).is_some() || sbml::is_sbml(&path) {
+            return None;
+        }
+        let formatted = format(&self.text, self.language()).ok()?;
+        if formatted == self.text {
+            return Some(vec![]);
+        }
+        Some(vec![TextEdit {
+            range: Range {
+                start: Position::default(),
+                end: position(&self.text, self.text.len()),
+            },
+            new_text: formatted,
+        }])
+    }
+
+    /// English when every keyword of the model is spelt in English and one is not in French,
+    /// French otherwise, as `fmt` defaults to.
+    fn language(&self) -> Language {
+        let (Ok((ast, _)), sources) = self.load() else {
+            return Language::default();
+        };
+        let english = check_language(&ast, &sources, Language::English).is_empty()
+            && !check_language(&ast, &sources, Language::French).is_empty();
+        if english {
+            Language::English
+        } else {
+            Language::default()
+        }
+    }
+}
@@ -0,0 +1,302 @@
+//! Language server of the models of the simulations, talking the Language Server Protocol
+//! over stdin and stdout: diagnostics as the model is edited, the probabilities of the
+//! enzymatic reactions on hover, go to the `init` of a species, completion and formatting.
+
+mod document;
+
+use std::collections::HashMap;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{
+    DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, Notification as _,
+    PublishDiagnostics,
+};
+use lsp_types::request::{
+    Completion, Formatting, GotoDefinition, HoverRequest, Request as LspRequest,
+};
+use lsp_types::{
+    CompletionOptions, CompletionResponse, DidChangeTextDocumentParams, DidCloseTextDocumentParams,
+    DidOpenTextDocumentParams, GotoDefinitionResponse, Hover, HoverContents,
+    HoverProviderCapability, MarkupContent, MarkupKind, OneOf, PublishDiagnosticsParams,
+    ServerCapabilities, TextDocumentSyncCapability, TextDocumentSyncKind, Url,
+};
+
+use crate::document::{offset, Document};
+
+fn main() -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+    serve(connection)?;
+    io_threads.join()?;
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::FULL)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        definition_provider: Some(OneOf::Left(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        document_formatting_provider: Some(OneOf::Left(true)),
+        ..Default::default()
+    }
+}
+
+/// Answers the requests of the client on `connection` until it shuts the server down. The
+/// connection is dropped then, which ends the thread writing to stdout.
+fn serve(connection: Connection) -> anyhow::Result<()> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+    let mut documents = HashMap::new();
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+                let response = respond(&documents, request);
+                connection.sender.send(Message::Response(response))?;
+            }
+            Message::Notification(notification) => {
+                if let Some(uri) = update(&mut documents, notification)? {
+                    let diagnostics = documents
+                        .get(&uri)
+                        .map(Document::diagnostics)
+                        .unwrap_or_default();
+                    let params = PublishDiagnosticsParams {
+                        uri,
+                        diagnostics,
+                        version: None,
+                    };
+                    connection
+                        .sender
+                        .send(Message::Notification(Notification::new(
+                            PublishDiagnostics::METHOD.to_string(),
+                            params,
+                        )))?;
+                }
+            }
+            Message::Response(_) => (),
+        }
+    }
+    Ok(())
+}
+
+/// Applies a notification opening, changing or closing a document, and returns the
+/// document whose diagnostics are to be published again.
+fn update(
+    documents: &mut HashMap<Url, Document>,
+    notification: Notification,
+) -> anyhow::Result<Option<Url>> {
+    match notification.method.as_str() {
+        DidOpenTextDocument::METHOD => {
+            let params: DidOpenTextDocumentParams = serde_json::from_value(notification.params)?;
+            let uri = params.text_document.uri;
+            let text = params.text_document.text;
+            documents.insert(
+                uri.clone(),
+                Document {
+                    uri: uri.clone(),
+                    text,
+                },
+            );
+            Ok(Some(uri))
+        }
+        DidChangeTextDocument::METHOD => {
+            let params: DidChangeTextDocumentParams = serde_json::from_value(notification.params)?;
+            let uri = params.text_document.uri;
+            // The whole text is sent, the server syncing documents in full.
+            let Some(change) = params.content_changes.into_iter().last() else {
+                return Ok(None);
+            };
+            documents.insert(
+                uri.clone(),
+                Document {
+                    uri: uri.clone(),
+                    text: change.text,
+                },
+            );
+            Ok(Some(uri))
+        }
+        DidCloseTextDocument::METHOD => {
+            let params: DidCloseTextDocumentParams = serde_json::from_value(notification.params)?;
+            documents.remove(&params.text_document.uri);
+            Ok(Some(params.text_document.uri))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Parses the parameters of `request` as those of `R`, and serializes the result `handler`
+/// gives.
+fn handle<R: LspRequest>(
+    request: Request,
+    handler: impl FnOnce(R::Params) -> R::Result,
+) -> Result<serde_json::Value, serde_json::Error> {
+    let params = serde_json::from_value(request.params)?;
+    serde_json::to_value(handler(params))
+}
+
+fn respond(documents: &HashMap<Url, Document>, request: Request) -> Response {
+    let id = request.id.clone();
+    let document = |uri: &Url| documents.get(uri);
+    let result = match request.method.as_str() {
+        HoverRequest::METHOD => handle::<HoverRequest>(request, |params| {
+            let at = params.text_document_position_params;
+            let document = document(&at.text_document.uri)?;
+            let hover = document.hover(offset(&document.text, at.position))?;
+            Some(Hover {
+                contents: HoverContents::Markup(MarkupContent {
+                    kind: MarkupKind::Markdown,
+                    value: hover,
+                }),
+                range: None,
+            })
+        }),
+        GotoDefinition::METHOD => handle::<GotoDefinition>(request, |params| {
+            let at = params.text_document_position_params;
+            let document = document(&at.text_document.uri)?;
+            let location = document.definition(offset(&document.text, at.position))?;
+            Some(GotoDefinitionResponse::Scalar(location))
+        }),
+        Completion::METHOD => handle::<Completion>(request, |params| {
+            let document = document(&params.text_document_position.text_document.uri)?;
+            Some(CompletionResponse::Array(document.completions()))
+        }),
+        Formatting::METHOD => handle::<Formatting>(request, |params| {
+            document(&params.text_document.uri)?.formatting()
+        }),
+        method => {
+            return Response::new_err(
+                id,
+                ErrorCode::MethodNotFound as i32,
+                format!("unsupported request `{method}`"),
+            )
+        }
+    };
+    match result {
+        Ok(result) => Response::new_ok(id, result),
+        Err(error) => Response::new_err(id, ErrorCode::InvalidParams as i32, error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use lsp_server::RequestId;
+    use lsp_types::notification::{Exit, Initialized};
+    use lsp_types::request::{Initialize, Shutdown};
+    use lsp_types::{DiagnosticSeverity, Position};
+    use serde_json::{json, Value};
+    use simulation_parser::kinetics::calc_probability;
+
+    use super::*;
+
+    fn notify<N: lsp_types::notification::Notification>(client: &Connection, params: Value) {
+        let notification = Notification::new(N::METHOD.to_string(), params);
+        client
+            .sender
+            .send(Message::Notification(notification))
+            .unwrap();
+    }
+
+    fn request<R: LspRequest>(client: &Connection, id: i32, params: Value) -> Value {
+        let request = Request::new(RequestId::from(id), R::METHOD.to_string(), params);
+        client.sender.send(Message::Request(request)).unwrap();
+        match client.receiver.recv().unwrap() {
+            Message::Response(response) => response.result.unwrap(),
+            message => panic!("expected a response, got {message:?}"),
+        }
+    }
+
+    fn published(client: &Connection) -> PublishDiagnosticsParams {
+        match client.receiver.recv().unwrap() {
+            Message::Notification(n) => serde_json::from_value(n.params).unwrap(),
+            message => panic!("expected diagnostics, got {message:?}"),
+        }
+    }
+
+    #[test]
+    fn t_document_positions() {
+        let text = "init(é) = 1;\n\"𝜇 s\" : a -> b;";
+        let at = |line, character| Position { line, character };
+        assert_eq!(at(1, 3), document::position(text, text.find(" s").unwrap()));
+        assert_eq!(text.find('=').unwrap(), offset(text, at(0, 8)));
+        assert_eq!(text.find('\n').unwrap(), offset(text, at(0, 40)));
+        assert_eq!(text.len(), offset(text, at(5, 0)));
+    }
+
+    #[test]
+    fn t_server() {
+        let (server, client) = Connection::memory();
+        let server = thread::spawn(move || serve(server));
+        request::<Initialize>(&client, 1, json!({ "capabilities": {} }));
+        notify::<Initialized>(&client, json!({}));
+
+        let uri = "file:///models/model.txt";
+        notify::<DidOpenTextDocument>(
+            &client,
+            json!({ "textDocument": {
+                "uri": uri, "languageId": "model", "version": 1, "text": "init(s) = ;"
+            }}),
+        );
+        let diagnostics = published(&client).diagnostics;
+        assert_eq!(Some(DiagnosticSeverity::ERROR), diagnostics[0].severity);
+
+        let text = "E1:s->p|100-50;\ninit(E1)=30;\ninit(s)=2000;\n";
+        notify::<DidChangeTextDocument>(
+            &client,
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{ "text": text }]
+            }),
+        );
+        assert!(published(&client)
+            .diagnostics
+            .iter()
+            .all(|d| d.severity != Some(DiagnosticSeverity::ERROR)));
+
+        let at = |line, character| json!({ "textDocument": { "uri": uri }, "position": { "line": line, "character": character } });
+        let hover = request::<HoverRequest>(&client, 2, at(0, 1));
+        let (p1, p2, p3) = calc_probability(100., 50., 1e-4);
+        let expected = format!("`E1 : s -> p`: P1 = {p1}, P2 = {p2}, P3 = {p3}");
+        assert!(hover["contents"]["value"]
+            .as_str()
+            .unwrap()
+            .contains(&expected));
+        assert_eq!(Value::Null, request::<HoverRequest>(&client, 3, at(1, 2)));
+
+        let definition = request::<GotoDefinition>(&client, 4, at(0, 3));
+        assert_eq!(json!(uri), definition["uri"]);
+        assert_eq!(
+            json!({ "line": 2, "character": 0 }),
+            definition["range"]["start"]
+        );
+
+        let completions = request::<Completion>(&client, 5, at(1, 0));
+        let labels = completions
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|c| c["label"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        for label in ["E1", "s", "p", "init", "vitesse", "speed", "param"] {
+            assert!(labels.contains(&label), "`{label}` is not completed");
+        }
+
+        let edits = request::<Formatting>(
+            &client,
+            6,
+            json!({ "textDocument": { "uri": uri }, "options": { "tabSize": 4, "insertSpaces": true } }),
+        );
+        let formatted = simulation_parser::printer::format(text, Default::default()).unwrap();
+        assert_eq!(json!(formatted), edits[0]["newText"]);
+        assert_eq!(
+            json!({ "line": 3, "character": 0 }),
+            edits[0]["range"]["end"]
+        );
+
+        request::<Shutdown>(&client, 7, Value::Null);
+        notify::<Exit>(&client, Value::Null);
+        server.join().unwrap().unwrap();
+    }
+}
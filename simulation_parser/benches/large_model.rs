@@ -0,0 +1,82 @@
+//! Parsing time of generated genome-scale models, which should grow linearly with their
+//! number of reactions: the time per reaction reported for each size should not grow,
+//! which `linear` checks before measuring it.
+
+use std::fmt::Write;
+use std::time::{Duration, Instant};
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use simulation_parser::{Ast, Parsable};
+
+/// A model of `reactions` reactions between twice as many metabolites, with the
+/// declarations of its species.
+fn model(reactions: usize) -> String {
+    let mut rng = fastrand::Rng::with_seed(7);
+    let metabolites = 2 * reactions;
+    let mut metabolite = || format!("M{}", rng.usize(..metabolites));
+    let mut text = String::from("// Generated model.\nparam km = 120 uM;\nparam kcat = 80 /s;\n\n");
+    for i in 0..reactions {
+        let (s1, s2, p) = (metabolite(), metabolite(), metabolite());
+        match i % 4 {
+            0 => writeln!(text, "E{i} : {s1} + {s2} -> {p} | km - kcat;"),
+            1 => writeln!(
+                text,
+                "E{i} : {s1} <-> {p} | 150 uM - 10 /s, 2.5 mM - 1e2 /s, keq = 1.5;"
+            ),
+            2 => writeln!(text, "{s1} + {s2} -> {p} @ k = 0.5 /uM/s; // binding"),
+            _ => writeln!(
+                text,
+                "\"E{i}-x\" : 2 {s1} -> {p} + {s2} | (km * 2) - 1_000 /min;"
+            ),
+        }
+        .unwrap();
+    }
+    for i in 0..reactions {
+        writeln!(text, "init(E{i}) = {};", 10 + i % 90).unwrap();
+        writeln!(text, "vitesse(E{i}) = 1.5 um2/s;").unwrap();
+        writeln!(text, "diametre(E{i}) = 5 nm;").unwrap();
+    }
+    text
+}
+
+/// The fastest of a few parses of `text`, per reaction of its `reactions`.
+fn time_per_reaction(text: &str, reactions: usize) -> Duration {
+    (0..3)
+        .map(|_| {
+            let start = Instant::now();
+            Ast::parse(text.into()).unwrap();
+            start.elapsed()
+        })
+        .min()
+        .unwrap()
+        / reactions as u32
+}
+
+/// Fails when parsing a reaction of the largest model takes much longer than one of the
+/// smallest, which a parse growing faster than linearly would.
+fn linear(_: &mut Criterion) {
+    let base = time_per_reaction(&model(1_000), 1_000);
+    for reactions in [10_000, 100_000] {
+        let time = time_per_reaction(&model(reactions), reactions);
+        assert!(
+            time < 3 * base,
+            "a reaction of a model of {reactions} takes {time:?} to parse, against {base:?} in a model of 1000"
+        );
+    }
+}
+
+fn parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parse");
+    group.sample_size(10);
+    for reactions in [1_000, 10_000, 100_000] {
+        let text = model(reactions);
+        group.throughput(Throughput::Elements(reactions as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(reactions), &text, |b, text| {
+            b.iter(|| Ast::parse(text.as_str().into()).unwrap())
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, linear, parse);
+criterion_main!(benches);
@@ -0,0 +1,598 @@
+//! Kinetic constants of the BRENDA enzyme database, read from the text file it can be
+//! downloaded as, for reactions whose kinetics are written `brenda(1.1.1.1, ethanol)`
+//! instead of their Km and kcat.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::brenda_parser_helpers::*;
+use crate::diagnostic::{Diagnostic, Span};
+use crate::formula::{Formula, Value};
+use crate::parser_combinator::{cut, Parser, ParserInput, ParserResult, ParserSuccess};
+use crate::printer::identifier;
+use crate::units::{Unit, UNITS};
+use crate::{Ast, Expression, Parsable, Reaction};
+
+/// `brenda(1.1.1.1, ethanol)`, or `brenda(1.1.1.1, ethanol, "Homo sapiens")` to only use
+/// the measurements made in an organism.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct BrendaQuery {
+    /// EC number of the enzyme.
+    pub ec: String,
+    /// Substrate as BRENDA names it, compared case-insensitively.
+    pub substrate: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub organism: Option<String>,
+    /// Where the Km and kcat come from, once looked up by [`Database::fill`].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<Provenance>,
+}
+
+impl Parsable for BrendaQuery {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess { next_input, .. } = parse_brenda().parse(text)?;
+        let ParserSuccess { next_input, .. } = parse_lparen().parse(next_input)?;
+        cut(|input: ParserInput<'a>| -> ParserResult<'a, Self> {
+            let ParserSuccess {
+                content: ec,
+                next_input,
+            } = parse_ec_number().parse(input)?;
+            let ParserSuccess { next_input, .. } = parse_comma().parse(next_input)?;
+            let ParserSuccess {
+                content: substrate,
+                next_input,
+            } = parse_identifier().parse(next_input)?;
+            let ParserSuccess {
+                content: organism,
+                next_input,
+            } = parse_comma()
+                .skip_me(parse_string())
+                .optional()
+                .parse(next_input)?;
+            let ParserSuccess { next_input, .. } = parse_rparen().parse(next_input)?;
+            Ok(ParserSuccess {
+                content: Self {
+                    ec,
+                    substrate,
+                    organism,
+                    provenance: None,
+                },
+                next_input,
+            })
+        })
+        .parse(next_input)
+    }
+}
+
+impl Display for BrendaQuery {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "brenda({}, {}", self.ec, identifier(&self.substrate))?;
+        if let Some(organism) = &self.organism {
+            write!(f, ", \"{organism}\"")?;
+        }
+        write!(f, ")")
+    }
+}
+
+/// A kinetic constant BRENDA lists measurements of.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Constant {
+    /// `KM_VALUE`, in mM.
+    Km,
+    /// `TURNOVER_NUMBER`, in s⁻¹.
+    Kcat,
+}
+
+impl Constant {
+    fn name(self) -> &'static str {
+        match self {
+            Constant::Km => "Km",
+            Constant::Kcat => "kcat",
+        }
+    }
+    /// Unit BRENDA gives the constant in.
+    fn unit(self) -> Unit {
+        let symbol = match self {
+            Constant::Km => "mM",
+            Constant::Kcat => "/s",
+        };
+        *UNITS
+            .iter()
+            .find(|u| u.symbol == symbol)
+            .expect("BRENDA units are known units")
+    }
+}
+
+/// A value measured in one or several organisms, as a `KM` or `TN` line gives it.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Measurement {
+    pub value: f64,
+    pub organisms: Vec<String>,
+    /// Numbers of the references of the enzyme in BRENDA.
+    pub references: Vec<u32>,
+}
+
+/// The median of the measurements of a constant, in the unit BRENDA gives it in.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Estimate {
+    pub value: f64,
+    pub measurements: Vec<Measurement>,
+}
+
+impl Estimate {
+    fn new(measurements: Vec<Measurement>) -> Self {
+        let mut values = measurements.iter().map(|m| m.value).collect::<Vec<_>>();
+        values.sort_by(f64::total_cmp);
+        let middle = values.len() / 2;
+        let value = if values.len() % 2 == 0 {
+            (values[middle - 1] + values[middle]) / 2.
+        } else {
+            values[middle]
+        };
+        Self {
+            value,
+            measurements,
+        }
+    }
+    fn describe(&self, constant: Constant) -> String {
+        let symbol = constant.unit().symbol;
+        let measurement = |m: &Measurement| {
+            let references = m.references.iter().map(u32::to_string).collect::<Vec<_>>();
+            format!(
+                "{} {symbol} in {} <{}>",
+                m.value,
+                m.organisms.join(", "),
+                references.join(",")
+            )
+        };
+        match self.measurements.as_slice() {
+            [m] => format!("{} = {}", constant.name(), measurement(m)),
+            measurements => format!(
+                "{} = {} {symbol}, median of {}",
+                constant.name(),
+                self.value,
+                measurements
+                    .iter()
+                    .map(measurement)
+                    .collect::<Vec<_>>()
+                    .join("; ")
+            ),
+        }
+    }
+}
+
+/// The BRENDA measurements the Km and kcat of a reaction are taken from.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Provenance {
+    pub km: Estimate,
+    pub kcat: Estimate,
+}
+
+impl Display for Provenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}\n{}",
+            self.km.describe(Constant::Km),
+            self.kcat.describe(Constant::Kcat)
+        )
+    }
+}
+
+/// A `KM` or `TN` line: `#1,2# 0.25 {ethanol} (#1# pH 7.5 <4>) <4,7>`.
+#[derive(Debug)]
+struct Entry {
+    proteins: Vec<u32>,
+    /// The middle of the range when one is given.
+    value: f64,
+    substrate: String,
+    references: Vec<u32>,
+}
+
+#[derive(Debug, Default)]
+struct Enzyme {
+    /// Organism of each protein, by protein number.
+    organisms: HashMap<u32, String>,
+    km: Vec<Entry>,
+    kcat: Vec<Entry>,
+}
+
+impl Enzyme {
+    fn entries(&self, constant: Constant) -> &[Entry] {
+        match constant {
+            Constant::Km => &self.km,
+            Constant::Kcat => &self.kcat,
+        }
+    }
+    fn organisms_of(&self, entry: &Entry) -> Vec<String> {
+        let mut organisms = vec![];
+        for protein in &entry.proteins {
+            let organism = self
+                .organisms
+                .get(protein)
+                .cloned()
+                .unwrap_or_else(|| format!("protein #{protein}"));
+            if !organisms.contains(&organism) {
+                organisms.push(organism);
+            }
+        }
+        organisms
+    }
+}
+
+/// The `TAG\tcontent` lines of a BRENDA text file, with their continuation lines, which
+/// start with a tab, joined. Section titles and the `///` ending an enzyme have no content.
+fn lines(text: &str) -> Vec<(&str, String)> {
+    let mut lines: Vec<(&str, String)> = vec![];
+    for line in text.lines() {
+        if let Some(continuation) = line.strip_prefix('\t') {
+            if let Some((_, content)) = lines.last_mut() {
+                content.push(' ');
+                content.push_str(continuation.trim());
+            }
+        } else if !line.trim().is_empty() {
+            let (tag, content) = line.split_once('\t').unwrap_or((line, ""));
+            lines.push((tag.trim(), content.trim().to_string()));
+        }
+    }
+    lines
+}
+
+/// The protein numbers `#1,2#` starting `content`, and what follows them.
+fn proteins(content: &str) -> Option<(Vec<u32>, &str)> {
+    let (numbers, rest) = content.strip_prefix('#')?.split_once('#')?;
+    let proteins = numbers
+        .split(',')
+        .filter_map(|n| n.trim().parse().ok())
+        .collect();
+    Some((proteins, rest.trim()))
+}
+
+/// The reference numbers `<4,7>` ending `content`.
+fn references(content: &str) -> Vec<u32> {
+    content
+        .trim_end()
+        .strip_suffix('>')
+        .and_then(|rest| rest.rsplit_once('<'))
+        .map(|(_, numbers)| {
+            numbers
+                .split(',')
+                .filter_map(|n| n.trim().parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// The organism of a `PR` line, without the accession number that may follow it:
+/// `#1# Homo sapiens P07327 UniProt <1,2>` gives `Homo sapiens`.
+fn organism(content: &str) -> Option<(u32, String)> {
+    let (proteins, rest) = proteins(content)?;
+    let name = rest[..rest.find(['<', '(']).unwrap_or(rest.len())].trim();
+    let mut words = name.split_whitespace().collect::<Vec<_>>();
+    let databases = ["UniProt", "SwissProt", "Swissprot", "TrEMBL", "GenBank"];
+    if words.len() > 2 && databases.contains(&words[words.len() - 1]) {
+        words.truncate(words.len() - 2);
+    }
+    Some((*proteins.first()?, words.join(" ")))
+}
+
+/// A `KM` or `TN` line. Values BRENDA leaves unknown, written `-999`, are skipped.
+fn entry(content: &str) -> Option<Entry> {
+    let (proteins, rest) = proteins(content)?;
+    let (value, rest) = rest.split_once(char::is_whitespace)?;
+    let value = match value.split_once('-') {
+        _ if value == "-999" => return None,
+        Some((low, high)) if !low.is_empty() => {
+            (low.parse::<f64>().ok()? + high.parse::<f64>().ok()?) / 2.
+        }
+        _ => value.parse().ok()?,
+    };
+    let rest = rest.trim_start().strip_prefix('{')?;
+    let mut depth = 1;
+    let end = rest.find(|c| {
+        match c {
+            '{' => depth += 1,
+            '}' => depth -= 1,
+            _ => (),
+        }
+        depth == 0
+    })?;
+    Some(Entry {
+        proteins,
+        value,
+        substrate: rest[..end].trim().to_string(),
+        references: references(&rest[end..]),
+    })
+}
+
+/// The Km and turnover numbers of the enzymes of a BRENDA text file, by EC number.
+#[derive(Debug, Default)]
+pub struct Database {
+    enzymes: HashMap<String, Enzyme>,
+}
+
+impl Database {
+    /// Reads the `ID`, `PR`, `KM` and `TN` lines of a BRENDA text file, ignoring the
+    /// others. Fails when the text has no enzyme.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut database = Self::default();
+        let mut current: Option<(String, Enzyme)> = None;
+        for (tag, content) in lines(text) {
+            if matches!(tag, "ID" | "///") {
+                database.enzymes.extend(current.take());
+            }
+            if tag == "ID" {
+                let ec = content.split_whitespace().next().unwrap_or_default();
+                current = Some((ec.to_string(), Enzyme::default()));
+            }
+            let Some((_, enzyme)) = &mut current else {
+                continue;
+            };
+            match tag {
+                "PR" => enzyme.organisms.extend(organism(&content)),
+                "KM" => enzyme.km.extend(entry(&content)),
+                "TN" => enzyme.kcat.extend(entry(&content)),
+                _ => (),
+            }
+        }
+        database.enzymes.extend(current);
+        if database.enzymes.is_empty() {
+            return Err("no enzyme found, expected the `ID` lines of a BRENDA text file".into());
+        }
+        Ok(database)
+    }
+
+    /// The median of the measurements of `constant` for the query, restricted to
+    /// `organism` when one is given.
+    fn estimate(
+        &self,
+        enzyme: &Enzyme,
+        query: &BrendaQuery,
+        constant: Constant,
+        organism: Option<&str>,
+        span: Span,
+    ) -> Result<Estimate, Diagnostic> {
+        let entries = enzyme.entries(constant);
+        let measurements = entries
+            .iter()
+            .filter(|e| e.substrate.eq_ignore_ascii_case(&query.substrate))
+            .map(|e| Measurement {
+                value: e.value,
+                organisms: enzyme.organisms_of(e),
+                references: e.references.clone(),
+            })
+            .collect::<Vec<_>>();
+        let missing = format!(
+            "BRENDA has no {} of EC {} for `{}`",
+            constant.name(),
+            query.ec,
+            query.substrate
+        );
+        if measurements.is_empty() {
+            let substrates = entries
+                .iter()
+                .map(|e| e.substrate.as_str())
+                .collect::<BTreeSet<_>>();
+            let note = if substrates.is_empty() {
+                format!("it has no {} of this enzyme", constant.name())
+            } else {
+                format!(
+                    "it has some for: {}",
+                    substrates.into_iter().collect::<Vec<_>>().join(", ")
+                )
+            };
+            return Err(Diagnostic::error(missing, span).with_note(note));
+        }
+        let Some(organism) = organism else {
+            return Ok(Estimate::new(measurements));
+        };
+        let (selected, others): (Vec<_>, Vec<_>) = measurements
+            .into_iter()
+            .partition(|m| m.organisms.iter().any(|o| o.eq_ignore_ascii_case(organism)));
+        if selected.is_empty() {
+            let organisms = others
+                .iter()
+                .flat_map(|m| &m.organisms)
+                .map(String::as_str)
+                .collect::<BTreeSet<_>>();
+            return Err(
+                Diagnostic::error(format!("{missing} in {organism}"), span).with_note(format!(
+                    "it has some in: {}",
+                    organisms.into_iter().collect::<Vec<_>>().join(", ")
+                )),
+            );
+        }
+        Ok(Estimate::new(selected))
+    }
+
+    /// Looks up the Km and kcat of the reactions written with `brenda(...)` and records
+    /// the measurements they come from. Queries that do not name an organism are
+    /// restricted to `organism` when one is given, and use the median of every
+    /// organism otherwise.
+    pub fn fill(&self, ast: &mut Ast, organism: Option<&str>) -> Vec<Diagnostic> {
+        let mut diagnostics = vec![];
+        for expression in &mut ast.0 {
+            let Expression::Reaction(r) = expression else {
+                continue;
+            };
+            let Some(query) = &mut r.brenda else {
+                continue;
+            };
+            let Some(enzyme) = self.enzymes.get(&query.ec) else {
+                diagnostics.push(Diagnostic::error(
+                    format!("EC {} is not in the BRENDA file", query.ec),
+                    r.span,
+                ));
+                continue;
+            };
+            let organism = query.organism.as_deref().or(organism);
+            let km = self.estimate(enzyme, query, Constant::Km, organism, r.span);
+            let kcat = self.estimate(enzyme, query, Constant::Kcat, organism, r.span);
+            match (km, kcat) {
+                (Ok(km), Ok(kcat)) => {
+                    r.km = value(&km, Constant::Km);
+                    r.kcat = value(&kcat, Constant::Kcat);
+                    query.provenance = Some(Provenance { km, kcat });
+                }
+                (km, kcat) => diagnostics.extend(km.err().into_iter().chain(kcat.err())),
+            }
+        }
+        diagnostics
+    }
+}
+
+/// The estimate written in the unit BRENDA gives it in, such as `0.25 mM`.
+fn value(estimate: &Estimate, constant: Constant) -> Value {
+    Value::new(Formula::Unit(
+        Box::new(Formula::Number(estimate.value as f32)),
+        constant.unit(),
+    ))
+}
+
+/// The reactions whose Km and kcat were looked up in BRENDA, with the measurements they
+/// come from.
+pub fn provenances(ast: &Ast) -> impl Iterator<Item = (&Reaction, &Provenance)> {
+    ast.0.iter().filter_map(|e| match e {
+        Expression::Reaction(r) => Some((r.as_ref(), r.brenda.as_ref()?.provenance.as_ref()?)),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::check::check;
+    use crate::formats::Format;
+    use crate::keywords::Language;
+    use crate::printer;
+    use crate::settings::Settings;
+
+    #[test]
+    fn t_lines() {
+        let text =
+            "ID\t1.1.1.1\n\nKM\t#1,2# 0.25-0.75 {ethanol {95%}} (#1# pH 7.5 <4>)\n\t<4,7>\n///";
+        let lines = lines(text);
+        assert_eq!(
+            vec![
+                ("ID", "1.1.1.1".to_string()),
+                (
+                    "KM",
+                    "#1,2# 0.25-0.75 {ethanol {95%}} (#1# pH 7.5 <4>) <4,7>".to_string()
+                ),
+                ("///", String::new()),
+            ],
+            lines
+        );
+        let entry = entry(&lines[1].1).unwrap();
+        assert_eq!(vec![1, 2], entry.proteins);
+        assert_eq!(0.5, entry.value);
+        assert_eq!("ethanol {95%}", entry.substrate);
+        assert_eq!(vec![4, 7], entry.references);
+        assert!(super::entry("#1# -999 {ethanol} <1>").is_none());
+        assert_eq!(
+            Some((3, "Homo sapiens".to_string())),
+            organism("#3# Homo sapiens P07327 UniProt <1,2>")
+        );
+    }
+
+    #[test]
+    fn t_estimate() {
+        let measurement = |value| Measurement {
+            value,
+            organisms: vec!["Homo sapiens".into()],
+            references: vec![1],
+        };
+        let estimate = Estimate::new(vec![measurement(4.), measurement(1.), measurement(2.)]);
+        assert_eq!(2., estimate.value);
+        let estimate = Estimate::new(vec![measurement(4.), measurement(1.)]);
+        assert_eq!(2.5, estimate.value);
+        assert_eq!(
+            "Km = 2.5 mM, median of 4 mM in Homo sapiens <1>; 1 mM in Homo sapiens <1>",
+            estimate.describe(Constant::Km)
+        );
+    }
+
+    #[test]
+    fn t_brenda() {
+        let database = Database::parse(include_str!("./test_brenda.txt")).unwrap();
+        let model = "E1 : s -> i | brenda(1.1.1.1, Ethanol);\n\
+                     E2 : s -> i | brenda(1.1.1.1, ethanol, \"Homo sapiens\");\n\
+                     init(E1) = 10;\ninit(E2) = 10;\ninit(s) = 100;";
+        let mut ast = Ast::parse(model.into()).unwrap().content;
+        let reaction = |ast: &Ast, i: usize| match &ast.0[i] {
+            Expression::Reaction(r) => (r.km.value, r.kcat.value),
+            e => panic!("{e:?}"),
+        };
+        assert!(reaction(&ast, 0).0.is_nan());
+        let unfilled = |ast: &Ast| {
+            check(ast, &Settings::default())
+                .iter()
+                .filter(|d| d.message.contains("no BRENDA file was given"))
+                .count()
+        };
+        assert_eq!(2, unfilled(&ast));
+        assert_eq!(
+            model.lines().next(),
+            printer::print(&ast, Language::French).lines().next()
+        );
+
+        assert!(database.fill(&mut ast, None).is_empty());
+        assert_eq!(0, unfilled(&ast));
+        // The median of 0.2, 17 and the middle of 0.5-0.7 mM.
+        assert_eq!((600., 4.2), reaction(&ast, 0));
+        assert_eq!((200., 3.5), reaction(&ast, 1));
+        let (reaction, provenance) = provenances(&ast).nth(1).unwrap();
+        assert_eq!("E2", reaction.enzhym);
+        assert_eq!(
+            "Km = 0.2 mM in Homo sapiens <1>\nkcat = 3.5 /s in Homo sapiens <1>",
+            provenance.to_string()
+        );
+        let (_, provenance) = provenances(&ast).next().unwrap();
+        assert_eq!(vec![4], provenance.km.measurements[2].references);
+        assert!(provenance.to_string().contains("kcat = 4.2 /s, median of"));
+        let json = Format::Json.write(&ast).unwrap();
+        let mut read: Ast = Format::Json.read(&json, 0).unwrap();
+        read.resolve(&[]).unwrap();
+        assert_eq!(ast.without_spans(), read.without_spans());
+
+        let mut ast = Ast::parse("E1 : s -> i | brenda(1.1.1.1, ethanol);".into())
+            .unwrap()
+            .content;
+        let diagnostics = database.fill(&mut ast, Some("Mus musculus"));
+        assert_eq!(2, diagnostics.len());
+        assert_eq!(
+            "BRENDA has no Km of EC 1.1.1.1 for `ethanol` in Mus musculus",
+            diagnostics[0].message
+        );
+        assert_eq!(
+            vec!["it has some in: Equus caballus, Homo sapiens, Saccharomyces cerevisiae"],
+            diagnostics[0].notes
+        );
+        assert!(database.fill(&mut ast, Some("equus caballus")).is_empty());
+
+        let mut ast = Ast::parse(
+            "HK : glc -> g6p | brenda(2.7.1.1, \"D-glucose\");\nE : s -> p | brenda(9.9.9.9, s);"
+                .into(),
+        )
+        .unwrap()
+        .content;
+        let diagnostics = database.fill(&mut ast, None);
+        let messages = diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "BRENDA has no kcat of EC 2.7.1.1 for `D-glucose`",
+                "EC 9.9.9.9 is not in the BRENDA file"
+            ],
+            messages
+        );
+
+        assert!(Reaction::parse("E : s -> p | brenda(1.1, s);".into()).is_err());
+        assert!(Reaction::parse("E : s -> p | brenda(1.1.1.1);".into()).is_err());
+        assert!(Database::parse("not a BRENDA file").is_err());
+    }
+}
@@ -1,19 +1,23 @@
 use crate::{
-    between_spaces, identifier, literal, natural_number, nothing, parser_combinator::Parser,
+    anychar, between_spaces, delimited, eof, expecting,
+    formula::Value,
+    identifier,
+    keywords::Keyword,
+    literal, natural_number,
+    parser_combinator::{quoted_string, Parser},
     real_number,
+    units::{quantity, Dimension},
+    Speed,
 };
 
 // End of File
 
 pub fn parse_eof<'a>() -> impl Parser<'a, ()> {
-    between_spaces(literal("").map(|_| ()))
+    between_spaces(eof)
 }
 
 // Separators
 
-pub fn parse_uN<'a>() -> impl Parser<'a, &'static str> {
-    between_spaces(literal("uN"))
-}
 pub fn parse_lparen<'a>() -> impl Parser<'a, &'static str> {
     between_spaces(literal("("))
 }
@@ -30,6 +34,18 @@ pub fn parse_arrow<'a>() -> impl Parser<'a, &'static str> {
     between_spaces(literal("->"))
 }
 
+pub fn parse_reversible_arrow<'a>() -> impl Parser<'a, &'static str> {
+    between_spaces(literal("<->"))
+}
+
+pub fn parse_comma<'a>() -> impl Parser<'a, &'static str> {
+    between_spaces(literal(","))
+}
+
+pub fn parse_at<'a>() -> impl Parser<'a, &'static str> {
+    between_spaces(literal("@"))
+}
+
 pub fn parse_pipe<'a>() -> impl Parser<'a, &'static str> {
     between_spaces(literal("|"))
 }
@@ -42,6 +58,22 @@ pub fn parse_dash<'a>() -> impl Parser<'a, &'static str> {
     between_spaces(literal("-"))
 }
 
+pub fn parse_lbrace<'a>() -> impl Parser<'a, &'static str> {
+    between_spaces(literal("{"))
+}
+
+pub fn parse_lbracket<'a>() -> impl Parser<'a, &'static str> {
+    between_spaces(literal("["))
+}
+
+pub fn parse_rbracket<'a>() -> impl Parser<'a, &'static str> {
+    between_spaces(literal("]"))
+}
+
+pub fn parse_rbrace<'a>() -> impl Parser<'a, &'static str> {
+    between_spaces(literal("}"))
+}
+
 pub fn parse_equal<'a>() -> impl Parser<'a, &'static str> {
     between_spaces(literal("="))
 }
@@ -56,27 +88,91 @@ pub fn parse_identifier<'a>() -> impl Parser<'a, String> {
     between_spaces(identifier())
 }
 
-pub fn parse_solubes_and_results<'a>() -> impl Parser<'a, Vec<String>> {
-    identifier()
-        .chain(parse_plus().skip_me(nothing()))
-        .map(|(x, _)| x)
-        .zero_or_more()
-        .chain(identifier())
-        .map(|(mut res, v)| {
-            res.push(v);
-            res
+pub fn parse_string<'a>() -> impl Parser<'a, String> {
+    between_spaces(expecting(quoted_string(), "string")).map(str::to_string)
+}
+
+/// A species, followed by the compartment it is in when it is in one: `s`, or `s[nucleus]`
+/// which is its name.
+pub fn parse_species_identifier<'a>() -> impl Parser<'a, String> {
+    parse_identifier()
+        .chain(delimited(parse_lbracket(), parse_identifier(), parse_rbracket()).optional())
+        .map(|(species, compartment)| match compartment {
+            Some(compartment) => format!("{species}[{compartment}]"),
+            None => species,
         })
 }
 
+pub fn parse_stoichiometric_identifier<'a>() -> impl Parser<'a, (String, u32)> {
+    parse_uint()
+        .predicate(|n| *n > 0, "Stoichiometric coefficient must be positive")
+        .chain(parse_species_identifier())
+        .map(|(n, identifier)| (identifier, n))
+        .or_else(parse_species_identifier().map(|identifier| (identifier, 1)))
+}
+
+pub fn parse_solubes_and_results<'a>() -> impl Parser<'a, Vec<(String, u32)>> {
+    parse_stoichiometric_identifier().sep_by1(parse_plus())
+}
+
+/// A reaction side, or `0` when nothing is consumed or produced.
+pub fn parse_species_or_nothing<'a>() -> impl Parser<'a, Vec<(String, u32)>> {
+    parse_solubes_and_results().or_else(between_spaces(literal("0")).map(|_| vec![]))
+}
+
 // Keywords
+// Those of the declarations about species have several spellings, see `keywords::ALIASES`.
 pub fn parse_init<'a>() -> impl Parser<'a, ()> {
-    between_spaces(literal("init")).map(|_| ())
+    Keyword::Init.parser()
 }
-pub fn parse_speed<'a>() -> impl Parser<'a, ()> {
-    between_spaces(literal("vitesse")).map(|_| ())
+/// `vitesse` and its aliases, or `diffusion`.
+pub fn parse_speed<'a>() -> impl Parser<'a, Keyword> {
+    Keyword::Speed
+        .parser()
+        .map(|_| Keyword::Speed)
+        .or_else(Keyword::Diffusion.parser().map(|_| Keyword::Diffusion))
 }
 pub fn parse_diameter<'a>() -> impl Parser<'a, ()> {
-    between_spaces(literal("diametre")).map(|_| ())
+    Keyword::Diameter.parser()
+}
+pub fn parse_k<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("k")).map(|_| ())
+}
+pub fn parse_keq<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("keq")).map(|_| ())
+}
+pub fn parse_param<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("param")).map(|_| ())
+}
+pub fn parse_include<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("include")).map(|_| ())
+}
+pub fn parse_simulation<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("simulation")).map(|_| ())
+}
+pub fn parse_compartment<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("compartment")).map(|_| ())
+}
+pub fn parse_inhibited_by<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("inhibited_by")).map(|_| ())
+}
+pub fn parse_activated_by<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("activated_by")).map(|_| ())
+}
+pub fn parse_ki<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("Ki")).map(|_| ())
+}
+pub fn parse_ka<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("Ka")).map(|_| ())
+}
+pub fn parse_alpha<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("alpha")).map(|_| ())
+}
+pub fn parse_beta<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("beta")).map(|_| ())
+}
+pub fn parse_brenda<'a>() -> impl Parser<'a, ()> {
+    between_spaces(literal("brenda")).map(|_| ())
 }
 
 // Numbers
@@ -84,10 +180,98 @@ pub fn parse_float<'a>() -> impl Parser<'a, f32> {
     between_spaces(real_number())
 }
 
-pub fn parse_float_ranged<'a>(a: f32, b: f32) -> impl Parser<'a, f32> {
-    parse_float().predicate(move |x| *x >= a && *x <= b, "Value out of range")
+/// A dimensionless formula.
+pub fn parse_number<'a>() -> impl Parser<'a, Value> {
+    quantity(&[], true).map(|(x, _)| x)
+}
+
+/// Michaelis constant, in µM. It is followed by the `-` separating it from kcat, so a
+/// subtraction must be written in parentheses.
+pub fn parse_concentration<'a>() -> impl Parser<'a, Value> {
+    quantity(&[Dimension::Concentration], false).map(|(x, _)| x)
+}
+
+/// Turnover number, in s⁻¹.
+pub fn parse_rate<'a>() -> impl Parser<'a, Value> {
+    quantity(&[Dimension::Rate], true).map(|(x, _)| x)
+}
+
+/// Duration, in s.
+pub fn parse_time<'a>() -> impl Parser<'a, Value> {
+    quantity(&[Dimension::Time], true)
+        .map(|(x, _)| x)
+        .predicate(
+            |x| x.value.is_nan() || x.value > 0.,
+            "Duration must be positive",
+        )
+}
+
+/// Time since the start of the simulation, in s.
+pub fn parse_instant<'a>() -> impl Parser<'a, Value> {
+    quantity(&[Dimension::Time], true)
+        .map(|(x, _)| x)
+        .predicate(
+            |x| x.value.is_nan() || x.value >= 0.,
+            "Time must not be negative",
+        )
+}
+
+/// Diameter, in nm.
+pub fn parse_length<'a>() -> impl Parser<'a, Value> {
+    quantity(&[Dimension::Length], true)
+        .map(|(x, _)| x)
+        // Formulas referring to parameters are only evaluated once the whole model is read.
+        .predicate(
+            |x| x.value.is_nan() || x.value > 0.,
+            "Length must be positive",
+        )
+}
+
+/// Volume of a compartment, in µm³.
+pub fn parse_volume<'a>() -> impl Parser<'a, Value> {
+    quantity(&[Dimension::Volume], true)
+        .map(|(x, _)| x)
+        .predicate(
+            |x| x.value.is_nan() || x.value > 0.,
+            "Volume must be positive",
+        )
+}
+
+/// Either a distance travelled per step (bare number or length unit) or a diffusion
+/// coefficient (µm²/s).
+pub fn parse_motion<'a>() -> impl Parser<'a, Speed> {
+    quantity(&[Dimension::Length, Dimension::Diffusion], true).map(|(x, dimension)| match dimension
+    {
+        Some(Dimension::Diffusion) => Speed::Diffusion(x),
+        _ => Speed::Step(x),
+    })
+}
+
+/// A diffusion coefficient, in µm²/s.
+pub fn parse_diffusion<'a>() -> impl Parser<'a, Speed> {
+    quantity(&[Dimension::Diffusion], true).map(|(x, _)| Speed::Diffusion(x))
+}
+
+/// EC number of an enzyme, four numbers separated by dots such as `1.1.1.1`.
+pub fn parse_ec_number<'a>() -> impl Parser<'a, String> {
+    between_spaces(expecting(
+        anychar
+            .predicate(|c| c.is_ascii_digit() || *c == '.', "Expected EC number")
+            .one_or_more()
+            .map(|chars| chars.into_iter().collect::<String>())
+            .predicate(
+                |ec| ec.split('.').count() == 4 && ec.split('.').all(|n| !n.is_empty()),
+                "Expected EC number",
+            ),
+        "EC number",
+    ))
 }
 
 pub fn parse_uint<'a>() -> impl Parser<'a, u32> {
     between_spaces(natural_number())
 }
+
+/// A number of molecules.
+pub fn parse_count<'a>() -> impl Parser<'a, u64> {
+    between_spaces(natural_number())
+}
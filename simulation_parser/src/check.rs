@@ -0,0 +1,672 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compartments::{compartment_of, Compartments};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::events::{Action, Trigger};
+use crate::include::declared_property;
+use crate::kinetics::{binding_probabilities, calc_probability};
+use crate::settings::{Settings, HASH_CELL_SIZE};
+use crate::sweep::SweepValues;
+use crate::thermodynamics::haldane_warnings;
+use crate::{Ast, Expression, Mechanism, Reaction};
+
+/// Finds the mistakes a model can be parsed with but is unlikely to be meant with, simulated
+/// with `settings`. Errors are models the simulations cannot run correctly, warnings are
+/// declarations or reactions that have no effect. Diagnostics are sorted by position.
+pub fn check(ast: &Ast, settings: &Settings) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    diagnostics.extend(duplicates(ast, settings.dt));
+    diagnostics.extend(unused_declarations(ast));
+    diagnostics.extend(never_produced(ast));
+    diagnostics.extend(negative_constants(ast));
+    diagnostics.extend(probabilities(ast, settings));
+    diagnostics.extend(substrate_inhibitions(ast));
+    diagnostics.extend(diameters(ast));
+    diagnostics.extend(brenda_lookups(ast));
+    diagnostics.extend(compartment_errors(ast, settings));
+    diagnostics.extend(late_events(ast, settings));
+    diagnostics.extend(sweep_errors(ast));
+    diagnostics.extend(haldane_warnings(ast));
+    diagnostics.sort_by_key(|d| (d.span.file, d.span.start));
+    diagnostics
+}
+
+/// A conversion some reaction performs: `enzyme + substrates -> enzyme + products` for an
+/// enzymatic reaction, one per direction and activated enzyme, along with the formation of
+/// its complexes and the binding of its inhibitors and activators.
+struct Conversion {
+    consumed: Vec<String>,
+    produced: Vec<String>,
+    span: Span,
+}
+
+fn names(species: &[(String, u32)]) -> Vec<String> {
+    species
+        .iter()
+        .map(|(identifier, _)| identifier.clone())
+        .collect()
+}
+
+fn conversions(Ast(expressions): &Ast) -> Vec<Conversion> {
+    let mut conversions = vec![];
+    for expression in expressions {
+        match expression {
+            Expression::Reaction(r) => {
+                for c in r.catalysed() {
+                    let mut consumed = names(&c.solubes);
+                    let mut produced = names(&c.results);
+                    consumed.push(c.enzhym.clone());
+                    produced.push(c.enzhym.clone());
+                    // The complexes inhibitors may bind, formed on the way.
+                    let bound = c.solube_units().count();
+                    conversions.push(Conversion {
+                        consumed: consumed.clone(),
+                        produced: (1..=bound).map(|i| c.complex(i)).collect(),
+                        span: r.span,
+                    });
+                    conversions.push(Conversion {
+                        consumed,
+                        produced,
+                        span: r.span,
+                    });
+                }
+                for b in r.bindings() {
+                    conversions.push(Conversion {
+                        consumed: vec![b.site.clone(), b.modifier.clone()],
+                        produced: vec![b.complex.clone()],
+                        span: r.span,
+                    });
+                    conversions.push(Conversion {
+                        consumed: vec![b.complex],
+                        produced: vec![b.site, b.modifier],
+                        span: r.span,
+                    });
+                }
+            }
+            Expression::MassAction(r) => conversions.push(Conversion {
+                consumed: names(&r.reactants),
+                produced: names(&r.products),
+                span: r.span,
+            }),
+            _ => (),
+        }
+    }
+    conversions
+}
+
+/// Names of the species reactions take part in, enzyme-substrate complexes included.
+fn reacting_species(ast: &Ast) -> HashSet<String> {
+    conversions(ast)
+        .iter()
+        .flat_map(|c| c.consumed.iter().chain(&c.produced))
+        .cloned()
+        .collect()
+}
+
+/// Declarations given more than once, which [`Ast::load`] reports across files but a lone
+/// model may still contain: the last one is used.
+fn duplicates(Ast(expressions): &Ast, dt: f32) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    let mut declared: HashMap<_, (f64, Span)> = HashMap::new();
+    let mut params = HashSet::new();
+    for expression in expressions {
+        if let Expression::Param(p) = expression {
+            if !params.insert(&p.identifier) {
+                diagnostics.push(
+                    Diagnostic::warning(
+                        format!("parameter `{}` is declared twice", p.identifier),
+                        p.span,
+                    )
+                    .with_note("formulas after this declaration use its value".into()),
+                );
+            }
+        }
+        let Some((keyword, identifier, value)) = declared_property(expression, dt) else {
+            continue;
+        };
+        let span = expression.span();
+        match declared.insert((keyword, identifier), (value, span)) {
+            Some((first, _)) if first == value => diagnostics.push(
+                Diagnostic::warning(format!("`{keyword}({identifier})` is declared twice"), span)
+                    .with_note("the declarations are identical".into()),
+            ),
+            Some(_) => diagnostics.push(
+                Diagnostic::error(
+                    format!("conflicting declarations of `{keyword}({identifier})`"),
+                    span,
+                )
+                .with_note("the earlier declaration is ignored".into()),
+            ),
+            None => (),
+        }
+    }
+    diagnostics
+}
+
+/// Declarations of species no reaction involves, such as the speed of a misspelt species.
+fn unused_declarations(ast: &Ast) -> Vec<Diagnostic> {
+    let species = reacting_species(ast);
+    ast.0
+        .iter()
+        .filter_map(|e| match e {
+            Expression::InitDeclaration(i) => Some(("init", &i.identifier, i.span)),
+            Expression::SpeedDeclaration(s) => Some(("vitesse", &s.identifier, s.span)),
+            Expression::DiameterDeclaration(d) => Some(("diametre", &d.identifier, d.span)),
+            _ => None,
+        })
+        .filter(|(_, identifier, _)| !species.contains(*identifier))
+        .map(|(keyword, identifier, span)| {
+            Diagnostic::warning(
+                format!("`{identifier}` is neither produced nor consumed by any reaction"),
+                span,
+            )
+            .with_note(format!(
+                "`{keyword}({identifier})` has no effect on the simulation"
+            ))
+        })
+        .collect()
+}
+
+/// Species some reaction consumes but that are never present: they have no initial
+/// molecules, no event adds any, and no reaction that could happen produces them.
+fn never_produced(ast: &Ast) -> Vec<Diagnostic> {
+    let conversions = conversions(ast);
+    let mut present = ast
+        .0
+        .iter()
+        .flat_map(|e| match e {
+            Expression::InitDeclaration(i) if i.number > 0 => vec![i.identifier.as_str()],
+            Expression::Event(e) => e
+                .actions
+                .iter()
+                .filter(|a| a.apply(0) > 0)
+                .map(Action::species)
+                .collect(),
+            _ => vec![],
+        })
+        .collect::<HashSet<_>>();
+    let mut possible = vec![false; conversions.len()];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (c, possible) in conversions.iter().zip(&mut possible) {
+            if !*possible && c.consumed.iter().all(|s| present.contains(s.as_str())) {
+                *possible = true;
+                changed = true;
+                present.extend(c.produced.iter().map(String::as_str));
+            }
+        }
+    }
+
+    let mut reported = HashSet::new();
+    let mut diagnostics = vec![];
+    for c in &conversions {
+        for species in &c.consumed {
+            if present.contains(species.as_str()) || !reported.insert(species) {
+                continue;
+            }
+            let produced = conversions.iter().any(|c| c.produced.contains(species));
+            diagnostics.push(
+                Diagnostic::warning(format!("`{species}` is never produced"), c.span).with_note(
+                    if produced {
+                        format!("`{species}` has no initial molecules and the reactions producing it can never happen")
+                    } else {
+                        format!("`{species}` has no initial molecules and no reaction produces it")
+                    },
+                ),
+            );
+        }
+    }
+    diagnostics
+}
+
+fn probability_errors(r: &Reaction, dt: f32) -> Vec<Diagnostic> {
+    let (p1, p2, p3) = calc_probability(r.km.value, r.kcat.value, dt);
+    [
+        ("binding", "P1", p1),
+        ("unbinding", "P2", p2),
+        ("catalysis", "P3", p3),
+    ]
+    .into_iter()
+    .filter(|(_, _, p)| !(0. ..=1.).contains(p))
+    .map(|(event, name, p)| {
+        Diagnostic::error(
+            format!(
+                "{event} probability of {} on {} is {p}, outside [0, 1]",
+                r.enzhym,
+                r.solube_units()
+                    .map(String::as_str)
+                    .collect::<Vec<_>>()
+                    .join(" + ")
+            ),
+            r.span,
+        )
+        .with_note(format!(
+            "{name} is computed from Km = {} µM and kcat = {} s⁻¹ for steps of {dt} s",
+            r.km.value, r.kcat.value
+        ))
+    })
+    .collect()
+}
+
+/// Inhibitors and activators whose dissociation constants do not give an unbinding
+/// probability with the step of `settings`.
+fn binding_probability_errors(r: &Reaction, settings: &Settings) -> Vec<Diagnostic> {
+    r.bindings()
+        .into_iter()
+        .filter_map(|b| {
+            let (_, p) = binding_probabilities(b.k, r.km.value, r.kcat.value, settings);
+            (!(0. ..=1.).contains(&p)).then(|| {
+                Diagnostic::error(
+                    format!(
+                        "unbinding probability of {} from {} is {p}, outside [0, 1]",
+                        b.modifier, b.site
+                    ),
+                    r.span,
+                )
+                .with_note(format!(
+                    "it is computed from a dissociation constant of {} µM for steps of {} s",
+                    b.k, settings.dt
+                ))
+            })
+        })
+        .collect()
+}
+
+/// The Km and kcat of `r` in both directions, with their names and units.
+fn constants(r: &Reaction) -> Vec<(&'static str, f32, &'static str)> {
+    let mut constants = vec![("Km", r.km.value, "µM"), ("kcat", r.kcat.value, "s⁻¹")];
+    if let Some(reverse) = &r.reverse {
+        constants.push(("reverse Km", reverse.km.value, "µM"));
+        constants.push(("reverse kcat", reverse.kcat.value, "s⁻¹"));
+    }
+    constants
+}
+
+/// Kinetic constants below zero, which no enzyme has.
+fn negative_constants(Ast(expressions): &Ast) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for expression in expressions {
+        if let Expression::Reaction(r) = expression {
+            for (name, value, unit) in constants(r) {
+                if value < 0. {
+                    diagnostics.push(Diagnostic::error(
+                        format!("{name} of `{}` is {value} {unit}, below zero", r.enzhym),
+                        r.span,
+                    ));
+                }
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Enzymatic reactions whose kinetics do not give probabilities with the step of
+/// `settings`.
+fn probabilities(Ast(expressions): &Ast, settings: &Settings) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for expression in expressions {
+        if let Expression::Reaction(r) = expression {
+            if r.awaits_brenda() {
+                // Reported by `brenda_lookups`.
+                continue;
+            }
+            if constants(r).iter().any(|&(_, value, _)| value < 0.) {
+                // Reported by `negative_constants`.
+                continue;
+            }
+            for c in r.catalysed() {
+                diagnostics.extend(probability_errors(&c, settings.dt));
+            }
+            diagnostics.extend(binding_probability_errors(r, settings));
+        }
+    }
+    diagnostics
+}
+
+/// Molecules larger than a cell of the spatial hash, whose collisions with molecules of
+/// neighbouring cells the entity-centered simulation misses.
+fn diameters(Ast(expressions): &Ast) -> Vec<Diagnostic> {
+    expressions
+        .iter()
+        .filter_map(|e| match e {
+            Expression::DiameterDeclaration(d) if d.diameter.value > HASH_CELL_SIZE => Some(
+                Diagnostic::warning(
+                    format!(
+                        "`{}` is {} nm wide, larger than the {HASH_CELL_SIZE} nm cells collisions are detected in",
+                        d.identifier, d.diameter.value
+                    ),
+                    d.span,
+                )
+                .with_note(
+                    "the entity-centered simulation misses its collisions with molecules of neighbouring cells".into(),
+                ),
+            ),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reactions whose Km and kcat come from BRENDA but were not looked up, which the
+/// simulations cannot run.
+fn brenda_lookups(Ast(expressions): &Ast) -> Vec<Diagnostic> {
+    expressions
+        .iter()
+        .filter_map(|e| match e {
+            Expression::Reaction(r) if r.awaits_brenda() => Some(
+                Diagnostic::error(
+                    format!(
+                        "the Km and kcat of `{}` come from BRENDA, but no BRENDA file was given",
+                        r.enzhym
+                    ),
+                    r.span,
+                )
+                .with_note("download the text file of BRENDA and pass it with `--brenda`".into()),
+            ),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Substrate inhibitions by species that are not substrates of the reaction, which are
+/// uncompetitive inhibitions written the wrong way.
+fn substrate_inhibitions(Ast(expressions): &Ast) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for expression in expressions {
+        let Expression::Reaction(r) = expression else {
+            continue;
+        };
+        for m in &r.modifiers {
+            if matches!(m.mechanism, Mechanism::Substrate { .. })
+                && !r.solubes.iter().any(|(s, _)| *s == m.species)
+            {
+                diagnostics.push(
+                    Diagnostic::error(
+                        format!(
+                            "substrate inhibition of `{}` by `{}`, which is not one of its substrates",
+                            r.enzhym, m.species
+                        ),
+                        r.span,
+                    )
+                    .with_note("write `uncompetitive` for inhibitors binding the enzyme-substrate complex".into()),
+                );
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Compartments the simulations cannot nest in the sphere of `settings`, species of
+/// undeclared compartments, and molecules of different compartments reacting together,
+/// which never meet.
+fn compartment_errors(ast: &Ast, settings: &Settings) -> Vec<Diagnostic> {
+    let compartments = Compartments::new(ast, settings);
+    let mut diagnostics = vec![];
+    let mut declared = HashSet::new();
+    for expression in &ast.0 {
+        let Expression::Compartment(c) = expression else {
+            continue;
+        };
+        if !declared.insert(&c.identifier) {
+            diagnostics.push(
+                Diagnostic::error(
+                    format!("compartment `{}` is declared twice", c.identifier),
+                    c.span,
+                )
+                .with_note("the earlier declaration is ignored".into()),
+            );
+        }
+        let Some(inside) = &c.inside else {
+            continue;
+        };
+        let ancestors = compartments.ancestors(&c.identifier);
+        let outermost = ancestors.last().copied().unwrap_or(inside);
+        if compartments.get(inside).is_none() {
+            diagnostics.push(Diagnostic::error(
+                format!(
+                    "compartment `{}` is inside `{inside}`, which is not declared",
+                    c.identifier
+                ),
+                c.span,
+            ));
+        } else if compartments
+            .get(outermost)
+            .and_then(|o| o.inside.as_deref())
+            == Some(&c.identifier)
+        {
+            let diagnostic = Diagnostic::error(
+                format!("compartment `{}` is inside itself", c.identifier),
+                c.span,
+            );
+            diagnostics.push(match ancestors.is_empty() {
+                true => diagnostic,
+                false => diagnostic.with_note(format!(
+                    "it is inside `{}`, which is inside `{}`",
+                    ancestors.join("`, inside `"),
+                    c.identifier
+                )),
+            });
+        }
+    }
+    let top = compartments.nested_in(None);
+    let filled = top
+        .iter()
+        .map(|c| compartments.enclosed(&c.identifier))
+        .sum::<f32>();
+    if let Some(first) = top.first() {
+        if filled >= compartments.sphere() {
+            diagnostics.push(
+                Diagnostic::error(
+                    format!(
+                        "the compartments take {} µm³, more than the {} µm³ of the simulated sphere",
+                        filled / 1e9,
+                        compartments.sphere() / 1e9
+                    ),
+                    first.span,
+                )
+                .with_note(format!(
+                    "the sphere has a radius of {} nm, which the `radius` of the `simulation` block sets",
+                    settings.radius
+                )),
+            );
+        }
+    }
+
+    let conversions = conversions(ast);
+    let declarations = ast.0.iter().flat_map(|e| match e {
+        Expression::InitDeclaration(i) => vec![(&i.identifier, i.span)],
+        Expression::SpeedDeclaration(s) => vec![(&s.identifier, s.span)],
+        Expression::DiameterDeclaration(d) => vec![(&d.identifier, d.span)],
+        Expression::Event(e) => e.species().map(|s| (s, e.span)).collect(),
+        _ => vec![],
+    });
+    let mut reported = HashSet::new();
+    for (species, span) in conversions
+        .iter()
+        .flat_map(|c| c.consumed.iter().chain(&c.produced).map(|s| (s, c.span)))
+        .chain(declarations)
+    {
+        let Some(compartment) = compartment_of(species) else {
+            continue;
+        };
+        if compartments.get(compartment).is_none() && reported.insert(species) {
+            diagnostics.push(Diagnostic::error(
+                format!("`{species}` is in compartment `{compartment}`, which is not declared"),
+                span,
+            ));
+        }
+    }
+    let mut reported = HashSet::new();
+    for c in &conversions {
+        let Some((first, other)) = c.consumed.iter().find_map(|a| {
+            c.consumed
+                .iter()
+                .find(|b| compartment_of(a) != compartment_of(b))
+                .map(|b| (a, b))
+        }) else {
+            continue;
+        };
+        if reported.insert((c.span.file, c.span.start)) {
+            diagnostics.push(
+                Diagnostic::error(
+                    format!(
+                        "`{first}` and `{other}` react together but are in different compartments"
+                    ),
+                    c.span,
+                )
+                .with_note("molecules only meet those of their own compartment".into()),
+            );
+        }
+    }
+    diagnostics
+}
+
+/// Events timed when the simulation has already ended, which never fire.
+fn late_events(Ast(expressions): &Ast, settings: &Settings) -> Vec<Diagnostic> {
+    expressions
+        .iter()
+        .filter_map(|e| match e {
+            Expression::Event(e) => match &e.trigger {
+                Trigger::At(time) if time.value >= settings.end_time => Some((time.value, e.span)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .map(|(time, span)| {
+            Diagnostic::warning(
+                format!(
+                    "this event happens at {time} s, once the simulation has ended at {} s",
+                    settings.end_time
+                ),
+                span,
+            )
+            .with_note(
+                "the `end_time` of the `simulation` block sets when the simulation ends".into(),
+            )
+        })
+        .collect()
+}
+
+/// Sweeps of parameters the model does not declare, and ranges without values.
+fn sweep_errors(ast: &Ast) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for expression in &ast.0 {
+        let Expression::Sweep(sweep) = expression else {
+            continue;
+        };
+        if ast.param(&sweep.identifier).is_none() {
+            diagnostics.push(
+                Diagnostic::error(
+                    format!("`{}` is swept but is not a parameter", sweep.identifier),
+                    sweep.span,
+                )
+                .with_note(format!(
+                    "declare it with `param {} = ...;`",
+                    sweep.identifier
+                )),
+            );
+        }
+        if let SweepValues::Range { from, to, step } = &sweep.values {
+            if step.value <= 0. {
+                diagnostics.push(Diagnostic::error(
+                    format!(
+                        "the step of the sweep is {}, but must be positive",
+                        step.value
+                    ),
+                    sweep.span,
+                ));
+            } else if to.value < from.value {
+                diagnostics.push(Diagnostic::error(
+                    format!(
+                        "the sweep goes from {} to {}, which is smaller",
+                        from.value, to.value
+                    ),
+                    sweep.span,
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diagnostic::Severity;
+    use crate::Parsable;
+
+    #[test]
+    fn t_probabilities() {
+        let reaction = Reaction::parse("E : s -> p | 10 uM - 20000;".into())
+            .unwrap()
+            .content;
+        let errors = probability_errors(&reaction, 1e-4);
+        assert_eq!(
+            vec!["catalysis probability of E on s is 2, outside [0, 1]"],
+            errors
+                .iter()
+                .map(|d| d.message.as_str())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            "P3 is computed from Km = 10 µM and kcat = 20000 s⁻¹ for steps of 0.0001 s",
+            errors[0].notes[0]
+        );
+        assert!(probability_errors(&reaction, 1e-5).is_empty());
+    }
+
+    #[test]
+    fn t_check() {
+        let file = "E1 : s -> i | 200 uM - 100;
+                    E2 : i -> p | 0.001 uM - 80;
+                    E3 : x -> p | 100 uM - 8;
+                    init(E1) = 30;
+                    init(E2) = 50;
+                    init(E3) = 50;
+                    init(s) = 2000;
+                    init(s) = 1000;
+                    vitesse(q) = 1;
+                    diametre(E1) = 20;
+                    param k = 1;
+                    param k = 2;";
+        let ast = Ast::parse(file.into()).unwrap().content;
+        let diagnostics = check(&ast, &Settings::default());
+        let messages = diagnostics
+            .iter()
+            .map(|d| (d.severity, d.message.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                (
+                    Severity::Error,
+                    "binding probability of E2 on i is 19.64133, outside [0, 1]"
+                ),
+                (Severity::Warning, "`x` is never produced"),
+                (Severity::Error, "conflicting declarations of `init(s)`"),
+                (
+                    Severity::Warning,
+                    "`q` is neither produced nor consumed by any reaction"
+                ),
+                (
+                    Severity::Warning,
+                    "`E1` is 20 nm wide, larger than the 10 nm cells collisions are detected in"
+                ),
+                (Severity::Warning, "parameter `k` is declared twice"),
+            ],
+            messages
+        );
+
+        let file = "E : s <-> p | 10 uM - 100, 10 uM - 20000;
+                    0 -> s @ k = 1 uM/s;
+                    init(E) = 10;";
+        let ast = Ast::parse(file.into()).unwrap().content;
+        let diagnostics = check(&ast, &Settings::default());
+        assert_eq!(
+            vec!["catalysis probability of E on p is 2, outside [0, 1]"],
+            diagnostics
+                .iter()
+                .map(|d| d.message.as_str())
+                .collect::<Vec<_>>()
+        );
+    }
+}
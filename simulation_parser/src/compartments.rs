@@ -0,0 +1,335 @@
+use serde::{Deserialize, Serialize};
+
+use crate::brenda_parser_helpers::*;
+use crate::diagnostic::Span;
+use crate::formula::Value;
+use crate::parser_combinator::*;
+use crate::settings::Settings;
+use crate::{Ast, Expression, Parsable};
+
+/// Number of nm³ in a µm³, the canonical unit of volumes.
+const NM3_PER_UM3: f32 = 1e9;
+
+/// `compartment nucleus { volume = 0.2 fL; inside = cytosol; }`
+///
+/// A species is put in a compartment by writing it after its name, `s[nucleus]`, and the
+/// species without one are in the simulated sphere, outside every compartment. Molecules
+/// only meet those of their own compartment; a reaction whose products are in another
+/// compartment than its reactants, such as `s[cytosol] -> s[nucleus] @ k = 2 /s;`,
+/// transports them.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct CompartmentDeclaration {
+    pub identifier: String,
+    /// Volume the species of the compartment move in, the compartments inside it left
+    /// out, in µm³.
+    pub volume: Value,
+    /// The compartment it is nested in, the simulated sphere when there is none.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inside: Option<String>,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+enum Setting {
+    Volume(Value),
+    Inside(String),
+}
+
+fn setting<'a>() -> impl Parser<'a, Setting> {
+    let named = |name: &'static str| between_spaces(literal(name)).skip_next(parse_equal());
+    named("volume")
+        .skip_me(parse_volume())
+        .map(Setting::Volume)
+        .or_else(
+            named("inside")
+                .skip_me(parse_identifier())
+                .map(Setting::Inside),
+        )
+        .skip_next(parse_semicolon())
+}
+
+impl Parsable for CompartmentDeclaration {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess { next_input, .. } = parse_compartment().parse(start)?;
+        let ParserSuccess {
+            content: identifier,
+            next_input,
+        } = parse_identifier().parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_lbrace().parse(next_input)?;
+        let (mut volume, mut inside) = (None, None);
+        let mut next_input = next_input;
+        while let Err(rbrace_error) = parse_rbrace().parse(next_input) {
+            let ParserSuccess {
+                content,
+                next_input: rest,
+            } = setting()
+                .parse(next_input)
+                .map_err(|e| e.merge(rbrace_error))?;
+            let ParserSuccess { next_input: at, .. } = space0().parse(next_input)?;
+            let duplicate = match content {
+                Setting::Volume(x) => volume.replace(x).is_some(),
+                Setting::Inside(x) => inside.replace(x).is_some(),
+            };
+            if duplicate {
+                return Err(at.generate_error("Setting already given".to_string()));
+            }
+            next_input = rest;
+        }
+        let Some(volume) = volume else {
+            return Err(
+                next_input.generate_error(format!("Compartment `{identifier}` has no volume"))
+            );
+        };
+        let ParserSuccess { next_input, .. } = parse_rbrace().parse(next_input)?;
+        Ok(ParserSuccess {
+            content: Self {
+                identifier,
+                volume,
+                inside,
+                span: next_input.span_from(start),
+            },
+            next_input,
+        })
+    }
+}
+
+/// The compartment `species` is in, which ends its name: `nucleus` for `s[nucleus]`, and
+/// for the complexes formed there such as `E1[nucleus]--s[nucleus]`.
+pub fn compartment_of(species: &str) -> Option<&str> {
+    let rest = species.strip_suffix(']')?;
+    rest.rfind('[').map(|open| &rest[open + 1..])
+}
+
+/// The compartments of a model, nested in the simulated sphere.
+#[derive(Debug, Clone)]
+pub struct Compartments {
+    declarations: Vec<CompartmentDeclaration>,
+    /// Volume of the simulated sphere, in nm³.
+    sphere: f32,
+}
+
+impl Compartments {
+    /// The compartments `ast` declares, in the sphere `settings` simulate. A compartment
+    /// declared twice has its last declaration.
+    pub fn new(Ast(expressions): &Ast, settings: &Settings) -> Self {
+        let mut declarations: Vec<CompartmentDeclaration> = vec![];
+        for expression in expressions {
+            if let Expression::Compartment(c) = expression {
+                declarations.retain(|d| d.identifier != c.identifier);
+                declarations.push(c.clone());
+            }
+        }
+        Self {
+            declarations,
+            sphere: settings.volume(),
+        }
+    }
+    pub fn get(&self, identifier: &str) -> Option<&CompartmentDeclaration> {
+        self.declarations
+            .iter()
+            .find(|d| d.identifier == identifier)
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &CompartmentDeclaration> {
+        self.declarations.iter()
+    }
+    /// The compartments `identifier` is nested in, innermost first, up to the first one
+    /// that is not declared or that repeats when they are nested in each other.
+    pub fn ancestors(&self, identifier: &str) -> Vec<&str> {
+        let mut ancestors = vec![];
+        let mut current = self.get(identifier);
+        while let Some(parent) = current.and_then(|c| c.inside.as_deref()) {
+            if parent == identifier || ancestors.contains(&parent) {
+                break;
+            }
+            ancestors.push(parent);
+            current = self.get(parent);
+        }
+        ancestors
+    }
+    /// The compartments directly inside `parent`, or inside the simulated sphere for
+    /// `None`, where those nested in an undeclared compartment are counted too.
+    pub fn nested_in(&self, parent: Option<&str>) -> Vec<&CompartmentDeclaration> {
+        self.declarations
+            .iter()
+            .filter(|d| match (parent, &d.inside) {
+                (Some(parent), Some(inside)) => parent == inside,
+                (None, Some(inside)) => self.get(inside).is_none(),
+                (_, None) => parent.is_none(),
+            })
+            .collect()
+    }
+    /// Volume enclosed by the membrane of `identifier`, its nested compartments included,
+    /// in nm³.
+    pub fn enclosed(&self, identifier: &str) -> f32 {
+        let own = self.get(identifier).map_or(0., |c| c.volume.value);
+        let nested = self
+            .declarations
+            .iter()
+            .filter(|d| d.identifier != identifier)
+            .filter(|d| self.ancestors(&d.identifier).contains(&identifier))
+            .map(|d| d.volume.value)
+            .sum::<f32>();
+        (own + nested) * NM3_PER_UM3
+    }
+    /// Volume the species of `compartment` move in, in nm³: that of the compartment, or
+    /// for `None` that of the simulated sphere around the compartments. Undeclared
+    /// compartments are taken to be the simulated sphere.
+    pub fn volume(&self, compartment: Option<&str>) -> f32 {
+        match compartment.and_then(|c| self.get(c)) {
+            Some(c) => c.volume.value * NM3_PER_UM3,
+            None => {
+                self.sphere
+                    - self
+                        .nested_in(None)
+                        .iter()
+                        .map(|c| self.enclosed(&c.identifier))
+                        .sum::<f32>()
+            }
+        }
+    }
+    /// Volume the molecules of `species` move in, in nm³, see [`Compartments::volume`].
+    pub fn volume_of(&self, species: &str) -> f32 {
+        self.volume(compartment_of(species))
+    }
+    /// Volume of the simulated sphere, in nm³.
+    pub fn sphere(&self) -> f32 {
+        self.sphere
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::check::check;
+    use crate::diagnostic::Severity;
+    use crate::keywords::Language;
+    use crate::{kinetics, printer, sbml, Reaction};
+
+    #[test]
+    fn t_nesting() {
+        let model = "compartment a { volume = 1 fL; }
+                     compartment b { volume = 2 fL; inside = a; }
+                     compartment c { volume = 3 fL; inside = b; }
+                     compartment d { volume = 4 fL; inside = golgi; }
+                     compartment e { volume = 5 fL; inside = f; }
+                     compartment f { volume = 6 fL; inside = e; }
+                     compartment a { volume = 7 fL; }";
+        let ast = Ast::parse(model.into()).unwrap().content;
+        let compartments = Compartments::new(&ast, &Settings::default());
+        assert_eq!(Some(7.), compartments.get("a").map(|a| a.volume.value));
+        assert_eq!(vec!["b", "a"], compartments.ancestors("c"));
+        assert_eq!(vec!["golgi"], compartments.ancestors("d"));
+        assert_eq!(vec!["f"], compartments.ancestors("e"));
+        let identifiers = |parent| {
+            compartments
+                .nested_in(parent)
+                .iter()
+                .map(|c| c.identifier.as_str())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(vec!["d", "a"], identifiers(None));
+        assert_eq!(vec!["c"], identifiers(Some("b")));
+        assert_eq!(12e9, compartments.enclosed("a"));
+        assert_eq!(2e9, compartments.volume(Some("b")));
+        assert_eq!(
+            compartments.volume(None),
+            compartments.volume(Some("golgi"))
+        );
+        assert_eq!(compartments.sphere() - 16e9, compartments.volume(None));
+    }
+
+    #[test]
+    fn t_compartments() {
+        let parse = |text: &str| CompartmentDeclaration::parse(text.into()).map(|s| s.content);
+        let nucleus = parse("compartment nucleus { inside = cytosol; volume = 0.5 um3; }").unwrap();
+        assert_eq!(
+            ("nucleus", 0.5, Some("cytosol")),
+            (
+                nucleus.identifier.as_str(),
+                nucleus.volume.value,
+                nucleus.inside.as_deref()
+            )
+        );
+        assert!(parse("compartment nucleus { inside = cytosol; }").is_err());
+        assert!(parse("compartment nucleus { volume = 1 fL; volume = 2 fL; }").is_err());
+        assert!(parse("compartment nucleus { volume = 1 nm; }").is_err());
+
+        let reaction =
+            Reaction::parse("E1[nucleus] : s[nucleus] -> s[ cytosol ] | 200 - 100;".into())
+                .unwrap()
+                .content;
+        assert_eq!(vec![("s[cytosol]".to_string(), 1)], reaction.results);
+        assert_eq!("E1[nucleus]--s[nucleus]", reaction.complex(1));
+        assert_eq!(Some("nucleus"), compartment_of(&reaction.complex(1)));
+        assert_eq!(Some("nucleus"), reaction.compartment());
+        assert_eq!(None, compartment_of("E1--s"));
+
+        let model = "simulation {
+    radius = 2 um;
+}
+
+compartment cytosol {
+    volume = 1 fL;
+}
+compartment nucleus {
+    volume = 0.5 fL;
+    inside = cytosol;
+}
+
+E1[nucleus] : s[nucleus] -> p[nucleus] | 200 - 100;
+
+s[cytosol] -> s[nucleus] @ k = 2 /s;
+
+init(E1[nucleus]) = 10;
+init(s[cytosol])  = 1000;
+";
+        let ast = Ast::parse(model.into()).unwrap().content;
+        assert_eq!(model, printer::print(&ast, Language::French));
+        let settings = ast.settings();
+        assert!(check(&ast, &settings).is_empty());
+        let compartments = Compartments::new(&ast, &settings);
+        assert_eq!(1.5e9, compartments.enclosed("cytosol"));
+        assert_eq!(1e9, compartments.volume_of("s[cytosol]"));
+        assert_eq!(settings.volume() - 1.5e9, compartments.volume_of("E1"));
+        // Rate constants in concentration units do not depend on the volume, but the
+        // molecules making a concentration do.
+        assert_eq!(
+            2. * kinetics::molecules_per_micromolar_in(5e8),
+            kinetics::molecules_per_micromolar_in(1e9)
+        );
+        assert!(
+            (kinetics::alpha_in(settings.volume()) - kinetics::alpha(&settings)).abs()
+                < 1e-6 * kinetics::alpha(&settings)
+        );
+        let sbml = sbml::export(&ast, &settings);
+        assert!(sbml.contains("<compartment id=\"nucleus\" spatialDimensions=\"3\" size=\"5e-16\""));
+        assert!(sbml.contains("name=\"s[nucleus]\" compartment=\"nucleus\""));
+
+        let model = "compartment cytosol { volume = 1 fL; inside = membrane; }
+compartment nucleus { volume = 1 fL; inside = nucleus; }
+E1 : s[cytosol] -> p | 200 - 100;
+s[golgi] -> 0 @ k = 1 /s;
+init(E1) = 10;
+init(s[cytosol]) = 10;
+init(s[golgi]) = 10;";
+        let ast = Ast::parse(model.into()).unwrap().content;
+        let messages = check(&ast, &Settings::default())
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "compartment `cytosol` is inside `membrane`, which is not declared",
+                "the compartments take 1 µm³, more than the 0.5235988 µm³ of the simulated sphere",
+                "compartment `nucleus` is inside itself",
+                "`s[cytosol]` and `E1` react together but are in different compartments",
+                "`s[golgi]` is in compartment `golgi`, which is not declared",
+            ],
+            messages
+        );
+    }
+}
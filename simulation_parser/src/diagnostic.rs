@@ -0,0 +1,195 @@
+use std::fmt::Display;
+
+/// Byte range of a node in the source it was parsed from.
+///
+/// See [`crate::Ast::without_spans`] to compare ASTs whatever their spans.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+    /// Index of the source file in [`crate::include::Sources`], 0 for a lone source.
+    pub file: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self {
+            start,
+            end,
+            file: 0,
+        }
+    }
+    /// Empty span at the 1-based `line` and `column` of `text`, as parsers of other formats
+    /// report positions.
+    pub fn at(text: &str, line: usize, column: usize, file: usize) -> Self {
+        let mut offset = text
+            .split_inclusive('\n')
+            .take(line.saturating_sub(1))
+            .map(str::len)
+            .sum::<usize>()
+            + column.saturating_sub(1);
+        offset = offset.min(text.len());
+        while !text.is_char_boundary(offset) {
+            offset -= 1;
+        }
+        Self {
+            start: offset,
+            end: offset,
+            file,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Span,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: String, span: Span) -> Self {
+        Self {
+            severity: Severity::Error,
+            message,
+            span,
+            notes: vec![],
+        }
+    }
+    pub fn warning(message: String, span: Span) -> Self {
+        Self {
+            severity: Severity::Warning,
+            message,
+            span,
+            notes: vec![],
+        }
+    }
+    pub fn with_note(mut self, note: String) -> Self {
+        self.notes.push(note);
+        self
+    }
+
+    /// Renders the diagnostic the way rustc does:
+    ///
+    /// ```text
+    /// error: expected `;`, found `x`
+    ///  --> model.txt:3:15
+    ///   |
+    /// 3 | init(E1) = 30 x
+    ///   |               ^
+    /// ```
+    pub fn render(&self, source: &str, path: &str) -> String {
+        let start = self.span.start.min(source.len());
+        let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let line = source[line_start..line_end].trim_end_matches('\r');
+        let line_number = source[..start].matches('\n').count() + 1;
+        let before = &source[line_start..start];
+        let underlined = source[start..self.span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        // Reuse tabs from the source line so that the carets stay aligned.
+        let padding = before
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let gutter = " ".repeat(line_number.to_string().len());
+
+        let mut rendered = format!(
+            "{}: {}\n{gutter}--> {}:{}:{}\n{gutter} |\n{} | {}\n{gutter} | {}{}",
+            self.severity,
+            self.message,
+            path,
+            line_number,
+            before.chars().count() + 1,
+            line_number,
+            line,
+            padding,
+            "^".repeat(underlined),
+        );
+        for note in &self.notes {
+            rendered.push_str(&format!("\n{gutter} = note: {}", note));
+        }
+        rendered
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Ast, Parsable};
+
+    #[test]
+    fn t_render() {
+        let source = "init(E1) = 30;\n\tvitesse(E1) = -1;\n";
+        let span = Span::at(source, 2, 16, 0);
+        assert_eq!(Span::new(30, 30), span);
+        assert_eq!(
+            Span::new(source.len(), source.len()),
+            Span::at(source, 9, 1, 0)
+        );
+        let warning = Diagnostic::warning("negative speed".into(), Span::new(30, 32))
+            .with_note("`E1` does not move".into());
+        assert_eq!(
+            "warning: negative speed
+ --> model.txt:2:16
+  |
+2 | \tvitesse(E1) = -1;
+  | \t              ^^
+  = note: `E1` does not move",
+            warning.render(source, "model.txt")
+        );
+    }
+
+    #[test]
+    fn t_spans() {
+        let file = "init(E1) = 30; // thirty\n  vitesse(E1) = 0.1;\n";
+        let Ast(expressions) = Ast::parse(file.into()).unwrap().content;
+        let spans = expressions
+            .iter()
+            .map(|e| &file[e.span().start..e.span().end])
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["init(E1) = 30;", "vitesse(E1) = 0.1;"], spans);
+    }
+
+    #[test]
+    fn t_diagnostic() {
+        let file = "init(E1) = 30;\n// comment\ninit(E2) = 50 x\n";
+        let error = Ast::parse(file.into()).unwrap_err();
+        assert_eq!((2, 14), (error.line, error.col));
+        assert_eq!(
+            "error: expected `;`, found `x`
+ --> model.txt:3:15
+  |
+3 | init(E2) = 50 x
+  |               ^",
+            error.render(file, "model.txt")
+        );
+
+        // Alternatives failing at the same place are listed together.
+        let error = Ast::parse("init(E1) = 30;\n;".into()).unwrap_err();
+        assert_eq!(
+            "expected one of identifier, natural number, `0`, `vitesse`, `speed`, `diffusion`, `init`, `count`, `diametre`, `diameter`, `param`, `sweep`, `simulation`, `compartment`, `at`, `when`, `include` or end of file",
+            error.message()
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::brenda_parser_helpers::*;
+use crate::diagnostic::Span;
+use crate::formula::Value;
+use crate::parser_combinator::*;
+use crate::printer::species_identifier;
+use crate::Parsable;
+
+/// `at t = 5 s: add(s, 1000);` or `when p > 500: set(E1, 0);`, changing the molecules of
+/// some species during the simulation, as a substrate pulse or a knock-down of an enzyme.
+/// Several actions are separated by commas.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct EventDeclaration {
+    /// Serialized as an `at` or `when` field.
+    #[serde(flatten)]
+    pub trigger: Trigger,
+    pub actions: Vec<Action>,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// Once, at a time in s.
+    At(Value),
+    /// Each time the condition becomes true, not when it already holds at the start.
+    When(Condition),
+}
+
+/// `p > 500`: a number of molecules of a species compared to a threshold.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Condition {
+    pub species: String,
+    pub comparison: Comparison,
+    pub threshold: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub enum Comparison {
+    #[serde(rename = ">")]
+    Above,
+    #[serde(rename = "<")]
+    Below,
+    #[serde(rename = ">=")]
+    AtLeast,
+    #[serde(rename = "<=")]
+    AtMost,
+}
+
+impl Comparison {
+    pub fn holds(self, count: u64, threshold: u64) -> bool {
+        match self {
+            Comparison::Above => count > threshold,
+            Comparison::Below => count < threshold,
+            Comparison::AtLeast => count >= threshold,
+            Comparison::AtMost => count <= threshold,
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Comparison::Above => write!(f, ">"),
+            Comparison::Below => write!(f, "<"),
+            Comparison::AtLeast => write!(f, ">="),
+            Comparison::AtMost => write!(f, "<="),
+        }
+    }
+}
+
+impl Condition {
+    pub fn holds(&self, count: u64) -> bool {
+        self.comparison.holds(count, self.threshold)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Action {
+    /// `add(s, 1000)`: adds molecules of a species.
+    Add { species: String, number: u64 },
+    /// `set(E1, 0)`: adds or removes molecules of a species until it has that many.
+    Set { species: String, number: u64 },
+}
+
+impl Action {
+    pub fn species(&self) -> &str {
+        match self {
+            Action::Add { species, .. } | Action::Set { species, .. } => species,
+        }
+    }
+    /// Molecules of the species after the action, when it had `count` before.
+    pub fn apply(&self, count: u64) -> u64 {
+        match self {
+            Action::Add { number, .. } => count + number,
+            Action::Set { number, .. } => *number,
+        }
+    }
+}
+
+impl EventDeclaration {
+    /// The species the event changes or watches, with repetitions.
+    pub fn species(&self) -> impl Iterator<Item = &String> {
+        let watched = match &self.trigger {
+            Trigger::When(c) => Some(&c.species),
+            Trigger::At(_) => None,
+        };
+        watched
+            .into_iter()
+            .chain(self.actions.iter().map(|a| match a {
+                Action::Add { species, .. } | Action::Set { species, .. } => species,
+            }))
+    }
+}
+
+fn comparison<'a>() -> impl Parser<'a, Comparison> {
+    between_spaces(choice((
+        literal(">=").map(|_| Comparison::AtLeast),
+        literal("<=").map(|_| Comparison::AtMost),
+        literal(">").map(|_| Comparison::Above),
+        literal("<").map(|_| Comparison::Below),
+    )))
+    .label("comparison")
+}
+
+/// `add(s, 1000)` or `set(E1, 0)`.
+fn action(text: ParserInput<'_>) -> ParserResult<'_, Action> {
+    let ParserSuccess {
+        content: add,
+        next_input,
+    } = between_spaces(literal("add"))
+        .map(|_| true)
+        .or_else(between_spaces(literal("set")).map(|_| false))
+        .label("action")
+        .parse(text)?;
+    let ParserSuccess { next_input, .. } = parse_lparen().parse(next_input)?;
+    let ParserSuccess {
+        content: species,
+        next_input,
+    } = parse_species_identifier().parse(next_input)?;
+    let ParserSuccess { next_input, .. } = parse_comma().parse(next_input)?;
+    let ParserSuccess {
+        content: number,
+        next_input,
+    } = parse_count().parse(next_input)?;
+    let ParserSuccess { next_input, .. } = parse_rparen().parse(next_input)?;
+    Ok(ParserSuccess {
+        content: if add {
+            Action::Add { species, number }
+        } else {
+            Action::Set { species, number }
+        },
+        next_input,
+    })
+}
+
+/// `at t = 5 s` or `when p > 500`.
+fn trigger(text: ParserInput<'_>) -> ParserResult<'_, Trigger> {
+    let ParserSuccess {
+        content: at,
+        next_input,
+    } = between_spaces(literal("at"))
+        .map(|_| true)
+        .or_else(between_spaces(literal("when")).map(|_| false))
+        .parse(text)?;
+    if at {
+        let ParserSuccess { next_input, .. } = between_spaces(literal("t")).parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_equal().parse(next_input)?;
+        return parse_instant().map(Trigger::At).parse(next_input);
+    }
+    let ParserSuccess {
+        content: species,
+        next_input,
+    } = parse_species_identifier().parse(next_input)?;
+    let ParserSuccess {
+        content: comparison,
+        next_input,
+    } = comparison().parse(next_input)?;
+    let ParserSuccess {
+        content: threshold,
+        next_input,
+    } = parse_count().parse(next_input)?;
+    Ok(ParserSuccess {
+        content: Trigger::When(Condition {
+            species,
+            comparison,
+            threshold,
+        }),
+        next_input,
+    })
+}
+
+impl Parsable for EventDeclaration {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess {
+            content: trigger,
+            next_input,
+        } = trigger(start)?;
+        let ParserSuccess { next_input, .. } = parse_colon().parse(next_input)?;
+        let ParserSuccess {
+            content: actions,
+            next_input,
+        } = action.sep_by1(parse_comma()).parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_semicolon().parse(next_input)?;
+        Ok(ParserSuccess {
+            content: Self {
+                trigger,
+                actions,
+                span: next_input.span_from(start),
+            },
+            next_input,
+        })
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Add { species, number } => {
+                write!(f, "add({}, {number})", species_identifier(species))
+            }
+            Action::Set { species, number } => {
+                write!(f, "set({}, {number})", species_identifier(species))
+            }
+        }
+    }
+}
+
+/// The event as it is written in a model, without its `;`.
+impl Display for EventDeclaration {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.trigger {
+            Trigger::At(time) => write!(f, "at t = {}:", time.formula)?,
+            Trigger::When(c) => write!(
+                f,
+                "when {} {} {}:",
+                species_identifier(&c.species),
+                c.comparison,
+                c.threshold
+            )?,
+        }
+        for (i, action) in self.actions.iter().enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{separator}{action}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Path of the log of the events fired during the simulation writing its results to
+/// `output`: `results.events.csv` for `results.csv`.
+pub fn log_path(output: &str) -> String {
+    let stem = output.strip_suffix(".csv").unwrap_or(output);
+    format!("{stem}.events.csv")
+}
+
+/// Log of the events fired during a simulation, as CSV rows of the time in s and the
+/// event.
+pub fn log_csv<'e>(fired: impl IntoIterator<Item = (f32, &'e EventDeclaration)>) -> String {
+    let mut csv = "time, event".to_string();
+    for (time, event) in fired {
+        csv.push_str(&format!(
+            "\n{time}, \"{}\"",
+            event.to_string().replace('"', "\"\"")
+        ));
+    }
+    csv
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::check::check;
+    use crate::keywords::Language;
+    use crate::settings::Settings;
+    use crate::{printer, Ast, Expression};
+
+    #[test]
+    fn t_species() {
+        let event = EventDeclaration::parse("when s < 10: add(s, 5), set(E1, 0);".into())
+            .unwrap()
+            .content;
+        assert_eq!(vec!["s", "s", "E1"], event.species().collect::<Vec<_>>());
+        assert_eq!("E1", event.actions[1].species());
+        let Trigger::When(condition) = &event.trigger else {
+            panic!("expected a condition")
+        };
+        assert!(condition.holds(9));
+        assert!(!condition.holds(10));
+        assert!(Comparison::AtMost.holds(10, 10));
+        assert!(!Comparison::Below.holds(11, 10));
+    }
+
+    #[test]
+    fn t_events() {
+        let parse = |text: &str| EventDeclaration::parse(text.into()).map(|s| s.content);
+        let pulse = parse("at t = 5s: add(s, 1000);").unwrap();
+        assert_eq!(
+            5.,
+            match &pulse.trigger {
+                Trigger::At(time) => time.value,
+                Trigger::When(_) => unreachable!(),
+            }
+        );
+        assert_eq!(
+            vec![Action::Add {
+                species: "s".to_string(),
+                number: 1000
+            }],
+            pulse.actions
+        );
+        assert_eq!(
+            Some(500.),
+            parse("at t=500ms:set(E1,0);")
+                .ok()
+                .and_then(|e| match e.trigger {
+                    Trigger::At(time) => Some(time.value * 1000.),
+                    Trigger::When(_) => None,
+                })
+        );
+        let knock_down = parse("when p[nucleus] >= 500: set(E1, 0), add(\"s 2\", 10);").unwrap();
+        assert_eq!(
+            Trigger::When(Condition {
+                species: "p[nucleus]".to_string(),
+                comparison: Comparison::AtLeast,
+                threshold: 500,
+            }),
+            knock_down.trigger
+        );
+        assert_eq!(
+            vec![
+                Action::Set {
+                    species: "E1".to_string(),
+                    number: 0
+                },
+                Action::Add {
+                    species: "s 2".to_string(),
+                    number: 10
+                }
+            ],
+            knock_down.actions
+        );
+        assert!(parse("at t = -1 s: add(s, 1);").is_err());
+        assert!(parse("at t = 1 um: add(s, 1);").is_err());
+        assert!(parse("when p > 500: ;").is_err());
+        assert!(parse("when p = 500: add(s, 1);").is_err());
+
+        assert!(!Comparison::Above.holds(500, 500));
+        assert!(Comparison::AtLeast.holds(500, 500));
+        let actions = parse("at t = 0 s: add(s, 1), set(s, 0);").unwrap().actions;
+        assert_eq!(
+            vec![3, 0],
+            actions.iter().map(|a| a.apply(2)).collect::<Vec<_>>()
+        );
+
+        let model = "E1 : s -> p | 200 - 100;
+
+init(E1) = 10;
+
+at t = 5 s: add(s, 1000);
+when p > 500: set(E1, 0), add(\"s 2\", 10);
+at t = 90 s: set(E1, 10);
+";
+        let ast = Ast::parse(model.into()).unwrap().content;
+        assert_eq!(model, printer::print(&ast, Language::French));
+        // `s` only comes from the pulse, which is not a mistake.
+        let messages = check(&ast, &Settings::default())
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["this event happens at 90 s, once the simulation has ended at 60 s"],
+            messages
+        );
+        assert_eq!("results.events.csv", log_path("results.csv"));
+        let Expression::Event(event) = &ast.0[3] else {
+            unreachable!()
+        };
+        assert_eq!(
+            "time, event\n0.5, \"when p > 500: set(E1, 0), add(\"\"s 2\"\", 10)\"",
+            log_csv([(0.5, event)])
+        );
+    }
+}
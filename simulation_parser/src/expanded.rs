@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+
+use crate::kinetics::{binding_rate_constants, elementary_rate_constants};
+use crate::printer::species;
+use crate::settings::Settings;
+use crate::{Ast, Binding, Expression, Reaction};
+
+/// The model as the Gillespie simulation runs it: mass-action reactions only, each
+/// enzymatic reaction expanded into its elementary steps with the enzyme-substrate
+/// complexes as species.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExpandedModel {
+    /// Species with their initial molecules, declared ones first, then in the order the
+    /// reactions involve them.
+    pub species: Vec<(String, u64)>,
+    pub reactions: Vec<ElementaryReaction>,
+}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct ElementaryReaction {
+    /// Short name made of the enzyme and the step, such as `E1_binds_s`. Several
+    /// reactions may have the same.
+    pub id: String,
+    /// The reaction of the model the step belongs to, and the step.
+    pub name: String,
+    pub reactants: Vec<(String, u32)>,
+    pub products: Vec<(String, u32)>,
+    /// Rate constant, in µM/s, s⁻¹ or µM⁻¹·s⁻¹ for zeroth, first and second order
+    /// reactions.
+    pub k: f32,
+}
+
+impl ElementaryReaction {
+    pub fn order(&self) -> u32 {
+        self.reactants.iter().map(|(_, n)| n).sum()
+    }
+}
+
+/// Elementary steps of the enzymatic reaction `r`: each substrate molecule binds the
+/// enzyme in turn and may unbind, and the full complex releases the enzyme and the
+/// products, with the rate constants of [`elementary_rate_constants`].
+fn elementary_steps(r: &Reaction, settings: &Settings) -> Vec<ElementaryReaction> {
+    let (k_on, k_off, kcat) = elementary_rate_constants(r.km.value, r.kcat.value, settings);
+    let one = |species: String| vec![(species, 1)];
+    let description = format!(
+        "{} : {} -> {}",
+        r.enzhym,
+        species(&r.solubes),
+        species(&r.results)
+    );
+    let mut steps = vec![];
+    for (i, substrate) in r.solube_units().enumerate() {
+        steps.push(ElementaryReaction {
+            id: format!("{}_binds_{substrate}", r.enzhym),
+            name: format!("{description}, binding of {substrate}"),
+            reactants: vec![(r.complex(i), 1), (substrate.clone(), 1)],
+            products: one(r.complex(i + 1)),
+            k: k_on,
+        });
+        steps.push(ElementaryReaction {
+            id: format!("{}_releases_{substrate}", r.enzhym),
+            name: format!("{description}, release of {substrate}"),
+            reactants: one(r.complex(i + 1)),
+            products: vec![(r.complex(i), 1), (substrate.clone(), 1)],
+            k: k_off,
+        });
+    }
+    let mut products = one(r.enzhym.clone());
+    products.extend(r.results.iter().cloned());
+    steps.push(ElementaryReaction {
+        id: format!("{}_catalysis", r.enzhym),
+        name: format!("{description}, catalysis"),
+        reactants: one(r.complex(r.solube_units().count())),
+        products,
+        k: kcat,
+    });
+    steps
+}
+
+/// Binding and unbinding of an inhibitor or activator of the reaction `r`, with the rate
+/// constants of [`binding_rate_constants`].
+fn binding_steps(r: &Reaction, b: &Binding, settings: &Settings) -> Vec<ElementaryReaction> {
+    let (k_on, k_off) = binding_rate_constants(b.k, r.km.value, r.kcat.value, settings);
+    let site = vec![(b.site.clone(), 1), (b.modifier.clone(), 1)];
+    let complex = vec![(b.complex.clone(), 1)];
+    vec![
+        ElementaryReaction {
+            id: format!("{}_binds_{}", b.site, b.modifier),
+            name: format!("{} + {} -> {}", b.site, b.modifier, b.complex),
+            reactants: site.clone(),
+            products: complex.clone(),
+            k: k_on,
+        },
+        ElementaryReaction {
+            id: format!("{}_releases_{}", b.site, b.modifier),
+            name: format!("{} -> {} + {}", b.complex, b.site, b.modifier),
+            reactants: complex,
+            products: site,
+            k: k_off,
+        },
+    ]
+}
+
+impl ExpandedModel {
+    /// Expands the resolved model `ast`, whose enzymatic rate constants depend on the
+    /// step and volume of `settings`.
+    pub fn new(Ast(expressions): &Ast, settings: &Settings) -> Self {
+        let mut reactions = vec![];
+        for expression in expressions {
+            match expression {
+                Expression::Reaction(r) => {
+                    for catalysed in r.catalysed() {
+                        reactions.extend(elementary_steps(&catalysed, settings));
+                    }
+                    for binding in r.bindings() {
+                        reactions.extend(binding_steps(r, &binding, settings));
+                    }
+                }
+                Expression::MassAction(r) => reactions.push(ElementaryReaction {
+                    id: "mass_action".to_string(),
+                    name: format!("{} -> {}", species(&r.reactants), species(&r.products)),
+                    reactants: r.reactants.clone(),
+                    products: r.products.clone(),
+                    k: r.k.value,
+                }),
+                _ => (),
+            }
+        }
+
+        let mut species: Vec<(String, u64)> = vec![];
+        let inits = expressions.iter().filter_map(|e| match e {
+            Expression::InitDeclaration(i) => Some((&i.identifier, Some(i.number))),
+            _ => None,
+        });
+        let reacting = reactions
+            .iter()
+            .flat_map(|r| r.reactants.iter().chain(&r.products))
+            .map(|(name, _)| (name, None));
+        for (name, number) in inits.chain(reacting) {
+            match species.iter_mut().find(|(s, _)| s == name) {
+                Some((_, n)) => *n = number.unwrap_or(*n),
+                None => species.push((name.clone(), number.unwrap_or(0))),
+            }
+        }
+        ExpandedModel { species, reactions }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Parsable;
+
+    #[test]
+    fn t_expanded() {
+        let model = "E1 : 2 s -> p | 100 - 10; 0 -> s @ k = 1 uM/s; init(p) = 5; init(p) = 7;";
+        let ast = Ast::parse(model.into()).unwrap().content;
+        let expanded = ExpandedModel::new(&ast, &Settings::default());
+        let reactions = expanded
+            .reactions
+            .iter()
+            .map(|r| {
+                format!(
+                    "{}: {} -> {}",
+                    r.id,
+                    species(&r.reactants),
+                    species(&r.products)
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "E1_binds_s: E1 + s -> \"E1--s\"",
+                "E1_releases_s: \"E1--s\" -> E1 + s",
+                "E1_binds_s: \"E1--s\" + s -> \"E1--s--s\"",
+                "E1_releases_s: \"E1--s--s\" -> \"E1--s\" + s",
+                "E1_catalysis: \"E1--s--s\" -> E1 + p",
+                "mass_action: 0 -> s",
+            ],
+            reactions
+        );
+        assert_eq!(
+            vec![2, 1, 2, 1, 1, 0],
+            expanded
+                .reactions
+                .iter()
+                .map(ElementaryReaction::order)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            (10., 1.),
+            (expanded.reactions[4].k, expanded.reactions[5].k)
+        );
+        // Declared species come first, with their last declaration.
+        assert_eq!(
+            vec![
+                ("p".to_string(), 7),
+                ("E1".to_string(), 0),
+                ("s".to_string(), 0),
+                ("E1--s".to_string(), 0),
+                ("E1--s--s".to_string(), 0),
+            ],
+            expanded.species
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use serde::de::DeserializeOwned;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::include::Sources;
+use crate::{sbml, Ast, Expression};
+
+/// Format of a model written as data rather than in the model language, for tools that
+/// generate models.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Format {
+    Json,
+    Toml,
+    Yaml,
+}
+
+impl Format {
+    /// The format of the file at `path`, from its extension, or `None` for a model text.
+    pub fn of(path: &str) -> Option<Format> {
+        match std::path::Path::new(path).extension()?.to_str()? {
+            "json" => Some(Format::Json),
+            "toml" => Some(Format::Toml),
+            "yaml" | "yml" => Some(Format::Yaml),
+            _ => None,
+        }
+    }
+
+    pub fn write<T: Serialize>(self, value: &T) -> Result<String, String> {
+        match self {
+            Format::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+            Format::Toml => toml::to_string_pretty(value).map_err(|e| e.to_string()),
+            Format::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Reads `text`, the content of the file `file` of the sources, pointing errors at
+    /// where the parser of the format found them.
+    pub fn read<T: DeserializeOwned>(self, text: &str, file: usize) -> Result<T, Diagnostic> {
+        match self {
+            Format::Json => serde_json::from_str(text).map_err(|e| {
+                let message = e.to_string();
+                // The position is repeated in the rendering.
+                let message = message.split(" at line ").next().unwrap_or(&message);
+                Diagnostic::error(message.into(), Span::at(text, e.line(), e.column(), file))
+            }),
+            Format::Toml => toml::from_str(text).map_err(|e| {
+                let range = e.span().unwrap_or(0..0);
+                Diagnostic::error(
+                    e.message().into(),
+                    Span {
+                        start: range.start,
+                        end: range.end,
+                        file,
+                    },
+                )
+            }),
+            Format::Yaml => serde_yaml::from_str(text).map_err(|e| {
+                let span = e.location().map_or(Span::at(text, 1, 1, file), |l| {
+                    Span::at(text, l.line(), l.column(), file)
+                });
+                let message = e.to_string();
+                let message = message.split(" at line ").next().unwrap_or(&message);
+                Diagnostic::error(message.into(), span)
+            }),
+        }
+    }
+}
+
+/// Serialized as a table holding the `expressions` array, as every format accepts at its
+/// top level.
+impl Serialize for Ast {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut model = serializer.serialize_struct("Ast", 1)?;
+        model.serialize_field("expressions", &self.0)?;
+        model.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Ast {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        struct Model {
+            expressions: Vec<Expression>,
+        }
+        Model::deserialize(deserializer).map(|model| Ast(model.expressions))
+    }
+}
+
+impl Ast {
+    /// Reads the model at `path`, whose content is `text` in the `format`, and resolves
+    /// its parameters. Spans point to the start of the file, the formats keeping no
+    /// positions, and models written as data cannot include others.
+    pub fn load_data(
+        path: &str,
+        text: String,
+        format: Format,
+        sources: &mut Sources,
+    ) -> Result<Ast, Diagnostic> {
+        let file = sources.add(path.to_string(), text);
+        let mut ast: Ast = format.read(sources.text(file), file)?;
+        if let Some(Expression::Include(include)) =
+            ast.0.iter().find(|e| matches!(e, Expression::Include(_)))
+        {
+            return Err(Diagnostic::error(
+                format!("cannot include `{}` from {path}", include.path),
+                Span {
+                    start: 0,
+                    end: 0,
+                    file,
+                },
+            )
+            .with_note("only model texts can include other files".into()));
+        }
+        ast.resolve(&[])?;
+        Ok(ast)
+    }
+}
+
+/// Reads the model at `path`, whose content is `text`, in the format its extension tells:
+/// an SBML document for `.xml` and `.sbml`, JSON, TOML or YAML data, and the model
+/// language otherwise. The diagnostics are those of an SBML import.
+pub fn load(
+    path: &str,
+    text: String,
+    sources: &mut Sources,
+) -> Result<(Ast, Vec<Diagnostic>), Diagnostic> {
+    if sbml::is_sbml(path) {
+        Ast::load_sbml(path, text, sources)
+    } else if let Some(format) = Format::of(path) {
+        Ast::load_data(path, text, format, sources).map(|ast| (ast, vec![]))
+    } else {
+        Ast::load(path, text, sources).map(|ast| (ast, vec![]))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::expanded::ExpandedModel;
+    use crate::generator::Generator;
+    use crate::settings::Settings;
+
+    #[test]
+    fn t_read() {
+        assert_eq!(Some(Format::Yaml), Format::of("model.yml"));
+        assert_eq!(Some(Format::Toml), Format::of("models/a.b.toml"));
+        assert_eq!(None, Format::of("model.txt"));
+        assert_eq!(None, Format::of("json"));
+        let text = "{\n  \"expressions\": [1]\n}";
+        let error = Format::Json.read::<Ast>(text, 0).unwrap_err();
+        assert_eq!(Span::new(20, 20), error.span);
+        let error = Format::Toml.read::<Ast>("expressions = 1", 2).unwrap_err();
+        assert_eq!(2, error.span.file);
+        assert_eq!(14, error.span.start);
+    }
+
+    #[test]
+    fn t_data_formats() {
+        let mut generator = Generator::new(15);
+        for _ in 0..200 {
+            let ast = generator.ast();
+            for format in [Format::Json, Format::Toml, Format::Yaml] {
+                let text = format.write(&ast).unwrap();
+                let mut read: Ast = format.read(&text, 0).unwrap();
+                read.resolve(&[]).unwrap();
+                assert_eq!(ast.without_spans(), read.without_spans(), "{text}");
+            }
+        }
+
+        let json = r#"{"expressions": [
+            {"kind": "param", "identifier": "km", "value": 100},
+            {"kind": "reaction", "enzyme": "E", "substrates": [["s", 1]],
+             "products": [["p", 1]], "km": "2 * km uM", "kcat": 10},
+            {"kind": "init", "identifier": "E", "number": 30},
+            {"kind": "speed", "identifier": "E", "step": 0.1}
+        ]}"#;
+        let mut sources = Sources::default();
+        let ast = Ast::load_data("model.json", json.into(), Format::Json, &mut sources).unwrap();
+        let Expression::Reaction(reaction) = &ast.0[1] else {
+            panic!("expected a reaction")
+        };
+        assert_eq!(200., reaction.km.value);
+        let expanded = ExpandedModel::new(&ast, &Settings::default());
+        assert_eq!(3, expanded.reactions.len());
+        assert_eq!(
+            expanded,
+            Format::Toml
+                .read(&Format::Toml.write(&expanded).unwrap(), 0)
+                .unwrap()
+        );
+
+        let error = Ast::load_data(
+            "model.json",
+            r#"{"expressions": [{"kind": "init", "identifier": "s"}]}"#.into(),
+            Format::Json,
+            &mut sources,
+        )
+        .unwrap_err();
+        assert_eq!("missing field `number`", error.message);
+        assert!(sources.render(&error).contains("model.json:1:53"));
+    }
+}
@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::brenda_parser_helpers::{parse_float, parse_identifier, parse_lparen, parse_rparen};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::parser_combinator::{
+    anychar, between_spaces, space0, Parser, ParserInput, ParserResult, ParserSuccess,
+};
+use crate::units::{any_unit, quantity, Dimension, Unit};
+
+/// Arithmetic expression written where a number is expected. It is kept as written so that
+/// a model can be printed back and evaluated again when its parameters change.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Formula {
+    Number(f32),
+    /// Reference to a `param` declaration.
+    Param(String, Span),
+    Neg(Box<Formula>),
+    Add(Box<Formula>, Box<Formula>),
+    Sub(Box<Formula>, Box<Formula>),
+    Mul(Box<Formula>, Box<Formula>),
+    Div(Box<Formula>, Box<Formula>),
+    Pow(Box<Formula>, Box<Formula>),
+    /// A formula followed by a unit, converted into the canonical unit of its dimension.
+    Unit(Box<Formula>, Unit),
+}
+
+impl Formula {
+    pub fn eval(&self, params: &HashMap<String, f64>) -> Result<f64, Diagnostic> {
+        Ok(match self {
+            Formula::Number(x) => *x as f64,
+            Formula::Param(name, span) => *params.get(name).ok_or_else(|| {
+                Diagnostic::error(format!("unknown parameter `{name}`"), *span)
+                    .with_note("parameters must be declared before they are used".into())
+            })?,
+            Formula::Neg(a) => -a.eval(params)?,
+            Formula::Add(a, b) => a.eval(params)? + b.eval(params)?,
+            Formula::Sub(a, b) => a.eval(params)? - b.eval(params)?,
+            Formula::Mul(a, b) => a.eval(params)? * b.eval(params)?,
+            Formula::Div(a, b) => a.eval(params)? / b.eval(params)?,
+            Formula::Pow(a, b) => a.eval(params)?.powf(b.eval(params)?),
+            Formula::Unit(a, unit) => a.eval(params)? * unit.factor,
+        })
+    }
+    /// Resets the spans of the parameters the formula refers to, see [`crate::Ast::without_spans`].
+    pub(crate) fn clear_spans(&mut self) {
+        match self {
+            Formula::Number(_) => (),
+            Formula::Param(_, span) => *span = Span::default(),
+            Formula::Neg(a) | Formula::Unit(a, _) => a.clear_spans(),
+            Formula::Add(a, b)
+            | Formula::Sub(a, b)
+            | Formula::Mul(a, b)
+            | Formula::Div(a, b)
+            | Formula::Pow(a, b) => {
+                a.clear_spans();
+                b.clear_spans();
+            }
+        }
+    }
+}
+
+/// Binding strength of the operators, from the loosest.
+const SUM: u8 = 0;
+const TERM: u8 = 1;
+const UNARY: u8 = 2;
+const POWER: u8 = 3;
+const ATOM: u8 = 4;
+
+impl Formula {
+    fn precedence(&self) -> u8 {
+        match self {
+            Formula::Add(..) | Formula::Sub(..) => SUM,
+            Formula::Mul(..) | Formula::Div(..) => TERM,
+            Formula::Neg(_) => UNARY,
+            Formula::Pow(..) => POWER,
+            Formula::Number(_) | Formula::Param(..) => ATOM,
+            // Units are only written after a whole formula.
+            Formula::Unit(..) => SUM,
+        }
+    }
+
+    /// The formula as it is written in a model, with only the parentheses its structure
+    /// needs. Without `subtraction`, subtractions are parenthesized too, see [`formula`].
+    pub fn to_source(&self, subtraction: bool) -> String {
+        let mut source = String::new();
+        self.write(&mut source, SUM, subtraction);
+        source
+    }
+
+    fn write(&self, out: &mut String, min_precedence: u8, subtraction: bool) {
+        if self.precedence() < min_precedence || matches!(self, Formula::Sub(..)) && !subtraction {
+            out.push('(');
+            self.write(out, SUM, true);
+            out.push(')');
+            return;
+        }
+        let mut binary = |a: &Formula, operator: &str, b: &Formula, precedence: u8| {
+            a.write(out, precedence, subtraction);
+            out.push_str(operator);
+            b.write(out, precedence + 1, subtraction);
+        };
+        match self {
+            Formula::Number(x) => out.push_str(&x.to_string()),
+            Formula::Param(name, _) => out.push_str(&crate::printer::identifier(name)),
+            Formula::Add(a, b) => binary(a, " + ", b, SUM),
+            Formula::Sub(a, b) => binary(a, " - ", b, SUM),
+            Formula::Mul(a, b) => binary(a, " * ", b, TERM),
+            Formula::Div(a, b) => binary(a, " / ", b, TERM),
+            Formula::Neg(a) => {
+                out.push('-');
+                a.write(out, UNARY, subtraction);
+            }
+            Formula::Pow(a, b) => {
+                a.write(out, ATOM, subtraction);
+                out.push('^');
+                b.write(out, UNARY, subtraction);
+            }
+            Formula::Unit(a, unit) => {
+                a.write(out, SUM, subtraction);
+                out.push(' ');
+                out.push_str(unit.symbol);
+            }
+        }
+    }
+}
+
+impl Display for Formula {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.to_source(true))
+    }
+}
+
+/// A number of the model: the formula it is written as, and its value once evaluated.
+#[derive(Debug, Clone)]
+pub struct Value {
+    pub formula: Formula,
+    /// In the canonical unit of its dimension. NaN until the parameters the formula
+    /// refers to are resolved.
+    pub value: f32,
+}
+
+impl Value {
+    pub fn new(formula: Formula) -> Self {
+        let value = formula.eval(&HashMap::new()).map_or(f32::NAN, |x| x as f32);
+        Self { formula, value }
+    }
+    /// Placeholder of a number looked up elsewhere, such as the Km of a reaction taken
+    /// from BRENDA. It evaluates to NaN.
+    pub fn missing() -> Self {
+        f32::NAN.into()
+    }
+    pub fn is_missing(&self) -> bool {
+        matches!(self.formula, Formula::Number(x) if x.is_nan())
+    }
+    pub fn resolve(&mut self, params: &HashMap<String, f64>) -> Result<(), Diagnostic> {
+        self.value = self.formula.eval(params)? as f32;
+        Ok(())
+    }
+}
+
+/// Missing values are equal to each other, unlike the NaN they evaluate to.
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_missing() && other.is_missing()
+            || self.formula == other.formula
+                && (self.value == other.value || self.value.is_nan() && other.value.is_nan())
+    }
+}
+
+impl From<f32> for Value {
+    fn from(value: f32) -> Self {
+        Self {
+            formula: Formula::Number(value),
+            value,
+        }
+    }
+}
+
+/// Serialized as a number when it is written as one, and as the formula it is written as
+/// otherwise, such as `"2 * km"` or `"200 uM"`. A formula may end with a unit of any
+/// dimension.
+impl Serialize for Value {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match &self.formula {
+            Formula::Number(x) => serializer.serialize_f32(*x),
+            formula => serializer.serialize_str(&formula.to_string()),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Value {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Written {
+            Number(f32),
+            Formula(String),
+        }
+        match Written::deserialize(deserializer)? {
+            Written::Number(x) => Ok(x.into()),
+            Written::Formula(source) => parse_quantity(&source).map_err(de::Error::custom),
+        }
+    }
+}
+
+/// ```text
+/// formula := term (('+' | '-') term)*
+/// term    := unary (('*' | '/') unary)*
+/// unary   := '-' unary | power
+/// power   := atom ('^' unary)?
+/// atom    := number | parameter | '(' formula ')'
+/// ```
+///
+/// Without `subtraction`, a `-` ends the formula instead, for numbers followed by a dash
+/// such as the Km of `200 uM - 100`. A subtraction can still be written in parentheses.
+pub fn formula<'a>(subtraction: bool) -> impl Parser<'a, Formula> {
+    move |input| sum(input, if subtraction { "+-" } else { "+" })
+}
+
+fn operator<'a>(operators: &'static str) -> impl Parser<'a, char> {
+    between_spaces(anychar.predicate(move |c| operators.contains(*c), "Expected operator"))
+}
+
+fn sum<'a>(input: ParserInput<'a>, operators: &'static str) -> ParserResult<'a, Formula> {
+    let ParserSuccess {
+        content: mut left,
+        mut next_input,
+    } = term(input)?;
+    while let Ok(ParserSuccess {
+        content: op,
+        next_input: rest,
+    }) = operator(operators).parse(next_input)
+    {
+        let ParserSuccess {
+            content: right,
+            next_input: rest,
+        } = term(rest)?;
+        left = match op {
+            '+' => Formula::Add(Box::new(left), Box::new(right)),
+            _ => Formula::Sub(Box::new(left), Box::new(right)),
+        };
+        next_input = rest;
+    }
+    Ok(ParserSuccess {
+        content: left,
+        next_input,
+    })
+}
+
+fn term<'a>(input: ParserInput<'a>) -> ParserResult<'a, Formula> {
+    let ParserSuccess {
+        content: mut left,
+        mut next_input,
+    } = unary(input)?;
+    loop {
+        // `100 /s` is a number followed by a unit, not a division.
+        let ParserSuccess { next_input: at, .. } = space0().parse(next_input)?;
+        if any_unit().parse(at).is_ok() {
+            break;
+        }
+        let Ok(ParserSuccess {
+            content: op,
+            next_input: rest,
+        }) = operator("*/").parse(next_input)
+        else {
+            break;
+        };
+        let ParserSuccess {
+            content: right,
+            next_input: rest,
+        } = unary(rest)?;
+        left = match op {
+            '*' => Formula::Mul(Box::new(left), Box::new(right)),
+            _ => Formula::Div(Box::new(left), Box::new(right)),
+        };
+        next_input = rest;
+    }
+    Ok(ParserSuccess {
+        content: left,
+        next_input,
+    })
+}
+
+fn unary<'a>(input: ParserInput<'a>) -> ParserResult<'a, Formula> {
+    match operator("-").parse(input) {
+        Ok(ParserSuccess { next_input, .. }) => {
+            unary(next_input).map(|success| success.map(|f| Formula::Neg(Box::new(f))))
+        }
+        Err(_) => power(input),
+    }
+}
+
+fn power<'a>(input: ParserInput<'a>) -> ParserResult<'a, Formula> {
+    let ParserSuccess {
+        content: base,
+        next_input,
+    } = atom(input)?;
+    let Ok(ParserSuccess { next_input, .. }) = operator("^").parse(next_input) else {
+        return Ok(ParserSuccess {
+            content: base,
+            next_input,
+        });
+    };
+    let ParserSuccess {
+        content: exponent,
+        next_input,
+    } = unary(next_input)?;
+    Ok(ParserSuccess {
+        content: Formula::Pow(Box::new(base), Box::new(exponent)),
+        next_input,
+    })
+}
+
+fn parameter<'a>(input: ParserInput<'a>) -> ParserResult<'a, Formula> {
+    let ParserSuccess {
+        next_input: start, ..
+    } = space0().parse(input)?;
+    parse_identifier().parse(start).map(|success| {
+        let span = success.next_input.span_from(start);
+        success.map(|name| Formula::Param(name, span))
+    })
+}
+
+fn atom<'a>(input: ParserInput<'a>) -> ParserResult<'a, Formula> {
+    parse_float()
+        .map(Formula::Number)
+        .or_else(parameter)
+        .or_else(
+            parse_lparen()
+                .skip_me(formula(true))
+                .skip_next(parse_rparen()),
+        )
+        .parse(input)
+}
+
+/// Parses the whole of `source` as a formula, optionally followed by a unit.
+fn parse_quantity(source: &str) -> Result<Value, String> {
+    let ParserSuccess {
+        content: (value, _),
+        next_input,
+    } = quantity(Dimension::ALL, true)
+        .parse(source.into())
+        .map_err(|e| e.message())?;
+    if next_input.offset() != source.len() {
+        return Err(format!("unexpected `{}`", &source[next_input.offset()..]));
+    }
+    Ok(value)
+}
+
+/// Parses a `name=value` command line assignment, where the value is a formula without
+/// parameters, optionally followed by a unit.
+pub fn parse_assignment(text: &str) -> Result<(String, f64), String> {
+    let (name, source) = text
+        .split_once('=')
+        .ok_or_else(|| format!("expected `name=value`, found `{text}`"))?;
+    let value = parse_quantity(source)?
+        .formula
+        .eval(&HashMap::new())
+        .map_err(|d| d.message)?;
+    Ok((name.trim().to_string(), value))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Parsable, Reaction};
+
+    #[test]
+    fn t_eval() {
+        let eval = |text: &str, params: &[(&str, f64)]| {
+            let params = params
+                .iter()
+                .map(|&(name, value)| (name.to_string(), value))
+                .collect();
+            formula(true)
+                .parse(text.into())
+                .unwrap()
+                .content
+                .eval(&params)
+                .map_err(|d| d.message)
+        };
+        assert_eq!(Ok(-7.), eval("1 - 2 * 2^2", &[]));
+        assert_eq!(Ok(-4.), eval("-2^2", &[]));
+        assert_eq!(Ok(2.), eval("2^-1 * (k + 3)", &[("k", 1.)]));
+        assert_eq!(Err("unknown parameter `k`".to_string()), eval("2 * k", &[]));
+        // Without subtraction, the formula stops before the `-`.
+        let parsed = formula(false).parse("3 * k - 1".into()).unwrap();
+        assert_eq!("3 * k", parsed.content.to_source(true));
+    }
+
+    #[test]
+    fn t_assignment() {
+        assert_eq!(
+            Ok(("km1".to_string(), 1500.)),
+            parse_assignment("km1=1.5 mM")
+        );
+        assert_eq!(Ok(("k".to_string(), 8.)), parse_assignment(" k = 2^3"));
+        assert!(parse_assignment("km1").is_err());
+        assert!(parse_assignment("km1=x").is_err());
+        assert!(parse_assignment("km1=1 2").is_err());
+    }
+
+    #[test]
+    fn t_formulas() {
+        let reaction = Reaction::parse("E1 : s -> p | (2 + 3) * 2^3 uM - 100 / 4 + 2;".into())
+            .unwrap()
+            .content;
+        assert_eq!((40., 27.), (reaction.km.value, reaction.kcat.value));
+        let reaction = Reaction::parse("E1 : s -> p | 0.1 mM - -2^2 + 10 /min;".into())
+            .unwrap()
+            .content;
+        assert_eq!((100., 0.1), (reaction.km.value, reaction.kcat.value));
+        // Without parentheses, the `-` after a Km separates it from kcat.
+        let reaction = Reaction::parse("E1 : s -> p | 300 - 100 - 10;".into())
+            .unwrap()
+            .content;
+        assert_eq!((300., 90.), (reaction.km.value, reaction.kcat.value));
+        let reaction = Reaction::parse("E1 : s -> p | (300 - 100) - 10;".into())
+            .unwrap()
+            .content;
+        assert_eq!((200., 10.), (reaction.km.value, reaction.kcat.value));
+    }
+}
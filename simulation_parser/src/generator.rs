@@ -0,0 +1,289 @@
+use crate::brenda::BrendaQuery;
+use crate::compartments::CompartmentDeclaration;
+use crate::diagnostic::Span;
+use crate::events::{Action, Comparison, Condition, EventDeclaration, Trigger};
+use crate::formula::{Formula, Value};
+use crate::settings::SimulationBlock;
+use crate::sweep::{SweepDeclaration, SweepValues};
+use crate::units::{Dimension, UNITS};
+use crate::{
+    Ast, DiameterDeclaration, Expression, IncludeDeclaration, InitDeclaration, MassActionReaction,
+    Mechanism, Modifier, ParamDeclaration, Reaction, ReverseKinetics, Speed, SpeedDeclaration,
+};
+
+/// Random models for the round trip tests, within what the grammar can express: numbers
+/// are never negative, lengths and durations are positive, and parameters are only used
+/// after their declaration.
+pub struct Generator {
+    rng: fastrand::Rng,
+    params: Vec<String>,
+}
+
+impl Generator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            rng: fastrand::Rng::with_seed(seed),
+            params: vec![],
+        }
+    }
+    fn identifier(&mut self) -> String {
+        let names = ["A", "B", "glc", "atp", "E1", "HK2", "E1--s", "2pg", "µ"];
+        names[self.rng.usize(..names.len())].to_string()
+    }
+    /// An identifier, or a species in a compartment.
+    fn species_name(&mut self) -> String {
+        let names = [
+            "s[nucleus]",
+            "E1[outer membrane]",
+            "E1[nucleus]--s[nucleus]",
+        ];
+        match self.rng.u32(0..4) {
+            0 => names[self.rng.usize(..names.len())].to_string(),
+            _ => self.identifier(),
+        }
+    }
+    fn species(&mut self, max: usize) -> Vec<(String, u32)> {
+        (0..self.rng.usize(1..=max))
+            .map(|_| (self.species_name(), self.rng.u32(1..4)))
+            .collect()
+    }
+    fn number(&mut self) -> Formula {
+        Formula::Number(self.rng.u32(1..100_000) as f32 / 100.)
+    }
+    fn formula(&mut self, depth: u32) -> Formula {
+        let b = Box::new;
+        match self.rng.u32(0..if depth == 0 { 2 } else { 8 }) {
+            0 => self.number(),
+            1 if !self.params.is_empty() => {
+                let name = self.params[self.rng.usize(..self.params.len())].clone();
+                Formula::Param(name, Span::default())
+            }
+            1 => self.number(),
+            2 => Formula::Neg(b(self.formula(depth - 1))),
+            3 => Formula::Add(b(self.formula(depth - 1)), b(self.formula(depth - 1))),
+            4 => Formula::Sub(b(self.formula(depth - 1)), b(self.formula(depth - 1))),
+            5 => Formula::Mul(b(self.formula(depth - 1)), b(self.formula(depth - 1))),
+            6 => Formula::Div(b(self.formula(depth - 1)), b(self.formula(depth - 1))),
+            _ => Formula::Pow(b(self.formula(depth - 1)), b(self.formula(depth - 1))),
+        }
+    }
+    /// A formula, followed by a unit of one of the `dimensions` unless `optional`.
+    fn quantity(&mut self, dimensions: &[Dimension], optional: bool) -> Value {
+        let formula = self.formula(3);
+        let units = UNITS
+            .iter()
+            .filter(|u| dimensions.contains(&u.dimension))
+            .collect::<Vec<_>>();
+        if units.is_empty() || optional && self.rng.bool() {
+            return Value::new(formula);
+        }
+        let unit = *units[self.rng.usize(..units.len())];
+        Value::new(Formula::Unit(Box::new(formula), unit))
+    }
+    /// A positive formula, without parameters whose sign is unknown.
+    fn positive_formula(&mut self, depth: u32) -> Formula {
+        let b = Box::new;
+        match self.rng.u32(0..if depth == 0 { 1 } else { 4 }) {
+            0 => self.number(),
+            1 => Formula::Add(b(self.positive_formula(depth - 1)), b(self.number())),
+            2 => Formula::Mul(b(self.positive_formula(depth - 1)), b(self.number())),
+            _ => Formula::Div(b(self.positive_formula(depth - 1)), b(self.number())),
+        }
+    }
+    fn positive(&mut self, dimension: Dimension) -> Value {
+        let formula = self.positive_formula(2);
+        if self.rng.bool() {
+            return Value::new(formula);
+        }
+        let units = UNITS
+            .iter()
+            .filter(|u| u.dimension == dimension)
+            .collect::<Vec<_>>();
+        let unit = *units[self.rng.usize(..units.len())];
+        Value::new(Formula::Unit(Box::new(formula), unit))
+    }
+    fn modifier(&mut self) -> Modifier {
+        let k = self.quantity(&[Dimension::Concentration], true);
+        let mechanism = match self.rng.u32(0..5) {
+            0 => Mechanism::Competitive { ki: k },
+            1 => Mechanism::Uncompetitive { ki: k },
+            2 => Mechanism::Substrate { ki: k },
+            3 => Mechanism::Mixed {
+                ki: k,
+                alpha: self.quantity(&[], true),
+            },
+            _ => Mechanism::Activation {
+                ka: k,
+                beta: self.quantity(&[], true),
+            },
+        };
+        Modifier {
+            species: self.species_name(),
+            mechanism,
+        }
+    }
+    fn expression(&mut self, i: usize) -> Expression {
+        let span = Span::default();
+        match self.rng.u32(0..11) {
+            0 => {
+                let brenda = (self.rng.u32(0..4) == 0).then(|| BrendaQuery {
+                    ec: "1.1.1.1".into(),
+                    substrate: self.identifier(),
+                    organism: self.rng.bool().then(|| "Homo sapiens".into()),
+                    provenance: None,
+                });
+                let (km, kcat) = match brenda {
+                    Some(_) => (Value::missing(), Value::missing()),
+                    None => (
+                        self.quantity(&[Dimension::Concentration], true),
+                        self.quantity(&[Dimension::Rate], true),
+                    ),
+                };
+                Expression::Reaction(Box::new(Reaction {
+                    enzhym: self.species_name(),
+                    solubes: self.species(3),
+                    results: self.species(3),
+                    km,
+                    kcat,
+                    reverse: self.rng.bool().then(|| ReverseKinetics {
+                        km: self.quantity(&[Dimension::Concentration], true),
+                        kcat: self.quantity(&[Dimension::Rate], true),
+                        keq: self.rng.bool().then(|| self.quantity(&[], true)),
+                    }),
+                    brenda,
+                    modifiers: (0..self.rng.usize(0..3)).map(|_| self.modifier()).collect(),
+                    span,
+                }))
+            }
+            1 => {
+                let (reactants, dimension) = match self.rng.u32(0..4) {
+                    0 => (vec![], Dimension::Flux),
+                    1 => (vec![(self.species_name(), 1)], Dimension::Rate),
+                    2 => (vec![(self.species_name(), 2)], Dimension::BimolecularRate),
+                    _ => (
+                        vec![(self.species_name(), 1), (self.species_name(), 1)],
+                        Dimension::BimolecularRate,
+                    ),
+                };
+                let products = match self.rng.bool() {
+                    true => self.species(2),
+                    false => vec![],
+                };
+                Expression::MassAction(MassActionReaction {
+                    reactants,
+                    products,
+                    k: self.quantity(&[dimension], true),
+                    span,
+                })
+            }
+            2 => Expression::SpeedDeclaration(SpeedDeclaration {
+                identifier: self.species_name(),
+                speed: match self.rng.bool() {
+                    true => Speed::Step(self.quantity(&[Dimension::Length], true)),
+                    false => Speed::Diffusion(self.quantity(&[Dimension::Diffusion], true)),
+                },
+                span,
+            }),
+            3 => Expression::InitDeclaration(InitDeclaration {
+                identifier: self.species_name(),
+                number: self.rng.u64(..100_000),
+                span,
+            }),
+            4 => Expression::DiameterDeclaration(DiameterDeclaration {
+                identifier: self.species_name(),
+                diameter: self.positive(Dimension::Length),
+                span,
+            }),
+            5 => {
+                let value = self.quantity(Dimension::ALL, true);
+                self.params.push(format!("p{i}"));
+                Expression::Param(ParamDeclaration {
+                    identifier: format!("p{i}"),
+                    value,
+                    span,
+                })
+            }
+            6 => Expression::Simulation(SimulationBlock {
+                end_time: self.rng.bool().then(|| self.positive(Dimension::Time)),
+                sample_every: self.rng.bool().then(|| self.positive(Dimension::Time)),
+                seed: self.rng.bool().then(|| self.rng.u32(..) as u64),
+                dt: self.rng.bool().then(|| self.positive(Dimension::Time)),
+                radius: self.rng.bool().then(|| self.positive(Dimension::Length)),
+                span,
+            }),
+            7 => Expression::Compartment(CompartmentDeclaration {
+                identifier: ["nucleus", "outer membrane"][self.rng.usize(..2)].to_string(),
+                volume: self.positive(Dimension::Volume),
+                inside: self.rng.bool().then(|| "cytosol".to_string()),
+                span,
+            }),
+            8 => Expression::Event(EventDeclaration {
+                trigger: match self.rng.bool() {
+                    true => Trigger::At(self.positive(Dimension::Time)),
+                    false => Trigger::When(Condition {
+                        species: self.species_name(),
+                        comparison: [
+                            Comparison::Above,
+                            Comparison::Below,
+                            Comparison::AtLeast,
+                            Comparison::AtMost,
+                        ][self.rng.usize(..4)],
+                        threshold: self.rng.u64(..100_000),
+                    }),
+                },
+                actions: (0..self.rng.usize(1..3))
+                    .map(|_| match self.rng.bool() {
+                        true => Action::Add {
+                            species: self.species_name(),
+                            number: self.rng.u64(..100_000),
+                        },
+                        false => Action::Set {
+                            species: self.species_name(),
+                            number: self.rng.u64(..100_000),
+                        },
+                    })
+                    .collect(),
+                span,
+            }),
+            9 => Expression::Sweep(SweepDeclaration {
+                identifier: format!("p{}", self.rng.usize(..4)),
+                values: match self.rng.bool() {
+                    true => SweepValues::Range {
+                        from: self.quantity(Dimension::ALL, true),
+                        to: self.quantity(Dimension::ALL, true),
+                        step: self.quantity(Dimension::ALL, true),
+                    },
+                    false => SweepValues::List(
+                        (0..self.rng.usize(1..4))
+                            .map(|_| self.quantity(Dimension::ALL, true))
+                            .collect(),
+                    ),
+                },
+                span,
+            }),
+            _ => Expression::Include(IncludeDeclaration {
+                path: ["common.txt", "../models/glycolysis v2.txt"][self.rng.usize(..2)]
+                    .to_string(),
+                span,
+            }),
+        }
+    }
+    /// A model whose values are all numbers, so that it compares equal to itself.
+    pub fn ast(&mut self) -> Ast {
+        loop {
+            self.params.clear();
+            let mut ast = Ast((0..self.rng.usize(1..12))
+                .map(|i| self.expression(i))
+                .collect());
+            ast.resolve(&[]).unwrap();
+            if ast
+                .0
+                .iter_mut()
+                .flat_map(|e| e.values_mut())
+                .all(|v| !v.value.is_nan())
+            {
+                return ast;
+            }
+        }
+    }
+}
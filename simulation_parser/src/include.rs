@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::diagnostic::{Diagnostic, Span};
+use crate::parser_combinator::ParserInput;
+use crate::{parse_expressions, Ast, Expression};
+
+/// Text of the files a model was read from, which spans point into.
+#[derive(Debug, Default)]
+pub struct Sources {
+    /// Path and text of each file.
+    files: Vec<(String, String)>,
+}
+
+impl Sources {
+    pub fn add(&mut self, path: String, text: String) -> usize {
+        self.files.push((path, text));
+        self.files.len() - 1
+    }
+    pub fn path(&self, file: usize) -> &str {
+        &self.files[file].0
+    }
+    pub fn text(&self, file: usize) -> &str {
+        &self.files[file].1
+    }
+    /// `path:line:column` of the start of `span`.
+    pub fn location(&self, span: Span) -> String {
+        let before = &self.text(span.file)[..span.start];
+        let line = before.matches('\n').count() + 1;
+        let column = before.chars().rev().take_while(|c| *c != '\n').count() + 1;
+        format!("{}:{}:{}", self.path(span.file), line, column)
+    }
+    /// Renders `diagnostic` with an excerpt of the file it points into.
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        diagnostic.render(
+            self.text(diagnostic.span.file),
+            self.path(diagnostic.span.file),
+        )
+    }
+}
+
+struct Loader<'s> {
+    sources: &'s mut Sources,
+    /// Files being read, the innermost last, to detect include cycles.
+    stack: Vec<(PathBuf, String)>,
+    /// Files already read, which are only included once.
+    loaded: Vec<PathBuf>,
+}
+
+impl Loader<'_> {
+    fn load(&mut self, path: &Path, text: String) -> Result<Vec<Expression>, Diagnostic> {
+        let file = self.sources.add(path.display().to_string(), text);
+        let text = self.sources.text(file);
+        let expressions = parse_expressions(ParserInput::in_file(text, file))
+            .map_err(|e| {
+                let mut diagnostic = e.to_diagnostic(text);
+                diagnostic.span.file = file;
+                diagnostic
+            })?
+            .content;
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.stack
+            .push((canonical.clone(), path.display().to_string()));
+        self.loaded.push(canonical);
+
+        let mut result = vec![];
+        for expression in expressions {
+            let Expression::Include(include) = expression else {
+                result.push(expression);
+                continue;
+            };
+            let included = path.parent().unwrap_or(Path::new("")).join(&include.path);
+            let cannot_read = |error: std::io::Error| {
+                Diagnostic::error(
+                    format!("cannot read `{}`: {}", included.display(), error),
+                    include.span,
+                )
+            };
+            let canonical = fs::canonicalize(&included).map_err(cannot_read)?;
+            if let Some(i) = self.stack.iter().position(|(p, _)| *p == canonical) {
+                let chain = self.stack[i..]
+                    .iter()
+                    .map(|(_, name)| name.as_str())
+                    .chain([self.stack[i].1.as_str()])
+                    .collect::<Vec<_>>();
+                return Err(Diagnostic::error(
+                    format!("`{}` includes itself", self.stack[i].1),
+                    include.span,
+                )
+                .with_note(format!("include cycle: {}", chain.join(" -> "))));
+            }
+            if self.loaded.contains(&canonical) {
+                continue;
+            }
+            let text = fs::read_to_string(&included).map_err(cannot_read)?;
+            result.extend(self.load(&included, text)?);
+        }
+        self.stack.pop();
+        Ok(result)
+    }
+}
+
+/// Species property set by a declaration, with its value, for declarations that may only
+/// be given once.
+pub(crate) fn declared_property(
+    expression: &Expression,
+    dt: f32,
+) -> Option<(&'static str, &str, f64)> {
+    match expression {
+        Expression::InitDeclaration(i) => Some(("init", &i.identifier, i.number as f64)),
+        Expression::SpeedDeclaration(s) => {
+            Some(("vitesse", &s.identifier, s.speed.per_step(dt) as f64))
+        }
+        Expression::DiameterDeclaration(d) => {
+            Some(("diametre", &d.identifier, d.diameter.value as f64))
+        }
+        _ => None,
+    }
+}
+
+impl Ast {
+    /// Reads the model at `path`, whose content is `text`, replacing its `include`
+    /// declarations by the content of the included files. Species are shared between files:
+    /// a declaration repeated in several files is kept once, and declarations giving a
+    /// species different values are an error.
+    ///
+    /// Every file read is added to `sources`, which renders the diagnostics.
+    pub fn load(path: &str, text: String, sources: &mut Sources) -> Result<Ast, Diagnostic> {
+        let mut loader = Loader {
+            sources,
+            stack: vec![],
+            loaded: vec![],
+        };
+        let mut ast = Ast(loader.load(Path::new(path), text)?);
+        ast.resolve(&[])?;
+
+        let dt = ast.settings().dt;
+        let mut declared: HashMap<_, (f64, Span)> = HashMap::new();
+        let mut expressions = vec![];
+        for expression in ast.0 {
+            if let Some((keyword, identifier, value)) = declared_property(&expression, dt) {
+                let key = (keyword, identifier.to_string());
+                if let Some(&(first_value, first_span)) = declared.get(&key) {
+                    if first_value == value {
+                        continue;
+                    }
+                    return Err(Diagnostic::error(
+                        format!("conflicting declarations of `{keyword}({identifier})`"),
+                        expression.span(),
+                    )
+                    .with_note(format!(
+                        "previously declared as `{}` at {}",
+                        &sources.text(first_span.file)[first_span.start..first_span.end],
+                        sources.location(first_span)
+                    )));
+                }
+                declared.insert(key, (value, expression.span()));
+            }
+            expressions.push(expression);
+        }
+        Ok(Ast(expressions))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    /// A directory of its own for each test, removed when it is dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new() -> Self {
+            static COUNT: AtomicUsize = AtomicUsize::new(0);
+            let name = format!(
+                "simulation_parser_test_{}_{}",
+                std::process::id(),
+                COUNT.fetch_add(1, Ordering::Relaxed)
+            );
+            let dir = std::env::temp_dir().join(name);
+            fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn t_sources() {
+        let mut sources = Sources::default();
+        sources.add("model.txt".into(), "init(s) = 1;\n".into());
+        let file = sources.add("é.txt".into(), "// é\ninit(é) = 1;".into());
+        assert_eq!(1, file);
+        assert_eq!("model.txt:1:1", sources.location(Span::default()));
+        assert_eq!(
+            "é.txt:2:6",
+            sources.location(Span {
+                start: 11,
+                end: 13,
+                file
+            })
+        );
+        let diagnostic = Diagnostic::error("unknown species".into(), Span::new(5, 6));
+        assert!(sources.render(&diagnostic).contains("--> model.txt:1:6"));
+    }
+
+    #[test]
+    fn t_include() {
+        let temp = TempDir::new();
+        let dir = &temp.0;
+        fs::create_dir_all(dir.join("pathways")).unwrap();
+        let write = |name: &str, text: &str| fs::write(dir.join(name), text).unwrap();
+        write(
+            "pathways/glycolysis.txt",
+            "param km = 100;\ninit(glc) = 10;\nHK : glc -> g6p | km - 10;",
+        );
+        write("pathways/shared.txt", "include \"glycolysis.txt\";");
+        let load = |text: &str| {
+            let mut sources = Sources::default();
+            let path = dir.join("model.txt").display().to_string();
+            Ast::load(&path, text.to_string(), &mut sources).map_err(|d| {
+                let rendered = sources.render(&d);
+                (d, rendered)
+            })
+        };
+
+        // Included once, with species and parameters shared between files.
+        let ast = load(
+            "include \"pathways/glycolysis.txt\";
+             include \"pathways/shared.txt\";
+             init(glc) = 10;
+             PGI : g6p -> f6p | 2 * km - 10;",
+        )
+        .unwrap();
+        let Expression::Reaction(reaction) = &ast.0[3] else {
+            panic!("expected a reaction")
+        };
+        assert_eq!(200., reaction.km.value);
+        let flattened = load(
+            "param km = 100;\ninit(glc) = 10;\nHK : glc -> g6p | km - 10;\n\
+             PGI : g6p -> f6p | 2 * km - 10;",
+        )
+        .unwrap();
+        assert_eq!(flattened.without_spans(), ast.without_spans());
+
+        let (error, rendered) =
+            load("include \"pathways/glycolysis.txt\";\ninit(glc) = 20;").unwrap_err();
+        assert_eq!("conflicting declarations of `init(glc)`", error.message);
+        assert!(rendered.contains("model.txt:2:1"));
+        assert!(error.notes[0].starts_with("previously declared as `init(glc) = 10;` at"));
+        assert!(error.notes[0].ends_with("glycolysis.txt:2:1"));
+
+        write("a.txt", "include \"b.txt\";");
+        write("b.txt", "init(x) = 1;\ninclude \"a.txt\";");
+        let (error, rendered) = load("include \"a.txt\";").unwrap_err();
+        assert!(error.message.ends_with("a.txt` includes itself"));
+        assert!(rendered.contains("b.txt:2:1"));
+
+        write("broken.txt", "init(x) = ;");
+        let (_, rendered) = load("include \"broken.txt\";").unwrap_err();
+        assert!(rendered.contains("broken.txt:1:11"));
+        let (error, _) = load("include \"missing.txt\";").unwrap_err();
+        assert!(error.message.starts_with("cannot read"));
+    }
+}
@@ -0,0 +1,238 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::diagnostic::Diagnostic;
+use crate::include::Sources;
+use crate::parser_combinator::{between_spaces, literal, Parser, ParserInput, ParserResult};
+use crate::{Ast, Expression};
+
+/// Language the keywords of a model are written in. Models may mix both, unless they are
+/// checked against one with [`check_language`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Language {
+    #[default]
+    French,
+    English,
+}
+
+impl Display for Language {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Language::French => write!(f, "French"),
+            Language::English => write!(f, "English"),
+        }
+    }
+}
+
+impl FromStr for Language {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "french" | "fr" => Ok(Language::French),
+            "english" | "en" => Ok(Language::English),
+            _ => Err(format!(
+                "unknown language `{s}`, expected `french` or `english`"
+            )),
+        }
+    }
+}
+
+/// Keyword of a declaration about a species, whatever its spelling.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Keyword {
+    /// Initial number of molecules.
+    Init,
+    /// Distance travelled at each step, or diffusion coefficient when written in µm²/s.
+    Speed,
+    /// Diffusion coefficient, in µm²/s when no unit is written.
+    Diffusion,
+    /// Diameter.
+    Diameter,
+}
+
+/// A spelling of a keyword, and the languages it belongs to.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Alias {
+    pub keyword: Keyword,
+    pub spelling: &'static str,
+    pub languages: &'static [Language],
+}
+
+const fn alias(keyword: Keyword, spelling: &'static str, languages: &'static [Language]) -> Alias {
+    Alias {
+        keyword,
+        spelling,
+        languages,
+    }
+}
+
+const FRENCH: &[Language] = &[Language::French];
+const ENGLISH: &[Language] = &[Language::English];
+const BOTH: &[Language] = &[Language::French, Language::English];
+
+/// Every spelling of the keywords. The first spelling of a keyword in a language is the
+/// one models are formatted with.
+pub const ALIASES: &[Alias] = &[
+    alias(Keyword::Speed, "vitesse", FRENCH),
+    alias(Keyword::Speed, "speed", ENGLISH),
+    alias(Keyword::Diffusion, "diffusion", BOTH),
+    alias(Keyword::Init, "init", BOTH),
+    alias(Keyword::Init, "count", ENGLISH),
+    alias(Keyword::Diameter, "diametre", FRENCH),
+    alias(Keyword::Diameter, "diameter", ENGLISH),
+];
+
+impl Keyword {
+    fn aliases(self) -> impl Iterator<Item = &'static Alias> {
+        ALIASES.iter().filter(move |a| a.keyword == self)
+    }
+
+    /// The spelling of the keyword in `language`.
+    pub fn spelling(self, language: Language) -> &'static str {
+        self.aliases()
+            .find(|a| a.languages.contains(&language))
+            .expect("every keyword has a spelling in every language")
+            .spelling
+    }
+
+    /// Any spelling of the keyword.
+    pub fn parser<'a>(self) -> impl Parser<'a, ()> {
+        move |input: ParserInput<'a>| -> ParserResult<'a, ()> {
+            let mut aliases = self.aliases();
+            let first = aliases.next().expect("every keyword has a spelling");
+            let mut result = between_spaces(literal(first.spelling)).parse(input);
+            for alias in aliases {
+                result = match result {
+                    Ok(success) => return Ok(success.map(|_| ())),
+                    Err(e) => between_spaces(literal(alias.spelling))
+                        .parse(input)
+                        .map_err(|e2| e.merge(e2)),
+                };
+            }
+            result.map(|success| success.map(|_| ()))
+        }
+    }
+}
+
+/// The alias the word starting `text` is, if it is a keyword.
+pub fn alias_at(text: &str) -> Option<&'static Alias> {
+    let end = text
+        .find(|c: char| !c.is_alphanumeric())
+        .unwrap_or(text.len());
+    ALIASES.iter().find(|a| a.spelling == &text[..end])
+}
+
+/// Errors on the keywords of the declarations of `ast` that are not spelt in `language`,
+/// for projects that settled on one. Only models read from text are checked, those
+/// written as data having no keywords.
+pub fn check_language(ast: &Ast, sources: &Sources, language: Language) -> Vec<Diagnostic> {
+    ast.0
+        .iter()
+        .filter(|e| {
+            matches!(
+                e,
+                Expression::InitDeclaration(_)
+                    | Expression::SpeedDeclaration(_)
+                    | Expression::DiameterDeclaration(_)
+            )
+        })
+        .filter_map(|e| {
+            let mut span = e.span();
+            let alias = alias_at(&sources.text(span.file)[span.start..])?;
+            if alias.languages.contains(&language) {
+                return None;
+            }
+            span.end = span.start + alias.spelling.len();
+            Some(
+                Diagnostic::error(
+                    format!("`{}` is not a {language} keyword", alias.spelling),
+                    span,
+                )
+                .with_note(format!(
+                    "write `{}` instead",
+                    alias.keyword.spelling(language)
+                )),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{printer, Parsable, Speed, SpeedDeclaration};
+
+    #[test]
+    fn t_aliases() {
+        assert_eq!(Ok(Language::English), "EN".parse());
+        assert!("german".parse::<Language>().is_err());
+        assert_eq!("diametre", Keyword::Diameter.spelling(Language::French));
+        assert_eq!("speed", Keyword::Speed.spelling(Language::English));
+        assert_eq!("diffusion", Keyword::Diffusion.spelling(Language::French));
+        assert_eq!(
+            Some(Keyword::Init),
+            alias_at("count(E1) = 3;").map(|a| a.keyword)
+        );
+        assert_eq!(None, alias_at("counter(E1) = 3;"));
+        assert!(Keyword::Speed.parser().parse("speed(E1)".into()).is_ok());
+        assert!(Keyword::Speed
+            .parser()
+            .parse("diffusion(E1)".into())
+            .is_err());
+    }
+
+    #[test]
+    fn t_keywords() {
+        let parse = |text: &str| Ast::parse(text.into()).unwrap().content;
+        let french =
+            "init(E1) = 30;\nvitesse(E1) = 0.1;\ndiametre(E1) = 2 um;\nvitesse(s) = 3 um2/s;";
+        let english =
+            "count(E1) = 30;\nspeed(E1) = 0.1;\ndiameter(E1) = 2 um;\nspeed(s) = 3 um2/s;";
+        let french = parse(french).without_spans();
+        assert_eq!(french, parse(english).without_spans());
+        assert_eq!(
+            french,
+            parse(&printer::print(&parse(english), Language::French)).without_spans()
+        );
+        match &parse("diffusion(s) = 3;").0[0] {
+            Expression::SpeedDeclaration(SpeedDeclaration {
+                speed: Speed::Diffusion(d),
+                ..
+            }) => assert_eq!(3., d.value),
+            e => panic!("{e:?}"),
+        }
+
+        let model = "count(E1) = 30;\nvitesse(E1) = 0.1;\ndiffusion(s) = 3;\ndiameter(E1) = 2 um;";
+        assert_eq!(
+            "init(E1) = 30;\n\nspeed(E1)    = 0.1;\ndiffusion(s) = 3;\n\ndiameter(E1) = 2 um;\n",
+            printer::format(model, Language::English).unwrap()
+        );
+        assert_eq!(
+            "init(E1) = 30;\n\nvitesse(E1)  = 0.1;\ndiffusion(s) = 3;\n\ndiametre(E1) = 2 um;\n",
+            printer::format(model, Language::French).unwrap()
+        );
+
+        let error = Ast::parse("diffusion(E1) = 2 nm;".into()).unwrap_err();
+        assert_eq!(
+            "Expected a diffusion unit, found length unit nm",
+            error.message()
+        );
+
+        let mut sources = Sources::default();
+        let file = sources.add(
+            "mixed.txt".into(),
+            "init(E1) = 30;\n  speed(E1) = 0.1;".into(),
+        );
+        let ast = Ast::parse(sources.text(file).into()).unwrap().content;
+        assert!(check_language(&ast, &sources, Language::English).is_empty());
+        let diagnostics = check_language(&ast, &sources, Language::French);
+        assert_eq!(1, diagnostics.len());
+        assert_eq!("`speed` is not a French keyword", diagnostics[0].message);
+        assert_eq!(vec!["write `vitesse` instead"], diagnostics[0].notes);
+        assert_eq!("mixed.txt:2:3", sources.location(diagnostics[0].span));
+
+        assert_eq!(Ok(Language::English), "en".parse());
+        assert!("german".parse::<Language>().is_err());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::settings::Settings;
+
+/// Number of molecules per nm³ in a 1 µM solution.
+const MOLECULES_PER_NM3_PER_MICROMOLAR: f32 = 6.022e-7;
+
+/// Collision factor of two molecules in a sphere of radius 500 nm.
+const ALPHA: f32 = 7.4e-7;
+
+/// Volume of the sphere [`ALPHA`] is the collision factor in, in nm³.
+const ALPHA_VOLUME: f32 = 4. / 3. * std::f32::consts::PI * 500. * 500. * 500.;
+
+/// Number of molecules making a concentration of 1 µM in the simulated sphere.
+pub fn molecules_per_micromolar(settings: &Settings) -> f32 {
+    molecules_per_micromolar_in(settings.volume())
+}
+
+/// Number of molecules making a concentration of 1 µM in `volume` nm³, such as that of a
+/// compartment.
+pub fn molecules_per_micromolar_in(volume: f32) -> f32 {
+    volume * MOLECULES_PER_NM3_PER_MICROMOLAR
+}
+
+/// Collision factor of two molecules in the simulated sphere: collisions get rarer as the
+/// volume grows.
+pub fn alpha(settings: &Settings) -> f32 {
+    alpha_in(settings.volume())
+}
+
+/// Collision factor of two molecules sharing `volume` nm³, such as those of a compartment.
+pub fn alpha_in(volume: f32) -> f32 {
+    ALPHA * ALPHA_VOLUME / volume
+}
+
+/// Per step probabilities `(P1, P2, P3)` of an enzymatic reaction with the Michaelis
+/// constant `km` (µM) and turnover number `kcat` (s⁻¹), for steps of `dt` s: P1 for a
+/// collision of the enzyme with its substrate to form the complex, P2 for the complex to
+/// release the substrate and P3 for it to release the products.
+///
+/// ```text
+/// P3 = kcat dt
+/// P2 = P3 / 10
+/// P1 = (P2 + P3) / (0.448 (1 + (P2 + P3)²) Km)
+/// ```
+///
+/// The fit (see proba.txt) gives P1 ≥ 1 when kcat ≥ 300 and Km ≤ 80 µM, where P1 is 1.
+pub fn calc_probability(km: f32, kcat: f32, dt: f32) -> (f32, f32, f32) {
+    let p3 = kcat * dt;
+    let p2 = p3 / 10.;
+    let p1 = if kcat >= 300. && km <= 80. {
+        1.
+    } else {
+        (p2 + p3) / (0.448 * (1. + (p2 + p3).powi(2)) * km)
+    };
+    (p1, p2, p3)
+}
+
+/// Rate constants of the elementary steps the Gillespie simulation expands an enzymatic
+/// reaction into, from the probabilities of [`calc_probability`]: binding of a substrate
+/// to the enzyme in µM⁻¹·s⁻¹, and release of the substrate and of the products in s⁻¹.
+pub fn elementary_rate_constants(km: f32, kcat: f32, settings: &Settings) -> (f32, f32, f32) {
+    let (p1, p2, p3) = calc_probability(km, kcat, settings.dt);
+    (
+        p1 * alpha(settings) * molecules_per_micromolar(settings) / settings.dt,
+        p2 / settings.dt,
+        p3 / settings.dt,
+    )
+}
+
+/// Per step probabilities `(P_on, P_off)` of a species binding a form of an enzyme with
+/// the dissociation constant `k` (µM), such as an inhibitor: it binds on collision as the
+/// substrates of the reaction with the Michaelis constant `km` and turnover number `kcat`
+/// do, with P1, and unbinds with `P_off = k α n P1`, n being the molecules per µM, so that
+/// `k` is the ratio of the unbinding and binding propensities.
+pub fn binding_probabilities(k: f32, km: f32, kcat: f32, settings: &Settings) -> (f32, f32) {
+    let (p1, _, _) = calc_probability(km, kcat, settings.dt);
+    (
+        p1,
+        k * alpha(settings) * molecules_per_micromolar(settings) * p1,
+    )
+}
+
+/// Rate constants of the binding, in µM⁻¹·s⁻¹, and unbinding, in s⁻¹, of the probabilities
+/// of [`binding_probabilities`].
+pub fn binding_rate_constants(k: f32, km: f32, kcat: f32, settings: &Settings) -> (f32, f32) {
+    let (p_on, p_off) = binding_probabilities(k, km, kcat, settings);
+    (
+        p_on * alpha(settings) * molecules_per_micromolar(settings) / settings.dt,
+        p_off / settings.dt,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() <= 1e-5 * a.abs().max(b.abs())
+    }
+
+    #[test]
+    fn t_probabilities() {
+        let (p1, p2, p3) = calc_probability(100., 10., 1e-4);
+        assert!(close(1e-3, p3));
+        assert!(close(1e-4, p2));
+        assert!(close(1.1e-3 / (0.448 * (1. + 1.1e-3 * 1.1e-3) * 100.), p1));
+        assert_eq!(1., calc_probability(80., 300., 1e-4).0);
+        assert!(calc_probability(81., 300., 1e-4).0 < 1.);
+
+        let settings = Settings::default();
+        assert!(close(ALPHA, alpha(&settings)));
+        assert!(close(ALPHA / 2., alpha_in(2. * settings.volume())));
+        let (k_on, k_off, kcat) = elementary_rate_constants(100., 10., &settings);
+        assert!(close(1., k_off));
+        assert!(close(10., kcat));
+        assert!(close(
+            p1 * ALPHA * molecules_per_micromolar(&settings) / 1e-4,
+            k_on
+        ));
+        // The dissociation constant is the ratio of the unbinding and binding rates.
+        let (k_on, k_off) = binding_rate_constants(5., 100., 10., &settings);
+        assert!(close(5., k_off / k_on));
+    }
+}
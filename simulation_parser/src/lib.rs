@@ -1,12 +1,67 @@
+use std::collections::HashMap;
+
+use brenda::BrendaQuery;
 use brenda_parser_helpers::*;
+use compartments::{compartment_of, CompartmentDeclaration};
+use diagnostic::{Diagnostic, Span};
+use events::{EventDeclaration, Trigger};
+use formula::Value;
+use keywords::Keyword;
 use parser_combinator::*;
+use serde::{Deserialize, Serialize};
+use settings::{Settings, SimulationBlock};
+use sweep::SweepDeclaration;
+use units::{quantity, Dimension};
+pub mod brenda;
 mod brenda_parser_helpers;
+pub mod check;
+pub mod compartments;
+pub mod diagnostic;
+pub mod events;
+pub mod expanded;
+pub mod formats;
+pub mod formula;
+#[cfg(test)]
+mod generator;
+pub mod include;
+pub mod keywords;
+pub mod kinetics;
 pub mod parser_combinator;
+pub mod printer;
+pub mod sbml;
+pub mod settings;
+pub mod sweep;
+pub mod thermodynamics;
+pub mod units;
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Speed {
+    /// Distance travelled at each simulation step, in nm.
+    Step(Value),
+    /// Diffusion coefficient, in µm²/s.
+    Diffusion(Value),
+}
+
+impl Speed {
+    /// Distance travelled at each step of `dt` seconds, in nm. A diffusion coefficient
+    /// is turned into the root mean square displacement `sqrt(6 D dt)`.
+    pub fn per_step(&self, dt: f32) -> f32 {
+        match self {
+            Speed::Step(x) => x.value,
+            Speed::Diffusion(d) => (6. * d.value * 1e6 * dt).sqrt(),
+        }
+    }
+}
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct SpeedDeclaration {
     pub identifier: String,
-    pub speed: f32,
+    /// Serialized as a `step` or `diffusion` field.
+    #[serde(flatten)]
+    pub speed: Speed,
+    #[serde(skip)]
+    pub span: Span,
 }
 
 pub trait Parsable: Sized {
@@ -15,121 +70,363 @@ pub trait Parsable: Sized {
 
 impl Parsable for SpeedDeclaration {
     fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
-        let ParserSuccess { next_input, .. } = parse_speed().parse(text)?;
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess {
+            content: keyword,
+            next_input,
+        } = parse_speed().parse(start)?;
         let ParserSuccess { next_input, .. } = parse_lparen().parse(next_input)?;
         let ParserSuccess {
             content: identifier,
             next_input,
-        } = parse_identifier().parse(next_input)?;
+        } = parse_species_identifier().parse(next_input)?;
         let ParserSuccess { next_input, .. } = parse_rparen().parse(next_input)?;
         let ParserSuccess { next_input, .. } = parse_equal().parse(next_input)?;
         let ParserSuccess {
             content: speed,
             next_input,
-        } = parse_float().parse(next_input)?;
+        } = match keyword {
+            Keyword::Diffusion => parse_diffusion().parse(next_input)?,
+            _ => parse_motion().parse(next_input)?,
+        };
         let ParserSuccess { next_input, .. } = parse_semicolon().parse(next_input)?;
         Ok(ParserSuccess {
-            content: SpeedDeclaration { identifier, speed },
+            content: SpeedDeclaration {
+                identifier,
+                speed,
+                span: next_input.span_from(start),
+            },
             next_input,
         })
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct DiameterDeclaration {
     pub identifier: String,
-    pub diameter: f32,
+    /// In nm.
+    pub diameter: Value,
+    #[serde(skip)]
+    pub span: Span,
 }
 
 impl Parsable for DiameterDeclaration {
     fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
-        let ParserSuccess { next_input, .. } = parse_diameter().parse(text)?;
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess { next_input, .. } = parse_diameter().parse(start)?;
         let ParserSuccess { next_input, .. } = parse_lparen().parse(next_input)?;
         let ParserSuccess {
             content: identifier,
             next_input,
-        } = parse_identifier().parse(next_input)?;
+        } = parse_species_identifier().parse(next_input)?;
         let ParserSuccess { next_input, .. } = parse_rparen().parse(next_input)?;
         let ParserSuccess { next_input, .. } = parse_equal().parse(next_input)?;
         let ParserSuccess {
             content: diameter,
             next_input,
-        } = parse_float_ranged(0., 1.).parse(next_input)?;
+        } = parse_length().parse(next_input)?;
         let ParserSuccess { next_input, .. } = parse_semicolon().parse(next_input)?;
         Ok(ParserSuccess {
             content: DiameterDeclaration {
                 identifier,
                 diameter,
+                span: next_input.span_from(start),
             },
             next_input,
         })
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct InitDeclaration {
     pub identifier: String,
-    pub number: u32,
+    pub number: u64,
+    #[serde(skip)]
+    pub span: Span,
 }
 
 impl Parsable for InitDeclaration {
     fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
-        let ParserSuccess { next_input, .. } = parse_init().parse(text)?;
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess { next_input, .. } = parse_init().parse(start)?;
         let ParserSuccess { next_input, .. } = parse_lparen().parse(next_input)?;
         let ParserSuccess {
             content: identifier,
             next_input,
-        } = parse_identifier().parse(next_input)?;
+        } = parse_species_identifier().parse(next_input)?;
         let ParserSuccess { next_input, .. } = parse_rparen().parse(next_input)?;
         let ParserSuccess { next_input, .. } = parse_equal().parse(next_input)?;
         let ParserSuccess {
             content: number,
             next_input,
-        } = parse_uint().parse(next_input)?;
+        } = parse_count().parse(next_input)?;
         let ParserSuccess { next_input, .. } = parse_semicolon().parse(next_input)?;
         Ok(ParserSuccess {
-            content: Self { identifier, number },
+            content: Self {
+                identifier,
+                number,
+                span: next_input.span_from(start),
+            },
             next_input,
         })
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
 pub struct Reaction {
+    #[serde(rename = "enzyme")]
     pub enzhym: String,
-    pub solubes: String,
-    pub results: String,
-    pub km: f32,
-    pub kcat: f32,
+    #[serde(rename = "substrates")]
+    pub solubes: Vec<(String, u32)>,
+    #[serde(rename = "products")]
+    pub results: Vec<(String, u32)>,
+    /// Michaelis constant, in µM. Missing until looked up when it comes from BRENDA.
+    #[serde(default = "Value::missing", skip_serializing_if = "Value::is_missing")]
+    pub km: Value,
+    /// Turnover number, in s⁻¹. Missing until looked up when it comes from BRENDA.
+    #[serde(default = "Value::missing", skip_serializing_if = "Value::is_missing")]
+    pub kcat: Value,
+    /// Kinetics of the reverse reaction, for reactions written with `<->`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reverse: Option<ReverseKinetics>,
+    /// Where the Km and kcat are looked up, for reactions written with `brenda(...)`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brenda: Option<BrendaQuery>,
+    /// Inhibitors and activators of the enzyme, written after the kinetics.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub modifiers: Vec<Modifier>,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ReverseKinetics {
+    /// Michaelis constant of the products, in µM.
+    pub km: Value,
+    /// Turnover number from products to substrates, in s⁻¹.
+    pub kcat: Value,
+    /// Equilibrium constant the kinetics are expected to agree with.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keq: Option<Value>,
+}
+
+impl Parsable for ReverseKinetics {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess { next_input, .. } = parse_comma().parse(text)?;
+        let ParserSuccess {
+            content: km,
+            next_input,
+        } = parse_concentration().parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_dash().parse(next_input)?;
+        let ParserSuccess {
+            content: kcat,
+            next_input,
+        } = parse_rate().parse(next_input)?;
+        let (keq, next_input) = match parse_comma().parse(next_input) {
+            Ok(ParserSuccess { next_input, .. }) => {
+                let ParserSuccess { next_input, .. } = parse_keq().parse(next_input)?;
+                let ParserSuccess { next_input, .. } = parse_equal().parse(next_input)?;
+                let ParserSuccess {
+                    content: keq,
+                    next_input,
+                } = parse_number().parse(next_input)?;
+                (Some(keq), next_input)
+            }
+            Err(_) => (None, next_input),
+        };
+        Ok(ParserSuccess {
+            content: Self { km, kcat, keq },
+            next_input,
+        })
+    }
+}
+
+/// How a species binding the enzyme changes the rate of a reaction.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "mechanism", rename_all = "snake_case")]
+pub enum Mechanism {
+    /// Binds the free enzyme, competing with the substrates. `ki` is in µM.
+    Competitive { ki: Value },
+    /// Binds the enzyme once every substrate is bound. `ki` is in µM.
+    Uncompetitive { ki: Value },
+    /// Binds the free enzyme with `ki` (µM), and the enzyme holding every substrate with
+    /// `alpha` times `ki`.
+    Mixed { ki: Value, alpha: Value },
+    /// A further molecule of a substrate binding the enzyme once every substrate is bound,
+    /// which blocks the catalysis until it leaves. `ki` is in µM.
+    Substrate { ki: Value },
+    /// Allosteric activation: binds the free enzyme with `ka` (µM), which then catalyses
+    /// the reaction `beta` times faster.
+    Activation { ka: Value, beta: Value },
+}
+
+/// `inhibited_by I (Ki = 5 uM, competitive)` or `activated_by A (Ka = 2 uM, beta = 3)`.
+/// Mixed inhibitors also give the factor applying to Ki when the substrates are bound:
+/// `inhibited_by I (Ki = 5 uM, mixed, alpha = 4)`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Modifier {
+    pub species: String,
+    #[serde(flatten)]
+    pub mechanism: Mechanism,
+}
+
+impl Parsable for Modifier {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess {
+            content: activator,
+            next_input,
+        } = parse_inhibited_by()
+            .map(|_| false)
+            .or_else(parse_activated_by().map(|_| true))
+            .parse(text)?;
+        cut(move |input: ParserInput<'a>| -> ParserResult<'a, Self> {
+            let ParserSuccess {
+                content: species,
+                next_input,
+            } = parse_species_identifier().parse(input)?;
+            let ParserSuccess { next_input, .. } = parse_lparen().parse(next_input)?;
+            let ParserSuccess { next_input, .. } = if activator {
+                parse_ka().parse(next_input)?
+            } else {
+                parse_ki().parse(next_input)?
+            };
+            let ParserSuccess { next_input, .. } = parse_equal().parse(next_input)?;
+            let ParserSuccess {
+                content: k,
+                next_input,
+            } = parse_concentration().parse(next_input)?;
+            let ParserSuccess { next_input, .. } = parse_comma().parse(next_input)?;
+            let ParserSuccess {
+                content: mechanism,
+                next_input,
+            } = if activator {
+                parse_beta()
+                    .skip_me(parse_equal())
+                    .skip_me(parse_number())
+                    .map(|beta| Mechanism::Activation {
+                        ka: k.clone(),
+                        beta,
+                    })
+                    .parse(next_input)?
+            } else {
+                choice((
+                    literal("competitive").map(|_| Mechanism::Competitive { ki: k.clone() }),
+                    literal("uncompetitive").map(|_| Mechanism::Uncompetitive { ki: k.clone() }),
+                    literal("substrate").map(|_| Mechanism::Substrate { ki: k.clone() }),
+                    literal("mixed")
+                        .skip_me(parse_comma())
+                        .skip_me(parse_alpha())
+                        .skip_me(parse_equal())
+                        .skip_me(parse_number())
+                        .map(|alpha| Mechanism::Mixed {
+                            ki: k.clone(),
+                            alpha,
+                        }),
+                ))
+                .label("inhibition mechanism")
+                .parse(next_input)?
+            };
+            let ParserSuccess { next_input, .. } = parse_rparen().parse(next_input)?;
+            Ok(ParserSuccess {
+                content: Self { species, mechanism },
+                next_input,
+            })
+        })
+        .parse(next_input)
+    }
+}
+
+impl Modifier {
+    fn values_mut(&mut self) -> Vec<&mut Value> {
+        match &mut self.mechanism {
+            Mechanism::Competitive { ki }
+            | Mechanism::Uncompetitive { ki }
+            | Mechanism::Substrate { ki } => vec![ki],
+            Mechanism::Mixed { ki, alpha } => vec![ki, alpha],
+            Mechanism::Activation { ka, beta } => vec![ka, beta],
+        }
+    }
+}
+
+/// A species reversibly binding a form of an enzyme, `E + I <-> E·I`, as the inhibitors
+/// and activators of a reaction do.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Binding {
+    /// The enzyme or enzyme-substrate complex the modifier binds.
+    pub site: String,
+    pub modifier: String,
+    /// Name of the complex formed, such as `E1·I` or `E1--s·I`.
+    pub complex: String,
+    /// Dissociation constant, in µM.
+    pub k: f32,
 }
 
 impl Parsable for Reaction {
     fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
         let ParserSuccess {
             content: enzhym,
             next_input,
-        } = parse_identifier().parse(text)?;
+        } = parse_species_identifier().parse(start)?;
         let ParserSuccess { next_input, .. } = parse_colon().parse(next_input)?;
         let ParserSuccess {
             content: solubes,
             next_input,
-        } = parse_identifier().parse(next_input)?;
-        let ParserSuccess { next_input, .. } = parse_arrow().parse(next_input)?;
+        } = parse_solubes_and_results().parse(next_input)?;
         let ParserSuccess {
-            content: results,
+            content: reversible,
             next_input,
-        } = parse_identifier().parse(next_input)?;
-        let ParserSuccess { next_input, .. } = parse_pipe().parse(next_input)?;
+        } = parse_arrow()
+            .map(|_| false)
+            .or_else(parse_reversible_arrow().map(|_| true))
+            .parse(next_input)?;
         let ParserSuccess {
-            content: km,
+            content: results,
             next_input,
-        } = parse_float().parse(next_input)?;
-        let ParserSuccess { next_input, .. } = parse_uN().parse(next_input)?;
-        let ParserSuccess { next_input, .. } = parse_dash().parse(next_input)?;
+        } = parse_solubes_and_results().parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_pipe().parse(next_input)?;
+        let (km, kcat, brenda, next_input) = match BrendaQuery::parse(next_input) {
+            Ok(ParserSuccess {
+                content: query,
+                next_input,
+            }) => (Value::missing(), Value::missing(), Some(query), next_input),
+            Err(e) if e.committed => return Err(e),
+            Err(e) => {
+                let ParserSuccess {
+                    content: km,
+                    next_input,
+                } = parse_concentration()
+                    .parse(next_input)
+                    .map_err(|e2| e.merge(e2))?;
+                let ParserSuccess { next_input, .. } = parse_dash().parse(next_input)?;
+                let ParserSuccess {
+                    content: kcat,
+                    next_input,
+                } = parse_rate().parse(next_input)?;
+                (km, kcat, None, next_input)
+            }
+        };
+        let (reverse, next_input) = if reversible {
+            let ParserSuccess {
+                content: reverse,
+                next_input,
+            } = ReverseKinetics::parse(next_input)?;
+            (Some(reverse), next_input)
+        } else {
+            (None, next_input)
+        };
         let ParserSuccess {
-            content: kcat,
+            content: modifiers,
             next_input,
-        } = parse_float().parse(next_input)?;
+        } = Modifier::parse.zero_or_more().parse(next_input)?;
         let ParserSuccess { next_input, .. } = parse_semicolon().parse(next_input)?;
         Ok(ParserSuccess {
             next_input,
@@ -139,47 +436,559 @@ impl Parsable for Reaction {
                 results,
                 km,
                 kcat,
+                reverse,
+                brenda,
+                modifiers,
+                span: next_input.span_from(start),
+            },
+        })
+    }
+}
+
+impl Reaction {
+    /// The reverse reaction of a `<->` reaction, with substrates and products swapped.
+    pub fn reversed(&self) -> Option<Reaction> {
+        self.reverse.as_ref().map(|reverse| Reaction {
+            enzhym: self.enzhym.clone(),
+            solubes: self.results.clone(),
+            results: self.solubes.clone(),
+            km: reverse.km.clone(),
+            kcat: reverse.kcat.clone(),
+            reverse: None,
+            brenda: None,
+            modifiers: vec![],
+            span: self.span,
+        })
+    }
+    /// The reactions the enzyme catalyses: this one without its modifiers, the reverse one
+    /// of a `<->` reaction, and those of the enzyme bound to each activator, named after
+    /// the complex such as `E1·A` and `beta` times faster.
+    pub fn catalysed(&self) -> Vec<Reaction> {
+        let forward = Reaction {
+            modifiers: vec![],
+            ..self.clone()
+        };
+        let mut reactions = vec![];
+        for m in &self.modifiers {
+            if let Mechanism::Activation { beta, .. } = &m.mechanism {
+                let faster = |kcat: &Value| Value::from(kcat.value * beta.value);
+                reactions.push(Reaction {
+                    enzhym: format!("{}·{}", self.enzhym, m.species),
+                    kcat: faster(&self.kcat),
+                    reverse: self.reverse.as_ref().map(|reverse| ReverseKinetics {
+                        kcat: faster(&reverse.kcat),
+                        ..reverse.clone()
+                    }),
+                    ..forward.clone()
+                });
+            }
+        }
+        reactions.insert(0, forward);
+        reactions
+            .into_iter()
+            .flat_map(|r| {
+                let reversed = r.reversed();
+                std::iter::once(r).chain(reversed)
+            })
+            .collect()
+    }
+    /// The bindings of the inhibitors and activators. Competitive inhibitors and activators
+    /// bind the free enzyme, uncompetitive and substrate inhibitors the complex holding
+    /// every substrate, and mixed inhibitors both.
+    pub fn bindings(&self) -> Vec<Binding> {
+        let full = self.complex(self.solube_units().count());
+        let binding = |site: &str, modifier: &str, k: f32| Binding {
+            site: site.to_string(),
+            modifier: modifier.to_string(),
+            complex: format!("{site}·{modifier}"),
+            k,
+        };
+        let mut bindings = vec![];
+        for m in &self.modifiers {
+            match &m.mechanism {
+                Mechanism::Competitive { ki } => {
+                    bindings.push(binding(&self.enzhym, &m.species, ki.value))
+                }
+                Mechanism::Uncompetitive { ki } | Mechanism::Substrate { ki } => {
+                    bindings.push(binding(&full, &m.species, ki.value))
+                }
+                Mechanism::Mixed { ki, alpha } => {
+                    bindings.push(binding(&self.enzhym, &m.species, ki.value));
+                    bindings.push(binding(&full, &m.species, alpha.value * ki.value));
+                }
+                Mechanism::Activation { ka, .. } => {
+                    bindings.push(binding(&self.enzhym, &m.species, ka.value))
+                }
+            }
+        }
+        bindings
+    }
+    /// The compartment the reaction happens in, that of its enzyme.
+    pub fn compartment(&self) -> Option<&str> {
+        compartment_of(&self.enzhym)
+    }
+    /// Whether the Km and kcat come from BRENDA but were not looked up yet.
+    pub fn awaits_brenda(&self) -> bool {
+        self.brenda.is_some() && (self.km.is_missing() || self.kcat.is_missing())
+    }
+    /// Equilibrium constant implied by the kinetics of a `<->` reaction through the Haldane
+    /// relationship `Keq = (kcat_f Km_r) / (kcat_r Km_f)`.
+    pub fn haldane_keq(&self) -> Option<f32> {
+        self.reverse.as_ref().map(|reverse| {
+            (self.kcat.value * reverse.km.value) / (reverse.kcat.value * self.km.value)
+        })
+    }
+    /// Substrates one molecule at a time, in binding order: `2 A + B` gives `A, A, B`.
+    pub fn solube_units(&self) -> impl Iterator<Item = &String> {
+        stoichiometric_units(&self.solubes)
+    }
+    /// Products one molecule at a time: `A + 2 B` gives `A, B, B`.
+    pub fn result_units(&self) -> impl Iterator<Item = &String> {
+        stoichiometric_units(&self.results)
+    }
+    /// Name of the complex formed once the enzyme has bound the first `bound` substrate
+    /// molecules. Substrates are bound in declaration order, so `E : A + B -> P` goes
+    /// through `E--A` then `E--A--B`, and `E : 2 A -> P` through `E--A` then `E--A--A`.
+    pub fn complex(&self, bound: usize) -> String {
+        if bound == 0 {
+            self.enzhym.clone()
+        } else {
+            let bound = self
+                .solube_units()
+                .take(bound)
+                .map(String::as_str)
+                .collect::<Vec<_>>();
+            format!("{}--{}", self.enzhym, bound.join("--"))
+        }
+    }
+}
+
+fn stoichiometric_units(species: &[(String, u32)]) -> impl Iterator<Item = &String> {
+    species
+        .iter()
+        .flat_map(|(identifier, n)| std::iter::repeat_n(identifier, *n as usize))
+}
+
+/// Reaction without an enzyme, following the law of mass action. An empty side is
+/// written `0`, for synthesis (`0 -> A`) and decay (`A -> 0`).
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct MassActionReaction {
+    pub reactants: Vec<(String, u32)>,
+    pub products: Vec<(String, u32)>,
+    /// Rate constant, in µM/s, s⁻¹ or µM⁻¹·s⁻¹ for zeroth, first and second order
+    /// reactions.
+    pub k: Value,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+impl Parsable for MassActionReaction {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess {
+            content: reactants,
+            next_input,
+        } = parse_species_or_nothing().parse(start)?;
+        let ParserSuccess { next_input, .. } = parse_arrow().parse(next_input)?;
+        let ParserSuccess {
+            content: products,
+            next_input,
+        } = parse_species_or_nothing().parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_at().parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_k().parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_equal().parse(next_input)?;
+        let dimension: &'static [Dimension] = match stoichiometric_units(&reactants).count() {
+            0 => &[Dimension::Flux],
+            1 => &[Dimension::Rate],
+            2 => &[Dimension::BimolecularRate],
+            _ => {
+                return Err(start.generate_error(
+                    "Mass-action reactions can have at most two reactant molecules".to_string(),
+                ))
+            }
+        };
+        let ParserSuccess {
+            content: (k, _),
+            next_input,
+        } = quantity(dimension, true).parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_semicolon().parse(next_input)?;
+        Ok(ParserSuccess {
+            content: Self {
+                reactants,
+                products,
+                k,
+                span: next_input.span_from(start),
             },
+            next_input,
         })
     }
 }
 
-#[derive(Debug, PartialEq)]
+impl MassActionReaction {
+    /// Reactants one molecule at a time.
+    pub fn reactant_units(&self) -> impl Iterator<Item = &String> {
+        stoichiometric_units(&self.reactants)
+    }
+    /// Products one molecule at a time.
+    pub fn product_units(&self) -> impl Iterator<Item = &String> {
+        stoichiometric_units(&self.products)
+    }
+    /// The compartment the reaction happens in: that of its reactants, or of its products
+    /// for a synthesis.
+    pub fn compartment(&self) -> Option<&str> {
+        self.reactant_units()
+            .chain(self.product_units())
+            .next()
+            .and_then(|species| compartment_of(species))
+    }
+}
+
+/// Named number that formulas can refer to, such as `param km1 = 120 uM;`. Its value can
+/// be overridden without editing the model, see [`Ast::resolve`].
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct ParamDeclaration {
+    pub identifier: String,
+    /// In the canonical unit of its dimension, when it has a unit.
+    pub value: Value,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+impl Parsable for ParamDeclaration {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess { next_input, .. } = parse_param().parse(start)?;
+        let ParserSuccess {
+            content: identifier,
+            next_input,
+        } = parse_identifier().parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_equal().parse(next_input)?;
+        let ParserSuccess {
+            content: (value, _),
+            next_input,
+        } = quantity(Dimension::ALL, true).parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_semicolon().parse(next_input)?;
+        Ok(ParserSuccess {
+            content: Self {
+                identifier,
+                value,
+                span: next_input.span_from(start),
+            },
+            next_input,
+        })
+    }
+}
+
+/// `include "glycolysis.txt";`, replaced by the content of the file by [`Ast::load`].
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct IncludeDeclaration {
+    /// Relative to the directory of the including file.
+    pub path: String,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+impl Parsable for IncludeDeclaration {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess { next_input, .. } = parse_include().parse(start)?;
+        let ParserSuccess {
+            content: path,
+            next_input,
+        } = parse_string().parse(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_semicolon().parse(next_input)?;
+        Ok(ParserSuccess {
+            content: Self {
+                path,
+                span: next_input.span_from(start),
+            },
+            next_input,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
 pub struct Ast(pub Vec<Expression>);
 
+impl Ast {
+    /// Evaluates every formula of the model. A parameter takes its value from `overrides`
+    /// when it is listed there, and may only refer to parameters declared before it.
+    /// Overriding a parameter the model does not declare is an error, shown at the start of
+    /// the model since overrides are not written in it.
+    pub fn resolve(&mut self, overrides: &[(String, f64)]) -> Result<(), Diagnostic> {
+        if let Some((name, _)) = overrides
+            .iter()
+            .find(|(name, _)| self.param(name).is_none())
+        {
+            return Err(Diagnostic::error(
+                format!("the model declares no parameter `{name}`"),
+                Span::default(),
+            )
+            .with_note("only declared parameters can be given another value".into()));
+        }
+        let mut params = HashMap::new();
+        for expression in &mut self.0 {
+            if let Expression::Param(param) = expression {
+                match overrides
+                    .iter()
+                    .rev()
+                    .find(|(name, _)| *name == param.identifier)
+                {
+                    Some((_, value)) => param.value.value = *value as f32,
+                    None => param.value.resolve(&params)?,
+                }
+                params.insert(param.identifier.clone(), param.value.value as f64);
+            }
+        }
+        for expression in &mut self.0 {
+            if !matches!(expression, Expression::Param(_)) {
+                for value in expression.values_mut() {
+                    value.resolve(&params)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// The same model with every span reset, to compare models wherever they were written.
+    pub fn without_spans(&self) -> Ast {
+        Ast(self.0.iter().map(Expression::without_spans).collect())
+    }
+    /// Settings of the `simulation` blocks, a later block overriding the earlier ones.
+    pub fn settings(&self) -> Settings {
+        let mut settings = Settings::default();
+        for expression in &self.0 {
+            if let Expression::Simulation(block) = expression {
+                settings.apply(block);
+            }
+        }
+        settings
+    }
+    pub fn sweeps(&self) -> impl Iterator<Item = &SweepDeclaration> {
+        self.0.iter().filter_map(|e| match e {
+            Expression::Sweep(s) => Some(s),
+            _ => None,
+        })
+    }
+    pub fn param(&self, identifier: &str) -> Option<&ParamDeclaration> {
+        self.0.iter().find_map(|e| match e {
+            Expression::Param(p) if p.identifier == identifier => Some(p),
+            _ => None,
+        })
+    }
+}
+
+/// Expressions up to the end of `text`, with their formulas left unresolved.
+fn parse_expressions<'a>(text: ParserInput<'a>) -> ParserResult<'a, Vec<Expression>> {
+    // Parsed by hand rather than with `one_or_more` so that the error of the expression
+    // that failed is reported instead of a missing end of file.
+    let ParserSuccess {
+        content: first,
+        mut next_input,
+    } = Expression::parse(text)?;
+    let mut expressions = vec![first];
+    while let Err(eof_error) = parse_eof().parse(next_input) {
+        let ParserSuccess {
+            content,
+            next_input: rest,
+        } = Expression::parse(next_input).map_err(|e| e.merge(eof_error))?;
+        expressions.push(content);
+        next_input = rest;
+    }
+    Ok(ParserSuccess {
+        content: expressions,
+        next_input,
+    })
+}
+
 impl Parsable for Ast {
+    /// Parses a lone model, whose `include` declarations are left as they are: see
+    /// [`Ast::load`] to read them too.
     fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
-        Expression::parse
-            .one_or_more()
-            .map(Self)
-            .skip_next(parse_eof())
-            .parse(text)
+        let ParserSuccess {
+            content: expressions,
+            next_input,
+        } = parse_expressions(text)?;
+        let mut ast = Self(expressions);
+        ast.resolve(&[])
+            .map_err(|d| text.at(d.span.start).generate_error(d.message))?;
+        Ok(ParserSuccess {
+            content: ast,
+            next_input,
+        })
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Serialized with a `kind` field naming the expression: `reaction`, `mass_action`,
+/// `speed`, `init`, `diameter`, `param`, `sweep`, `simulation`, `compartment`, `event` or
+/// `include`.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
 pub enum Expression {
-    Reaction(Reaction),
+    Reaction(Box<Reaction>),
+    MassAction(MassActionReaction),
+    #[serde(rename = "speed")]
     SpeedDeclaration(SpeedDeclaration),
+    #[serde(rename = "init")]
     InitDeclaration(InitDeclaration),
+    #[serde(rename = "diameter")]
     DiameterDeclaration(DiameterDeclaration),
+    Param(ParamDeclaration),
+    Sweep(SweepDeclaration),
+    Simulation(SimulationBlock),
+    Compartment(CompartmentDeclaration),
+    Event(EventDeclaration),
+    Include(IncludeDeclaration),
+}
+
+/// The declaration the keyword starting `text` introduces, parsed without trying the
+/// other expressions.
+fn declaration(text: ParserInput<'_>) -> Option<ParserResult<'_, Expression>> {
+    let ParserSuccess { next_input, .. } = space0().parse(text).ok()?;
+    let rest = next_input.rest();
+    let word = &rest[..rest
+        .find(|c: char| !c.is_alphanumeric())
+        .unwrap_or(rest.len())];
+    Some(match (word, keywords::alias_at(word).map(|a| a.keyword)) {
+        (_, Some(Keyword::Init)) => InitDeclaration::parse
+            .map(Expression::InitDeclaration)
+            .parse(text),
+        (_, Some(Keyword::Speed | Keyword::Diffusion)) => SpeedDeclaration::parse
+            .map(Expression::SpeedDeclaration)
+            .parse(text),
+        (_, Some(Keyword::Diameter)) => DiameterDeclaration::parse
+            .map(Expression::DiameterDeclaration)
+            .parse(text),
+        ("param", _) => ParamDeclaration::parse.map(Expression::Param).parse(text),
+        ("sweep", _) => SweepDeclaration::parse.map(Expression::Sweep).parse(text),
+        ("simulation", _) => SimulationBlock::parse
+            .map(Expression::Simulation)
+            .parse(text),
+        ("compartment", _) => CompartmentDeclaration::parse
+            .map(Expression::Compartment)
+            .parse(text),
+        ("at" | "when", _) => EventDeclaration::parse.map(Expression::Event).parse(text),
+        ("include", _) => IncludeDeclaration::parse
+            .map(Expression::Include)
+            .parse(text),
+        _ => return None,
+    })
 }
 
 impl Parsable for Expression {
     fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
-        Reaction::parse(text)
-            .map(|c| c.map(Expression::Reaction))
-            .or(SpeedDeclaration::parse(text).map(|c| c.map(Expression::SpeedDeclaration)))
-            .or(InitDeclaration::parse(text).map(|c| c.map(Expression::InitDeclaration)))
-            .or(DiameterDeclaration::parse(text).map(|c| c.map(Expression::DiameterDeclaration)))
+        // Declarations start with their keyword. A species may be named after one, and
+        // the errors are those of every expression, so all of them are only tried when
+        // the declaration does not parse.
+        if let Some(Ok(declaration)) = declaration(text) {
+            return Ok(declaration);
+        }
+        choice((
+            Reaction::parse.map(|r| Expression::Reaction(Box::new(r))),
+            MassActionReaction::parse.map(Expression::MassAction),
+            SpeedDeclaration::parse.map(Expression::SpeedDeclaration),
+            InitDeclaration::parse.map(Expression::InitDeclaration),
+            DiameterDeclaration::parse.map(Expression::DiameterDeclaration),
+            ParamDeclaration::parse.map(Expression::Param),
+            SweepDeclaration::parse.map(Expression::Sweep),
+            SimulationBlock::parse.map(Expression::Simulation),
+            CompartmentDeclaration::parse.map(Expression::Compartment),
+            EventDeclaration::parse.map(Expression::Event),
+            IncludeDeclaration::parse.map(Expression::Include),
+        ))
+        .parse(text)
+    }
+}
+
+impl Expression {
+    pub fn span(&self) -> Span {
+        match self {
+            Expression::Reaction(r) => r.span,
+            Expression::MassAction(r) => r.span,
+            Expression::SpeedDeclaration(s) => s.span,
+            Expression::InitDeclaration(i) => i.span,
+            Expression::DiameterDeclaration(d) => d.span,
+            Expression::Param(p) => p.span,
+            Expression::Sweep(s) => s.span,
+            Expression::Simulation(s) => s.span,
+            Expression::Compartment(c) => c.span,
+            Expression::Event(e) => e.span,
+            Expression::Include(i) => i.span,
+        }
+    }
+    /// The same expression with its span, and those of the parameters it refers to, reset.
+    pub fn without_spans(&self) -> Expression {
+        let mut expression = self.clone();
+        *expression.span_mut() = Span::default();
+        for value in expression.values_mut() {
+            value.formula.clear_spans();
+        }
+        expression
+    }
+    fn span_mut(&mut self) -> &mut Span {
+        match self {
+            Expression::Reaction(r) => &mut r.span,
+            Expression::MassAction(r) => &mut r.span,
+            Expression::SpeedDeclaration(s) => &mut s.span,
+            Expression::InitDeclaration(i) => &mut i.span,
+            Expression::DiameterDeclaration(d) => &mut d.span,
+            Expression::Param(p) => &mut p.span,
+            Expression::Sweep(s) => &mut s.span,
+            Expression::Simulation(s) => &mut s.span,
+            Expression::Compartment(c) => &mut c.span,
+            Expression::Event(e) => &mut e.span,
+            Expression::Include(i) => &mut i.span,
+        }
+    }
+    /// Every number written in the expression.
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Expression::Reaction(r) => {
+                let mut values = vec![&mut r.km, &mut r.kcat];
+                if let Some(reverse) = &mut r.reverse {
+                    values.extend([&mut reverse.km, &mut reverse.kcat]);
+                    values.extend(reverse.keq.as_mut());
+                }
+                values.extend(r.modifiers.iter_mut().flat_map(Modifier::values_mut));
+                values
+            }
+            Expression::MassAction(r) => vec![&mut r.k],
+            Expression::SpeedDeclaration(s) => match &mut s.speed {
+                Speed::Step(x) | Speed::Diffusion(x) => vec![x],
+            },
+            Expression::InitDeclaration(_) | Expression::Include(_) => vec![],
+            Expression::DiameterDeclaration(d) => vec![&mut d.diameter],
+            Expression::Param(p) => vec![&mut p.value],
+            Expression::Sweep(s) => s.values_mut(),
+            Expression::Simulation(s) => s.values_mut(),
+            Expression::Compartment(c) => vec![&mut c.volume],
+            Expression::Event(e) => match &mut e.trigger {
+                Trigger::At(time) => vec![time],
+                Trigger::When(_) => vec![],
+            },
+        }
     }
 }
 
 #[cfg(test)]
 mod test {
     use crate::{
-        parse_eof, Ast, DiameterDeclaration, Parsable, Parser, ParserError, ParserResult,
-        ParserSuccess, SpeedDeclaration,
+        check::check,
+        diagnostic::{Severity, Span},
+        expanded::ExpandedModel,
+        formula::{Formula, Value},
+        keywords::Language,
+        parse_eof, printer,
+        settings::Settings,
+        units::UNITS,
+        Ast, Binding, DiameterDeclaration, Expression, InitDeclaration, MassActionReaction,
+        Mechanism, Modifier, Parsable, Parser, ParserResult, ParserSuccess, Reaction, Speed,
+        SpeedDeclaration,
     };
 
     #[test]
@@ -187,22 +996,194 @@ mod test {
         let res: ParserResult<SpeedDeclaration> =
             Parsable::parse("vitesse(    E23 ) =           2.12  ; hello".into());
         assert_eq!(
-            Err(ParserError {
-                error: "Hello".into()
+            Ok(SpeedDeclaration {
+                identifier: "E23".into(),
+                speed: Speed::Step(2.12.into()),
+                span: Span::new(0, 37),
             }),
-            res
+            res.map(|r| r.content)
         )
     }
     #[test]
     fn t_diameter() {
         assert_eq!(
-            Err(ParserError {
-                error: "Hello".into()
+            Ok(DiameterDeclaration {
+                identifier: "E23".into(),
+                diameter: 0.7.into(),
+                span: Span::new(0, 37),
             }),
             DiameterDeclaration::parse("diametre(    E23 ) =           0.7  ; hello".into())
+                .map(|r| r.content)
         )
     }
 
+    #[test]
+    fn t_reaction() {
+        let reaction = Reaction::parse("E1 : ATP + glc -> ADP + g6p | 200uN - 100;".into())
+            .unwrap()
+            .content;
+        assert_eq!("E1", reaction.enzhym);
+        assert_eq!(vec![("ATP".into(), 1), ("glc".into(), 1)], reaction.solubes);
+        assert_eq!(vec![("ADP".into(), 1), ("g6p".into(), 1)], reaction.results);
+        assert_eq!((200., 100.), (reaction.km.value, reaction.kcat.value));
+        assert_eq!(None, reaction.reverse);
+        assert_eq!("E1", reaction.complex(0));
+        assert_eq!("E1--ATP", reaction.complex(1));
+        assert_eq!("E1--ATP--glc", reaction.complex(2));
+    }
+
+    #[test]
+    fn t_stoichiometry() {
+        let reaction = Reaction::parse("E1 : 2 A + B -> 3C | 200uN - 100;".into())
+            .unwrap()
+            .content;
+        assert_eq!(vec![("A".into(), 2), ("B".into(), 1)], reaction.solubes);
+        assert_eq!(vec![("C".into(), 3)], reaction.results);
+        assert_eq!(
+            vec!["A", "A", "B"],
+            reaction.solube_units().collect::<Vec<_>>()
+        );
+        assert_eq!("E1--A--A", reaction.complex(2));
+        assert!(Reaction::parse("E1 : 0 A -> C | 200uN - 100;".into()).is_err());
+    }
+
+    #[test]
+    fn t_reversible() {
+        let reaction = Reaction::parse("TPI : dhap <-> g3p | 970 uM - 430, 18 uM - 4300;".into())
+            .unwrap()
+            .content;
+        let reverse = reaction.reverse.as_ref().unwrap();
+        assert_eq!((18., 4300.), (reverse.km.value, reverse.kcat.value));
+        assert_eq!(None, reverse.keq);
+        let reversed = reaction.reversed().unwrap();
+        assert_eq!(vec![("g3p".to_string(), 1)], reversed.solubes);
+        assert_eq!((18., 4300.), (reversed.km.value, reversed.kcat.value));
+
+        let reaction =
+            Reaction::parse("E : s <-> p | 100 uM - 10, 100 uM - 10, keq = 1;".into()).unwrap();
+        assert_eq!(Some(1.), reaction.content.haldane_keq());
+        assert!(Reaction::parse("E : s -> p | 100 uM - 10, 100 uM - 10;".into()).is_err());
+        assert!(Reaction::parse("E : s <-> p | 100 uM - 10;".into()).is_err());
+    }
+
+    #[test]
+    fn t_mass_action() {
+        let reaction = MassActionReaction::parse("A + B -> C @ k = 1000000 /M/s;".into())
+            .unwrap()
+            .content;
+        assert_eq!(vec![("A".into(), 1), ("B".into(), 1)], reaction.reactants);
+        assert_eq!(vec![("C".into(), 1)], reaction.products);
+        assert_eq!(1., reaction.k.value);
+
+        let decay = MassActionReaction::parse("A -> 0 @ k = 6 /min;".into())
+            .unwrap()
+            .content;
+        assert!(decay.products.is_empty());
+        assert_eq!(0.1, decay.k.value);
+        let synthesis = MassActionReaction::parse("0 -> A @ k = 2 nM/s;".into())
+            .unwrap()
+            .content;
+        assert!(synthesis.reactants.is_empty());
+        assert_eq!(0.002, synthesis.k.value);
+
+        let error = MassActionReaction::parse("A -> B @ k = 1 /M/s;".into()).unwrap_err();
+        assert_eq!(
+            "Expected a rate unit, found bimolecular rate unit /M/s",
+            error.message()
+        );
+        assert!(MassActionReaction::parse("A + B + C -> D @ k = 1;".into()).is_err());
+    }
+
+    #[test]
+    fn t_numbers() {
+        let km_kcat = |text: &str| {
+            let r = Reaction::parse(format!("E1 : s -> p | {text};").as_str().into())
+                .map_err(|e| e.message())?
+                .content;
+            Ok::<_, String>((r.km.value, r.kcat.value))
+        };
+        assert_eq!(Ok((1e-3, 500.)), km_kcat("1e-3 - .5e3"));
+        assert_eq!(Ok((1000., 0.25)), km_kcat("1_000 uM - 2.5E-1"));
+        assert_eq!(Ok((2., 1.)), km_kcat("2. - 1e+0 /s"));
+        assert_eq!(Ok((0.5, 4.)), km_kcat("0.5 - -(-4)"));
+        assert_eq!(Ok((2e3, 0.1)), km_kcat("2 mM - 0.1"));
+        assert_eq!(
+            Err("Number `1e39` is too large".to_string()),
+            km_kcat("1e39 - 1")
+        );
+        let ast = Ast::parse("E1 : s -> p | -0.5 - 4;\nE2 : s <-> p | 1 - 1, 2 - -(4);".into())
+            .unwrap()
+            .content;
+        let messages = check(&ast, &Settings::default())
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                "Km of `E1` is -0.5 µM, below zero",
+                "reverse kcat of `E2` is -4 s⁻¹, below zero",
+            ],
+            messages
+        );
+
+        let init = |text: &str| {
+            InitDeclaration::parse(format!("init(s) = {text};").as_str().into())
+                .map(|s| s.content.number)
+                .map_err(|e| e.message())
+        };
+        assert_eq!(Ok(6_000_000_000), init("6_000_000_000"));
+        assert_eq!(Ok(u64::MAX), init("18446744073709551615"));
+        assert_eq!(
+            Err("Number `18446744073709551616` is too large".to_string()),
+            init("18446744073709551616")
+        );
+        assert_eq!(
+            Err("Expected natural number, found negative number".to_string()),
+            init("-5")
+        );
+        assert!(init("1__000").is_err());
+        assert_eq!(
+            Err("Number `5000000000` is too large".to_string()),
+            Reaction::parse("E1 : 5000000000 s -> p | 1 - 1;".into())
+                .map_err(|e| e.message())
+                .map(|_| ())
+        );
+    }
+
+    #[test]
+    fn t_params() {
+        let file = "param km1 = 0.12 mM;
+                    param kcat = 2 * km1 / 4;
+                    E1 : s -> p | km1 - kcat / 10;
+                    vitesse(s) = (kcat - 50) um;";
+        let mut ast = Ast::parse(file.into()).unwrap().content;
+        assert_eq!(120., ast.param("km1").unwrap().value.value);
+        let Expression::Reaction(reaction) = &ast.0[2] else {
+            panic!("expected a reaction")
+        };
+        assert_eq!((120., 6.), (reaction.km.value, reaction.kcat.value));
+
+        ast.resolve(&[("km1".into(), 150.)]).unwrap();
+        let Expression::Reaction(reaction) = &ast.0[2] else {
+            panic!("expected a reaction")
+        };
+        assert_eq!((150., 7.5), (reaction.km.value, reaction.kcat.value));
+        let error = ast.resolve(&[("km2".into(), 150.)]).unwrap_err();
+        assert_eq!("the model declares no parameter `km2`", error.message);
+        let Expression::SpeedDeclaration(speed) = &ast.0[3] else {
+            panic!("expected a speed")
+        };
+        assert_eq!(25000., speed.speed.per_step(1.));
+
+        let file = "E1 : s -> p | km2 - 10;\nparam km1 = 120;";
+        let error = Ast::parse(file.into()).unwrap_err();
+        assert_eq!((0, 14), (error.line, error.col));
+        assert_eq!("unknown parameter `km2`", error.message());
+        let error = Ast::parse("param a = 2 * b;\nparam b = 1;".into()).unwrap_err();
+        assert_eq!("unknown parameter `b`", error.message());
+    }
+
     #[test]
     fn t_eof() {
         assert_eq!(
@@ -217,11 +1198,97 @@ mod test {
     #[test]
     fn t_file() {
         let file = include_str!("./test_input.txt");
+        assert!(Ast::parse(file.into()).is_ok())
+    }
+
+    #[test]
+    fn t_modifiers() {
+        let parse = |text: &str| Reaction::parse(text.into()).map(|s| s.content);
+        let reaction =
+            parse("E1 : s -> i | 200 uM - 100 inhibited_by I (Ki = 5 uM, competitive);").unwrap();
         assert_eq!(
-            Err(ParserError {
-                error: "Hello".into()
-            }),
-            Ast::parse(file.into())
+            vec![Modifier {
+                species: "I".into(),
+                mechanism: Mechanism::Competitive {
+                    ki: Value::new(Formula::Unit(
+                        Box::new(Formula::Number(5.)),
+                        *UNITS.iter().find(|u| u.symbol == "uM").unwrap()
+                    ))
+                },
+            }],
+            reaction.modifiers
+        );
+        let binding = |site: &str, modifier: &str, complex: &str, k: f32| Binding {
+            site: site.into(),
+            modifier: modifier.into(),
+            complex: complex.into(),
+            k,
+        };
+        assert_eq!(vec![binding("E1", "I", "E1·I", 5.)], reaction.bindings());
+
+        let reaction = parse(
+            "E1 : s -> i | 200 - 100 inhibited_by I (Ki = 5, mixed, alpha = 4) \
+             inhibited_by s (Ki = 2 mM, substrate) activated_by A (Ka = 1, beta = 3);",
         )
+        .unwrap();
+        assert_eq!(
+            vec![
+                binding("E1", "I", "E1·I", 5.),
+                binding("E1--s", "I", "E1--s·I", 20.),
+                binding("E1--s", "s", "E1--s·s", 2000.),
+                binding("E1", "A", "E1·A", 1.),
+            ],
+            reaction.bindings()
+        );
+        let catalysed = reaction.catalysed();
+        assert_eq!(2, catalysed.len());
+        assert_eq!("E1·A", catalysed[1].enzhym);
+        assert_eq!(
+            (200., 300.),
+            (catalysed[1].km.value, catalysed[1].kcat.value)
+        );
+        assert!(catalysed.iter().all(|c| c.modifiers.is_empty()));
+        let reaction =
+            parse("E : s <-> p | 100 - 10, 100 - 5 activated_by A (Ka = 1 uM, beta = 2);").unwrap();
+        let kcats = reaction
+            .catalysed()
+            .iter()
+            .map(|c| c.kcat.value)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![10., 5., 20., 10.], kcats);
+
+        let error =
+            parse("E1 : s -> i | 200 - 100 inhibited_by I (Ki = 5, sideways);").unwrap_err();
+        assert!(error.committed);
+        assert_eq!("expected inhibition mechanism", error.message());
+        assert!(parse("E1 : s -> i | 200 - 100 activated_by A (Ka = 5);").is_err());
+
+        let model = "E1 : s -> i | 200 uM - 100 inhibited_by I (Ki = 5 uM, competitive) inhibited_by J (Ki = 1, substrate);\n\
+                     init(E1) = 10;\ninit(s) = 100;\ninit(I) = 100;\ninit(J) = 100;";
+        let ast = Ast::parse(model.into()).unwrap().content;
+        assert_eq!(
+            model.lines().next().unwrap(),
+            printer::print(&ast, Language::French)
+                .lines()
+                .next()
+                .unwrap()
+        );
+        let diagnostics = check(&ast, &Settings::default());
+        let messages = diagnostics
+            .iter()
+            .map(|d| d.message.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["substrate inhibition of `E1` by `J`, which is not one of its substrates"],
+            messages
+        );
+        let expanded = ExpandedModel::new(&ast, &Settings::default());
+        assert!(expanded.species.iter().any(|(s, _)| s == "E1·I"));
+        assert!(expanded.species.iter().any(|(s, _)| s == "E1--s·J"));
+        // Binding and catalysis of s, and binding and release of each inhibitor.
+        assert_eq!(7, expanded.reactions.len());
+        let k = |id: &str| expanded.reactions.iter().find(|r| r.id == id).unwrap().k;
+        let (k_on, k_off) = (k("E1_binds_I"), k("E1_releases_I"));
+        assert!((k_off / k_on - 5.).abs() < 1e-3);
     }
 }
@@ -1,27 +1,94 @@
+use std::cell::OnceCell;
+use std::num::{IntErrorKind, ParseIntError};
+use std::rc::{Rc, Weak};
+use std::str::FromStr;
+
+use crate::diagnostic::{Diagnostic, Span};
+
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub struct ParserInput<'a> {
     content: &'a str,
+    offset: usize,
     line: usize,
     col: usize,
+    /// Offset right after the last character that was not skipped as a blank, so that
+    /// spans do not swallow the whitespace and comments that follow a node.
+    token_end: usize,
+    /// Source file the spans point into.
+    file: usize,
 }
 
 impl<'a> ParserInput<'a> {
     fn new(input: &'a str) -> Self {
+        Self::in_file(input, 0)
+    }
+    /// Text of the source file `file`, see [`crate::include::Sources`].
+    pub fn in_file(input: &'a str, file: usize) -> Self {
         Self {
             content: input,
+            offset: 0,
             line: 0,
             col: 0,
+            token_end: 0,
+            file,
         }
     }
-    fn generate_error(self, error_msg: String) -> ParserError {
-        let error = format!(
-            "Error At: line {}, column {} , \n At: {} \n Reason: {}",
-            self.line + 1,
-            self.col + 1,
-            self.content,
-            error_msg
-        );
-        ParserError { error }
+    /// Byte offset from the start of the parsed text.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    /// The text left to parse.
+    pub(crate) fn rest(&self) -> &'a str {
+        self.content
+    }
+    /// Span going from `start` to the end of the last token consumed before `self`.
+    pub fn span_from(&self, start: ParserInput<'a>) -> Span {
+        Span {
+            start: start.offset,
+            end: self.token_end.max(start.offset),
+            file: self.file,
+        }
+    }
+    /// The same input, moved forward to `offset`.
+    pub(crate) fn at(self, offset: usize) -> Self {
+        self.advance(&self.content[..offset.saturating_sub(self.offset).min(self.content.len())])
+    }
+    fn advance(self, consumed: &str) -> Self {
+        let (line, col) = consumed.chars().fold((self.line, self.col), |(l, c), ch| {
+            if ch == '\n' {
+                (l + 1, 0)
+            } else {
+                (l, c + 1)
+            }
+        });
+        Self {
+            content: &self.content[consumed.len()..],
+            offset: self.offset + consumed.len(),
+            line,
+            col,
+            token_end: self.offset + consumed.len(),
+            file: self.file,
+        }
+    }
+    pub(crate) fn generate_error(self, error_msg: String) -> ParserError {
+        ParserError {
+            offset: self.offset,
+            line: self.line,
+            col: self.col,
+            expected: vec![],
+            reason: Some(error_msg),
+            committed: false,
+        }
+    }
+    pub(crate) fn expected(self, what: &str) -> ParserError {
+        ParserError {
+            offset: self.offset,
+            line: self.line,
+            col: self.col,
+            expected: vec![what.to_string()],
+            reason: None,
+            committed: false,
+        }
     }
 }
 
@@ -49,11 +116,86 @@ impl<'a, Output> ParserSuccess<'a, Output> {
     }
 }
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub struct ParserError {
-    pub error: String,
+    /// Byte offset of the failure in the parsed text.
+    pub offset: usize,
+    pub line: usize,
+    pub col: usize,
+    /// What would have been accepted at this position.
+    pub expected: Vec<String>,
+    /// Why the text found here was rejected, when it is more specific than `expected`.
+    pub reason: Option<String>,
+    /// Whether the failure happened after a [`cut`], so that no alternative is tried.
+    pub committed: bool,
+}
+
+impl ParserError {
+    pub fn message(&self) -> String {
+        match (&self.reason, self.expected.as_slice()) {
+            (Some(reason), _) => reason.clone(),
+            (None, [expected]) => format!("expected {}", expected),
+            (None, [init @ .., last]) => format!("expected one of {} or {}", init.join(", "), last),
+            (None, []) => "invalid syntax".to_string(),
+        }
+    }
+    /// Keeps the error that got the furthest into the input, or combines both when they
+    /// failed at the same place.
+    pub fn merge(self, other: ParserError) -> ParserError {
+        match self.offset.cmp(&other.offset) {
+            std::cmp::Ordering::Greater => self,
+            std::cmp::Ordering::Less => other,
+            std::cmp::Ordering::Equal => {
+                let mut expected = self.expected;
+                for e in other.expected {
+                    if !expected.contains(&e) {
+                        expected.push(e);
+                    }
+                }
+                ParserError {
+                    expected,
+                    reason: self.reason.or(other.reason),
+                    committed: self.committed || other.committed,
+                    ..self
+                }
+            }
+        }
+    }
+    pub fn to_diagnostic(&self, source: &str) -> Diagnostic {
+        let found = match source[self.offset..].chars().next() {
+            Some(c) if self.reason.is_none() => format!(", found `{}`", c.escape_debug()),
+            None if self.reason.is_none() => ", found end of file".to_string(),
+            _ => String::new(),
+        };
+        let end = source[self.offset..]
+            .chars()
+            .next()
+            .map_or(self.offset, |c| self.offset + c.len_utf8());
+        Diagnostic::error(
+            format!("{}{}", self.message(), found),
+            Span::new(self.offset, end),
+        )
+    }
+    /// Renders the error rustc style, with an excerpt of `source` pointing at the failure.
+    pub fn render(&self, source: &str, path: &str) -> String {
+        self.to_diagnostic(source).render(source, path)
+    }
 }
 
+impl std::fmt::Display for ParserError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line + 1,
+            self.col + 1,
+            self.message()
+        )
+    }
+}
+
+impl std::error::Error for ParserError {}
+
 pub type ParserResult<'a, Output> = Result<ParserSuccess<'a, Output>, ParserError>;
 
 pub trait Parser<'a, Output> {
@@ -81,6 +223,19 @@ pub trait Parser<'a, Output> {
     fn skip_next<P1, Output1>(self, p: P1) -> impl Parser<'a, Output>
     where
         P1: Parser<'a, Output1>;
+    /// `None` when the parser fails, without consuming anything.
+    fn optional(self) -> impl Parser<'a, Option<Output>>;
+    /// Zero or more items separated by `separator`. A separator must be followed by an
+    /// item.
+    fn sep_by<P1, Output1>(self, separator: P1) -> impl Parser<'a, Vec<Output>>
+    where
+        P1: Parser<'a, Output1>;
+    /// One or more items separated by `separator`.
+    fn sep_by1<P1, Output1>(self, separator: P1) -> impl Parser<'a, Vec<Output>>
+    where
+        P1: Parser<'a, Output1>;
+    /// Names what the parser reads in its errors, see [`expecting`].
+    fn label(self, what: &'static str) -> impl Parser<'a, Output>;
 }
 
 impl<'a, Output, P> Parser<'a, Output> for P
@@ -137,7 +292,7 @@ where
             if p(&result.content) {
                 Ok(result)
             } else {
-                Err(ParserError { error: msg.into() })
+                Err(input.generate_error(msg.into()))
             }
         }
     }
@@ -146,13 +301,17 @@ where
     where
         P1: Parser<'a, Output>,
     {
-        move |input| self.parse(input).or(p.parse(input))
+        move |input| match self.parse(input) {
+            Ok(result) => Ok(result),
+            Err(e1) if e1.committed => Err(e1),
+            Err(e1) => p.parse(input).map_err(|e2| e1.merge(e2)),
+        }
     }
 
     fn zero_or_more(self) -> impl Parser<'a, Vec<Output>> {
         move |mut input| {
             let mut results = Vec::new();
-            while let Ok(result) = self.parse(input) {
+            while let Some(result) = uncommitted(self.parse(input))? {
                 input = result.next_input;
                 results.push(result.content);
             }
@@ -169,7 +328,7 @@ where
             let result = self.parse(input)?;
             input = result.next_input;
             results.push(result.content);
-            while let Ok(result) = self.parse(input) {
+            while let Some(result) = uncommitted(self.parse(input))? {
                 input = result.next_input;
                 results.push(result.content);
             }
@@ -193,20 +352,303 @@ where
     {
         self.chain(p).map(|(r, _)| r)
     }
+
+    fn optional(self) -> impl Parser<'a, Option<Output>> {
+        move |input| {
+            Ok(match uncommitted(self.parse(input))? {
+                Some(result) => result.map(Some),
+                None => ParserSuccess {
+                    content: None,
+                    next_input: input,
+                },
+            })
+        }
+    }
+
+    fn sep_by<P1, Output1>(self, separator: P1) -> impl Parser<'a, Vec<Output>>
+    where
+        P1: Parser<'a, Output1>,
+    {
+        separated(self, separator, false)
+    }
+
+    fn sep_by1<P1, Output1>(self, separator: P1) -> impl Parser<'a, Vec<Output>>
+    where
+        P1: Parser<'a, Output1>,
+    {
+        separated(self, separator, true)
+    }
+
+    fn label(self, what: &'static str) -> impl Parser<'a, Output> {
+        move |input: ParserInput<'a>| {
+            self.parse(input).map_err(|e| {
+                if e.offset == input.offset && !e.committed {
+                    ParserError {
+                        committed: e.committed,
+                        ..input.expected(what)
+                    }
+                } else {
+                    e
+                }
+            })
+        }
+    }
+}
+
+/// The success of a parser, `None` when it failed in a way alternatives may recover from.
+fn uncommitted<'a, O>(
+    result: ParserResult<'a, O>,
+) -> Result<Option<ParserSuccess<'a, O>>, ParserError> {
+    match result {
+        Ok(result) => Ok(Some(result)),
+        Err(e) if e.committed => Err(e),
+        Err(_) => Ok(None),
+    }
+}
+
+fn separated<'a, P, S, O, O1>(item: P, separator: S, at_least_one: bool) -> impl Parser<'a, Vec<O>>
+where
+    P: Parser<'a, O>,
+    S: Parser<'a, O1>,
+{
+    move |input| {
+        let first = item.parse(input);
+        let ParserSuccess {
+            content: first,
+            mut next_input,
+        } = match first {
+            Err(e) if !at_least_one && !e.committed => {
+                return Ok(ParserSuccess {
+                    content: vec![],
+                    next_input: input,
+                })
+            }
+            first => first?,
+        };
+        let mut results = vec![first];
+        while let Some(separator) = uncommitted(separator.parse(next_input))? {
+            let result = item.parse(separator.next_input)?;
+            results.push(result.content);
+            next_input = result.next_input;
+        }
+        Ok(ParserSuccess {
+            content: results,
+            next_input,
+        })
+    }
+}
+
+/// `parser` between `open` and `close`, such as a parenthesized expression.
+pub fn delimited<'a, O1, O, O2>(
+    open: impl Parser<'a, O1>,
+    parser: impl Parser<'a, O>,
+    close: impl Parser<'a, O2>,
+) -> impl Parser<'a, O> {
+    open.skip_me(parser).skip_next(close)
+}
+
+/// Alternatives tried in turn by [`choice`].
+pub trait Alternatives<'a, Output> {
+    fn parse_first(&self, input: ParserInput<'a>) -> ParserResult<'a, Output>;
+}
+
+macro_rules! alternatives {
+    ($First:ident $first:ident $(, $Parser:ident $parser:ident)*) => {
+        impl<'a, Output, $First, $($Parser),*> Alternatives<'a, Output> for ($First, $($Parser),*)
+        where
+            $First: Parser<'a, Output>,
+            $($Parser: Parser<'a, Output>),*
+        {
+            fn parse_first(&self, input: ParserInput<'a>) -> ParserResult<'a, Output> {
+                let ($first, $($parser),*) = self;
+                let mut error = match $first.parse(input) {
+                    Ok(result) => return Ok(result),
+                    Err(e) => e,
+                };
+                $(
+                    if error.committed {
+                        return Err(error);
+                    }
+                    error = match $parser.parse(input) {
+                        Ok(result) => return Ok(result),
+                        Err(e) => error.merge(e),
+                    };
+                )*
+                Err(error)
+            }
+        }
+    };
+}
+
+alternatives!(A a, B b);
+alternatives!(A a, B b, C c);
+alternatives!(A a, B b, C c, D d);
+alternatives!(A a, B b, C c, D d, E e);
+alternatives!(A a, B b, C c, D d, E e, F f);
+alternatives!(A a, B b, C c, D d, E e, F f, G g);
+alternatives!(A a, B b, C c, D d, E e, F f, G g, H h);
+alternatives!(A a, B b, C c, D d, E e, F f, G g, H h, I i);
+alternatives!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j);
+alternatives!(A a, B b, C c, D d, E e, F f, G g, H h, I i, J j, K k);
+
+/// The first of a tuple of parsers that succeeds, or the errors of all of them merged as
+/// [`Parser::or_else`] does.
+pub fn choice<'a, Output>(alternatives: impl Alternatives<'a, Output>) -> impl Parser<'a, Output> {
+    move |input| alternatives.parse_first(input)
+}
+
+/// Succeeds without consuming anything when `parser` fails, such as a keyword that must
+/// not be followed by a letter.
+pub fn not<'a, O>(parser: impl Parser<'a, O>) -> impl Parser<'a, ()> {
+    move |input: ParserInput<'a>| match parser.parse(input) {
+        Ok(result) => {
+            let found = &input.content[..result.next_input.offset - input.offset];
+            Err(input.generate_error(format!("Unexpected `{found}`")))
+        }
+        Err(_) => Ok(ParserSuccess {
+            content: (),
+            next_input: input,
+        }),
+    }
+}
+
+/// What `parser` reads, without consuming it.
+pub fn peek<'a, O>(parser: impl Parser<'a, O>) -> impl Parser<'a, O> {
+    move |input| {
+        parser.parse(input).map(|result| ParserSuccess {
+            content: result.content,
+            next_input: input,
+        })
+    }
+}
+
+/// Commits to the alternative being parsed: once the parsers before it succeeded, a
+/// failure of `parser` is reported as is instead of trying the other alternatives, which
+/// gives errors pointing at the actual mistake. The commitment holds up to the top.
+pub fn cut<'a, O>(parser: impl Parser<'a, O>) -> impl Parser<'a, O> {
+    move |input| {
+        parser.parse(input).map_err(|e| ParserError {
+            committed: true,
+            ..e
+        })
+    }
+}
+
+type Definition<'a, O> = OnceCell<Box<dyn Fn(ParserInput<'a>) -> ParserResult<'a, O> + 'a>>;
+
+/// A parser being defined by [`recursive`], to use inside its own definition.
+pub struct Recursive<'a, O>(Weak<Definition<'a, O>>);
+
+impl<'a, O> Clone for Recursive<'a, O> {
+    fn clone(&self) -> Self {
+        Recursive(self.0.clone())
+    }
+}
+
+impl<'a, O: 'a> Recursive<'a, O> {
+    pub fn parser(&self) -> impl Parser<'a, O> {
+        let definition = self.0.clone();
+        move |input| {
+            let definition = definition
+                .upgrade()
+                .expect("a recursive parser is only used while it exists");
+            let parser = definition
+                .get()
+                .expect("a recursive parser is only used once defined");
+            parser(input)
+        }
+    }
+}
+
+/// A parser that refers to itself, such as nested parentheses: `define` gets a handle to
+/// the parser it returns.
+pub fn recursive<'a, O: 'a, P>(define: impl FnOnce(Recursive<'a, O>) -> P) -> impl Parser<'a, O>
+where
+    P: Parser<'a, O> + 'a,
+{
+    let definition: Rc<Definition<'a, O>> = Rc::new(OnceCell::new());
+    let parser = define(Recursive(Rc::downgrade(&definition)));
+    let _ = definition.set(Box::new(move |input| parser.parse(input)));
+    move |input| (definition.get().expect("the parser was just defined"))(input)
+}
+
+/// Length of the block comment starting `text`, which may contain other block comments,
+/// or `None` when it is not terminated.
+fn block_comment_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let (mut depth, mut len) = (0, 0);
+    loop {
+        match (bytes.get(len)?, bytes.get(len + 1)) {
+            (b'/', Some(b'*')) => {
+                depth += 1;
+                len += 2;
+            }
+            (b'*', Some(b'/')) => {
+                depth -= 1;
+                len += 2;
+                if depth == 0 {
+                    return Some(len);
+                }
+            }
+            _ => len += 1,
+        }
+    }
+}
+
+/// Length of the blanks at the start of `text`: whitespace and whole comments, or the
+/// offset of the block comment that is not terminated.
+fn blanks_len(text: &str) -> Result<usize, usize> {
+    let mut len = 0;
+    loop {
+        let rest = &text[len..];
+        if rest.starts_with("//") {
+            len += rest.find('\n').unwrap_or(rest.len());
+        } else if rest.starts_with("/*") {
+            len += block_comment_len(rest).ok_or(len)?;
+        } else if let Some(c) = rest.chars().next().filter(|c| c.is_whitespace()) {
+            len += c.len_utf8();
+        } else {
+            return Ok(len);
+        }
+    }
+}
+
+/// Skips blanks, which do not count as tokens: the end of the last token is carried over
+/// them. A block comment that is not terminated is an error once its `/*` is read, as
+/// nothing else may start with it.
+fn skip_blanks<'a>(input: ParserInput<'a>, at_least_one: bool) -> ParserResult<'a, ()> {
+    let len = blanks_len(input.content).map_err(|start| ParserError {
+        committed: true,
+        ..input
+            .advance(&input.content[..start])
+            .generate_error("unterminated block comment".into())
+    })?;
+    if len == 0 && at_least_one {
+        return Err(input.expected("whitespace"));
+    }
+    let mut next_input = input.advance(&input.content[..len]);
+    next_input.token_end = input.token_end;
+    Ok(ParserSuccess {
+        content: (),
+        next_input,
+    })
 }
 
 pub fn space0<'a>() -> impl Parser<'a, ()> {
-    anychar
-        .predicate(|c| c.is_whitespace(), "")
-        .zero_or_more()
-        .map(|_| ())
+    move |input| skip_blanks(input, false)
 }
 
 pub fn space1<'a>() -> impl Parser<'a, ()> {
-    anychar
-        .predicate(|c| c.is_whitespace(), "")
-        .one_or_more()
-        .map(|_| ())
+    move |input| skip_blanks(input, true)
+}
+
+/// Replaces the error of `parser` by "expected `what`" when it fails without consuming
+/// anything, so that low level failures read as the construct that was being parsed.
+pub fn expecting<'a, P, R>(parser: P, what: &'static str) -> impl Parser<'a, R>
+where
+    P: Parser<'a, R>,
+{
+    parser.label(what)
 }
 
 pub fn between_spaces<'a, P, R>(parser: P) -> impl Parser<'a, R>
@@ -216,82 +658,152 @@ where
     space0().chain(parser).chain(space0()).map(|((_, r), _)| r)
 }
 
-fn naked_string<'a>() -> impl Parser<'a, String> {
-    anychar
-        .predicate(|c| c.is_alphabetic(), "Expected alphabetic character")
-        .chain(
-            anychar
-                .predicate(|c| c.is_alphanumeric(), "Expected alphanumeric character")
-                .zero_or_more(),
-        )
-        .map(|(head, mut tail)| {
-            tail.insert(0, head);
-            tail.into_iter().collect::<String>()
+/// A letter followed by letters and digits, borrowed from the input.
+fn naked_string<'a>() -> impl Parser<'a, &'a str> {
+    move |input: ParserInput<'a>| {
+        let mut chars = input.content.char_indices();
+        match chars.next() {
+            Some((_, c)) if c.is_alphabetic() => (),
+            Some(_) => return Err(input.generate_error("Expected alphabetic character".into())),
+            None => return Err(input.generate_error("Unexpected end of file".into())),
+        }
+        let len = chars
+            .find(|(_, c)| !c.is_alphanumeric())
+            .map_or(input.content.len(), |(i, _)| i);
+        let name = &input.content[..len];
+        Ok(ParserSuccess {
+            content: name,
+            next_input: input.advance(name),
         })
+    }
 }
 
-fn quoted_string<'a>() -> impl Parser<'a, String> {
-    anychar
-        .predicate(|c| *c == '"', "Expected double quote")
-        .skip_me(
-            anychar
-                .predicate(|c| *c != '"', "Expected non empty string")
-                .one_or_more(),
-        )
-        .skip_next(anychar.predicate(|c| *c == '"', "Expected double quote"))
-        .map(|v| v.into_iter().collect::<String>())
+/// Non-empty text between double quotes, borrowed from the input without its quotes.
+pub(crate) fn quoted_string<'a>() -> impl Parser<'a, &'a str> {
+    move |input: ParserInput<'a>| {
+        let Some(text) = input.content.strip_prefix('"') else {
+            return Err(input.generate_error("Expected double quote".into()));
+        };
+        match text.find('"') {
+            Some(0) => Err(input
+                .advance("\"")
+                .generate_error("Expected non empty string".into())),
+            Some(len) => Ok(ParserSuccess {
+                content: &text[..len],
+                next_input: input.advance(&input.content[..len + 2]),
+            }),
+            None => Err(input
+                .advance(input.content)
+                .generate_error("Unexpected end of file".into())),
+        }
+    }
 }
 
 pub fn identifier<'a>() -> impl Parser<'a, String> {
-    naked_string().or_else(quoted_string())
+    expecting(naked_string().or_else(quoted_string()), "identifier").map(str::to_string)
 }
 
-pub fn natural_number<'a>() -> impl Parser<'a, u32> {
-    anychar
-        .predicate(|c| c.is_numeric(), "Expected natural number")
-        .one_or_more()
-        .map(|v| v.into_iter().collect::<String>().parse().unwrap())
+/// Length of the digits at the start of `text`, with the `_` separating two digits.
+fn digits_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let digit = |i: usize| bytes.get(i).is_some_and(u8::is_ascii_digit);
+    let mut len = 0;
+    while digit(len) || len > 0 && bytes.get(len) == Some(&b'_') && digit(len + 1) {
+        len += 1;
+    }
+    len
 }
 
+/// Digits of a `T`, such as `1_000_000`. Negative numbers and numbers too large for a `T`
+/// are errors.
+pub fn natural_number<'a, T>() -> impl Parser<'a, T>
+where
+    T: FromStr<Err = ParseIntError>,
+{
+    move |input: ParserInput<'a>| {
+        let len = digits_len(input.content);
+        if len == 0 {
+            if input.content.starts_with('-') && digits_len(&input.content[1..]) > 0 {
+                return Err(
+                    input.generate_error("Expected natural number, found negative number".into())
+                );
+            }
+            return Err(input.expected("natural number"));
+        }
+        let digits = &input.content[..len];
+        match digits.replace('_', "").parse::<T>() {
+            Ok(n) => Ok(ParserSuccess {
+                content: n,
+                next_input: input.advance(digits),
+            }),
+            Err(e) if *e.kind() == IntErrorKind::PosOverflow => {
+                Err(input.generate_error(format!("Number `{digits}` is too large")))
+            }
+            Err(e) => Err(input.generate_error(e.to_string())),
+        }
+    }
+}
+
+/// A decimal number such as `1`, `0.5`, `.5`, `1.` or `1_000`, optionally followed by an
+/// exponent as in `6.022e23` or `1E-6`. Numbers too large for an `f32` are errors. The
+/// `..` of a range such as `10..200` ends the number.
 pub fn real_number<'a>() -> impl Parser<'a, f32> {
-    anychar
-        .predicate(|c| c.is_numeric() || *c == '.', "Expected number")
-        .one_or_more()
-        .predicate(
-            |v| v.into_iter().collect::<String>().parse::<f32>().is_ok(),
-            "Expected real number",
-        )
-        .map(|v| v.into_iter().collect::<String>().parse().unwrap())
-}
-
-fn anychar<'a>(input: ParserInput<'a>) -> ParserResult<'a, char> {
-    let mut chars = input.content.chars();
-    match chars.next() {
-        Some(c) => {
-            if c == '\n' {
-                Ok(ParserSuccess {
-                    content: c,
-                    next_input: ParserInput {
-                        content: &input.content[c.len_utf8()..],
-                        line: input.line + 1,
-                        col: 0,
-                    },
-                })
-            } else {
-                Ok(ParserSuccess {
-                    content: c,
-                    next_input: ParserInput {
-                        content: &input.content[c.len_utf8()..],
-                        line: input.line,
-                        col: input.col + 1,
-                    },
-                })
+    move |input: ParserInput<'a>| {
+        let text = input.content;
+        let mut len = digits_len(text);
+        if let Some(fraction) = text[len..]
+            .strip_prefix('.')
+            .filter(|f| !f.starts_with('.'))
+        {
+            let fraction = digits_len(fraction);
+            if len > 0 || fraction > 0 {
+                len += 1 + fraction;
             }
         }
+        if len == 0 {
+            return Err(input.expected("number"));
+        }
+        // An `e` not followed by digits belongs to what comes next.
+        if let Some(exponent) = text[len..].strip_prefix(['e', 'E']) {
+            let sign = usize::from(exponent.starts_with(['+', '-']));
+            let digits = digits_len(&exponent[sign..]);
+            if digits > 0 {
+                len += 1 + sign + digits;
+            }
+        }
+        let number = &text[..len];
+        match number.replace('_', "").parse::<f32>() {
+            Ok(x) if x.is_finite() => Ok(ParserSuccess {
+                content: x,
+                next_input: input.advance(number),
+            }),
+            Ok(_) => Err(input.generate_error(format!("Number `{number}` is too large"))),
+            Err(e) => Err(input.generate_error(e.to_string())),
+        }
+    }
+}
+
+pub(crate) fn anychar<'a>(input: ParserInput<'a>) -> ParserResult<'a, char> {
+    match input.content.chars().next() {
+        Some(c) => Ok(ParserSuccess {
+            content: c,
+            next_input: input.advance(&input.content[..c.len_utf8()]),
+        }),
         None => Err(input.generate_error("Unexpected end of file".to_string())),
     }
 }
 
+pub fn eof<'a>(input: ParserInput<'a>) -> ParserResult<'a, ()> {
+    if input.content.is_empty() {
+        Ok(ParserSuccess {
+            content: (),
+            next_input: input,
+        })
+    } else {
+        Err(input.expected("end of file"))
+    }
+}
+
 pub fn nothing<'a>() -> impl Parser<'a, ()> {
     move |input: ParserInput<'a>| {
         Ok(ParserSuccess {
@@ -303,104 +815,264 @@ pub fn nothing<'a>() -> impl Parser<'a, ()> {
 
 pub fn literal<'a>(keyword: &'static str) -> impl Parser<'a, &'static str> {
     move |input: ParserInput<'a>| {
-        let line_break_position = keyword.rfind(|c| c == '\n').unwrap_or(0);
-        let line = keyword.chars().filter(|c| *c != '\n').count();
-        let col = keyword.len() - line_break_position;
         if input.content.starts_with(keyword) {
             Ok(ParserSuccess {
                 content: keyword,
-                next_input: ParserInput {
-                    content: &input.content[keyword.len()..],
-                    line,
-                    col,
-                },
+                next_input: input.advance(keyword),
             })
         } else {
-            Err(input.generate_error(format!("expected keyword {}", keyword)))
+            Err(input.expected(&format!("`{}`", keyword)))
         }
     }
 }
 
 #[cfg(test)]
 mod test {
+    use super::*;
 
-    // #[test]
-    // fn t_anychar() {
-    //     assert_eq!(None, Some(anychar(ParserInput::new("hello"))));
-    // }
-
-    // #[test]
-    // fn t_map() {
-    //     assert_eq!(None, Some(map(anychar, |c| c as u8).parse("nice".into())))
-    // }
-
-    // #[test]
-    // fn t_predicate() {
-    //     assert_eq!(
-    //         None,
-    //         Some(predicate(anychar, |c| c.is_alphabetic()).parse("\nice".into()))
-    //     )
-    // }
-
-    // #[test]
-    // fn t_pair() {
-    //     let p1 = predicate(anychar, |c| *c == 'h');
-    //     let p2 = predicate(anychar, |c| *c == 'e');
-    //     assert_eq!(None, Some(pair(p1, p2).parse("hello".into())))
-    // }
-
-    // #[test]
-    // fn t_literal() {
-    //     assert_eq!(None, Some(literal("init").parse("init hello = 12".into())));
-    // }
-
-    // #[test]
-    // fn t_right() {
-    //     assert_eq!(
-    //         None,
-    //         Some(right(literal("1"), literal("2")).parse("1234".into()))
-    //     );
-    // }
-    // #[test]
-    // fn t_left() {
-    //     assert_eq!(
-    //         None,
-    //         Some(left(literal("1"), literal("2")).parse("1234".into()))
-    //     );
-    // }
-
-    // #[test]
-    // fn t_either() {
-    //     assert_eq!(
-    //         None,
-    //         Some(
-    //             literal("init")
-    //                 .chain(literal("vitesse"))
-    //                 .parse("vitesse(E1)=0.4;".into())
-    //         )
-    //     );
-    // }
-
-    // #[test]
-    // fn t_zero_or_more() {
-    //     assert_eq!(
-    //         None,
-    //         Some(zero_or_more(literal("1")).parse("112122".into()))
-    //     )
-    // }
-    // #[test]
-    // fn t_one_or_more() {
-    //     assert_eq!(None, Some(one_or_more(literal("1")).parse("2122".into())))
-    // }
-    // #[test]
-    // fn t_naked_string() {
-    //     assert_eq!(None, Some(naked_string().parse("EA31+".into())))
-    // }
-    // #[test]
-    // fn t_quoted_string() {
-    //     assert_eq!(
-    //         None,
-    //         Some(quoted_string().parse("\"EA+  45 ()\":i->p;".into()))
-    //     )
-    // }
+    #[test]
+    fn t_comments() {
+        let input = "// line\n  /* block /* nested */\n still */  x";
+        let ParserSuccess { next_input, .. } = space0().parse(input.into()).unwrap();
+        assert_eq!("x", next_input.content);
+        assert_eq!((2, 11), (next_input.line, next_input.col));
+        let error = space0().parse("  /* open /* */ x".into()).unwrap_err();
+        assert_eq!(
+            ("unterminated block comment".to_string(), 2, true),
+            (error.message(), error.offset, error.committed)
+        );
+        let error = between_spaces(literal("x"))
+            .label("x")
+            .parse("/* x".into())
+            .unwrap_err();
+        assert_eq!("unterminated block comment", error.message());
+    }
+
+    /// The content of a success and the text left after it.
+    fn parsed<'a, O>(result: ParserResult<'a, O>) -> (O, &'a str) {
+        let ParserSuccess {
+            content,
+            next_input,
+        } = result.unwrap();
+        (content, next_input.content)
+    }
+
+    #[test]
+    fn t_anychar() {
+        assert_eq!(('h', "ello"), parsed(anychar("hello".into())));
+        assert_eq!(('µ', "m"), parsed(anychar("µm".into())));
+        assert!(anychar("".into()).is_err());
+    }
+
+    #[test]
+    fn t_map() {
+        assert_eq!(
+            (b'n', "ice"),
+            parsed(anychar.map(|c| c as u8).parse("nice".into()))
+        );
+    }
+
+    #[test]
+    fn t_predicate() {
+        let letter = anychar.predicate(|c| c.is_alphabetic(), "Expected letter");
+        assert_eq!(('n', "ice"), parsed(letter.parse("nice".into())));
+        let error = letter.parse("\nice".into()).unwrap_err();
+        assert_eq!(
+            ("Expected letter".to_string(), 0),
+            (error.message(), error.offset)
+        );
+    }
+
+    #[test]
+    fn t_chain() {
+        let p = literal("h").chain(literal("e"));
+        assert_eq!((("h", "e"), "llo"), parsed(p.parse("hello".into())));
+        assert_eq!(1, p.parse("hallo".into()).unwrap_err().offset);
+        assert_eq!(
+            ("2", "34"),
+            parsed(literal("1").skip_me(literal("2")).parse("1234".into()))
+        );
+        assert_eq!(
+            ("1", "34"),
+            parsed(literal("1").skip_next(literal("2")).parse("1234".into()))
+        );
+    }
+
+    #[test]
+    fn t_literal() {
+        assert_eq!(
+            ("init", " hello = 12"),
+            parsed(literal("init").parse("init hello = 12".into()))
+        );
+        let error = literal("init").parse("vitesse(E1)".into()).unwrap_err();
+        assert_eq!("expected `init`", error.message());
+    }
+
+    #[test]
+    fn t_or_else() {
+        let p = literal("init").or_else(literal("vitesse"));
+        assert_eq!(("vitesse", "(E1)"), parsed(p.parse("vitesse(E1)".into())));
+        let error = p.parse("speed(E1)".into()).unwrap_err();
+        assert_eq!("expected one of `init` or `vitesse`", error.message());
+    }
+
+    #[test]
+    fn t_repetitions() {
+        assert_eq!(
+            (vec!["1", "1"], "2122"),
+            parsed(literal("1").zero_or_more().parse("112122".into()))
+        );
+        assert_eq!(
+            (vec![], "2122"),
+            parsed(literal("1").zero_or_more().parse("2122".into()))
+        );
+        assert!(literal("1").one_or_more().parse("2122".into()).is_err());
+        assert_eq!(
+            (vec!["1"], "2"),
+            parsed(literal("1").one_or_more().parse("12".into()))
+        );
+    }
+
+    #[test]
+    fn t_strings() {
+        assert_eq!(("EA31", "+"), parsed(naked_string().parse("EA31+".into())));
+        assert_eq!(
+            ("EA+  45 ()", ":i->p;"),
+            parsed(quoted_string().parse("\"EA+  45 ()\":i->p;".into()))
+        );
+        assert!(quoted_string().parse("\"\"".into()).is_err());
+    }
+
+    #[test]
+    fn t_optional() {
+        let sign = literal("-").optional();
+        assert_eq!((Some("-"), "1"), parsed(sign.parse("-1".into())));
+        assert_eq!((None, "1"), parsed(sign.parse("1".into())));
+    }
+
+    #[test]
+    fn t_sep_by() {
+        let list = || natural_number::<u32>().sep_by(literal(","));
+        assert_eq!((vec![1, 2, 3], ";"), parsed(list().parse("1,2,3;".into())));
+        assert_eq!((vec![], ";"), parsed(list().parse(";".into())));
+        // A separator must be followed by an item.
+        let error = list().parse("1,2,;".into()).unwrap_err();
+        assert_eq!(
+            ("expected natural number".to_string(), 4),
+            (error.message(), error.offset)
+        );
+
+        let list = natural_number::<u32>().sep_by1(literal(","));
+        assert_eq!((vec![7], " ,"), parsed(list.parse("7 ,".into())));
+        assert!(list.parse(";".into()).is_err());
+    }
+
+    #[test]
+    fn t_delimited() {
+        let p = delimited(literal("("), identifier(), literal(")"));
+        assert_eq!(
+            ("E1".to_string(), " = 3"),
+            parsed(p.parse("(E1) = 3".into()))
+        );
+        let error = p.parse("(E1 = 3".into()).unwrap_err();
+        assert_eq!(
+            ("expected `)`".to_string(), 3),
+            (error.message(), error.offset)
+        );
+    }
+
+    #[test]
+    fn t_choice() {
+        let keyword = choice((literal("init"), literal("vitesse"), literal("diametre")));
+        assert_eq!(
+            ("diametre", "(s)"),
+            parsed(keyword.parse("diametre(s)".into()))
+        );
+        let error = keyword.parse("param".into()).unwrap_err();
+        assert_eq!(
+            "expected one of `init`, `vitesse` or `diametre`",
+            error.message()
+        );
+        // The error that got the furthest wins.
+        let p = choice((
+            literal("ab"),
+            literal("a").chain(literal("c")).map(|_| "ac"),
+        ));
+        assert_eq!(1, p.parse("ad".into()).unwrap_err().offset);
+    }
+
+    #[test]
+    fn t_lookahead() {
+        let keyword = literal("k").skip_next(not(anychar.predicate(|c| c.is_alphanumeric(), "")));
+        assert_eq!(("k", " = 1"), parsed(keyword.parse("k = 1".into())));
+        let error = keyword.parse("keq = 1".into()).unwrap_err();
+        assert_eq!(
+            ("Unexpected `e`".to_string(), 1),
+            (error.message(), error.offset)
+        );
+
+        assert_eq!(
+            ("in", "init"),
+            parsed(peek(literal("in")).parse("init".into()))
+        );
+        assert!(peek(literal("in")).parse("vitesse".into()).is_err());
+    }
+
+    #[test]
+    fn t_cut() {
+        let param = || literal("param").skip_me(cut(literal(" ").skip_me(identifier())));
+        let p = param().map(Some).or_else(identifier().map(|_| None));
+        // Without the cut, `param1` would be read as an identifier.
+        let error = p.parse("param1".into()).unwrap_err();
+        assert_eq!(
+            ("expected ` `".to_string(), 5),
+            (error.message(), error.offset)
+        );
+        assert!(error.committed);
+        assert!(param().optional().parse("param 1".into()).is_err());
+        assert!(param()
+            .zero_or_more()
+            .parse("param \"x\"param1".into())
+            .is_err());
+        assert!(choice((param(), identifier()))
+            .parse("param\n".into())
+            .is_err());
+        assert_eq!(
+            (vec![], "x"),
+            parsed(param().zero_or_more().parse("x".into()))
+        );
+    }
+
+    #[test]
+    fn t_label() {
+        let count = natural_number::<u32>().label("number of molecules");
+        assert_eq!(
+            "expected number of molecules",
+            count.parse("x".into()).unwrap_err().message()
+        );
+        // Errors past the start are kept.
+        let pair = literal("(").chain(literal(")")).label("unit");
+        assert_eq!(
+            "expected `)`",
+            pair.parse("(x".into()).unwrap_err().message()
+        );
+    }
+
+    #[test]
+    fn t_recursive() {
+        // Depth of nested parentheses.
+        let nested = recursive(|nested| {
+            literal("(")
+                .skip_me(cut(nested.parser().skip_next(literal(")"))))
+                .map(|depth: u32| depth + 1)
+                .or_else(nothing().map(|_| 0))
+        });
+        assert_eq!((3, ""), parsed(nested.parse("((()))".into())));
+        assert_eq!((1, ")"), parsed(nested.parse("())".into())));
+        let error = nested.parse("((x".into()).unwrap_err();
+        assert_eq!(
+            ("expected `)`".to_string(), 2),
+            (error.message(), error.offset)
+        );
+    }
 }
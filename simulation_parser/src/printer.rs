@@ -0,0 +1,572 @@
+use crate::compartments::{compartment_of, CompartmentDeclaration};
+use crate::diagnostic::Diagnostic;
+use crate::formula::{Formula, Value};
+use crate::keywords::{Keyword, Language};
+use crate::settings::SimulationBlock;
+use crate::sweep::{SweepDeclaration, SweepValues};
+use crate::{
+    parse_expressions, Ast, Expression, MassActionReaction, Mechanism, Modifier, Reaction, Speed,
+};
+
+/// Group of expressions printed together, in the order the sections of a formatted model
+/// come in. Expressions of a section are separated from the other sections by a blank line.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+enum Section {
+    Include,
+    Param,
+    Sweep,
+    Simulation,
+    Compartment,
+    Reaction,
+    MassAction,
+    Init,
+    Event,
+    Speed,
+    Diameter,
+}
+
+fn section(expression: &Expression) -> Section {
+    match expression {
+        Expression::Include(_) => Section::Include,
+        Expression::Param(_) => Section::Param,
+        Expression::Sweep(_) => Section::Sweep,
+        Expression::Simulation(_) => Section::Simulation,
+        Expression::Compartment(_) => Section::Compartment,
+        Expression::Reaction(_) => Section::Reaction,
+        Expression::MassAction(_) => Section::MassAction,
+        Expression::InitDeclaration(_) => Section::Init,
+        Expression::Event(_) => Section::Event,
+        Expression::SpeedDeclaration(_) => Section::Speed,
+        Expression::DiameterDeclaration(_) => Section::Diameter,
+    }
+}
+
+/// An expression with the comments written around it.
+struct Item<'a> {
+    expression: &'a Expression,
+    /// Comments on the lines before the expression, or inside it.
+    leading: Vec<&'a str>,
+    /// Comments on the line the expression ends on.
+    trailing: Vec<&'a str>,
+}
+
+/// `name`, quoted when it is not a bare identifier.
+pub fn identifier(name: &str) -> String {
+    let mut chars = name.chars();
+    let bare = chars.next().is_some_and(char::is_alphabetic) && chars.all(char::is_alphanumeric);
+    if bare {
+        name.to_string()
+    } else {
+        format!("\"{name}\"")
+    }
+}
+
+/// The species `name`, quoted when it is not a bare identifier, with the compartment it is
+/// in: `s`, or `s[nucleus]` for `s` in `nucleus`.
+pub fn species_identifier(name: &str) -> String {
+    match compartment_of(name) {
+        Some(compartment) => format!(
+            "{}[{}]",
+            identifier(&name[..name.len() - compartment.len() - 2]),
+            identifier(compartment)
+        ),
+        None => identifier(name),
+    }
+}
+
+/// A reaction side: `2 A + B`, or `0` when it is empty.
+pub(crate) fn species(species: &[(String, u32)]) -> String {
+    if species.is_empty() {
+        return "0".to_string();
+    }
+    species
+        .iter()
+        .map(|(name, n)| match n {
+            1 => species_identifier(name),
+            n => format!("{n} {}", species_identifier(name)),
+        })
+        .collect::<Vec<_>>()
+        .join(" + ")
+}
+
+fn width<T>(items: &[T], column: impl Fn(&T) -> &str) -> usize {
+    items
+        .iter()
+        .map(|i| column(i).chars().count())
+        .max()
+        .unwrap_or(0)
+}
+
+/// `inhibited_by I (Ki = 5 uM, competitive)` or `activated_by A (Ka = 2 uM, beta = 3)`.
+fn modifier(m: &Modifier) -> String {
+    let species = species_identifier(&m.species);
+    let k = |k: &Value| k.formula.to_source(false);
+    match &m.mechanism {
+        Mechanism::Competitive { ki } => {
+            format!("inhibited_by {species} (Ki = {}, competitive)", k(ki))
+        }
+        Mechanism::Uncompetitive { ki } => {
+            format!("inhibited_by {species} (Ki = {}, uncompetitive)", k(ki))
+        }
+        Mechanism::Substrate { ki } => {
+            format!("inhibited_by {species} (Ki = {}, substrate)", k(ki))
+        }
+        Mechanism::Mixed { ki, alpha } => format!(
+            "inhibited_by {species} (Ki = {}, mixed, alpha = {})",
+            k(ki),
+            alpha.formula
+        ),
+        Mechanism::Activation { ka, beta } => format!(
+            "activated_by {species} (Ka = {}, beta = {})",
+            k(ka),
+            beta.formula
+        ),
+    }
+}
+
+/// Enzymatic reactions, with their enzymes, substrates, arrows and products aligned.
+fn reactions(reactions: &[&Reaction]) -> Vec<String> {
+    let columns = reactions
+        .iter()
+        .map(|r| {
+            let arrow = if r.reverse.is_some() { "<->" } else { "->" };
+            let mut kinetics = match &r.brenda {
+                Some(query) => query.to_string(),
+                None => format!("{} - {}", r.km.formula.to_source(false), r.kcat.formula),
+            };
+            if let Some(reverse) = &r.reverse {
+                kinetics += &format!(
+                    ", {} - {}",
+                    reverse.km.formula.to_source(false),
+                    reverse.kcat.formula
+                );
+                if let Some(keq) = &reverse.keq {
+                    kinetics += &format!(", keq = {}", keq.formula);
+                }
+            }
+            for m in &r.modifiers {
+                kinetics += &format!(" {}", modifier(m));
+            }
+            [
+                species_identifier(&r.enzhym),
+                species(&r.solubes),
+                arrow.to_string(),
+                species(&r.results),
+                kinetics,
+            ]
+        })
+        .collect::<Vec<_>>();
+    let w = |i: usize| width(&columns, |c| &c[i]);
+    let (enzyme, left, arrow, right) = (w(0), w(1), w(2), w(3));
+    columns
+        .iter()
+        .map(|[e, l, a, r, k]| format!("{e:<enzyme$} : {l:<left$} {a:>arrow$} {r:<right$} | {k};"))
+        .collect()
+}
+
+/// Mass-action reactions, with their reactants and products aligned.
+fn mass_actions(reactions: &[&MassActionReaction]) -> Vec<String> {
+    let columns = reactions
+        .iter()
+        .map(|r| {
+            [
+                species(&r.reactants),
+                species(&r.products),
+                r.k.formula.to_string(),
+            ]
+        })
+        .collect::<Vec<_>>();
+    let (left, right) = (width(&columns, |c| &c[0]), width(&columns, |c| &c[1]));
+    columns
+        .iter()
+        .map(|[l, r, k]| format!("{l:<left$} -> {r:<right$} @ k = {k};"))
+        .collect()
+}
+
+/// `name = value;` declarations, with their `=` aligned.
+fn assignments(assignments: &[(String, String)]) -> Vec<String> {
+    let name = width(assignments, |(n, _)| n);
+    assignments
+        .iter()
+        .map(|(n, v)| format!("{n:<name$} = {v};"))
+        .collect()
+}
+
+fn simulation(block: &SimulationBlock) -> String {
+    let value = |v: &Option<crate::formula::Value>| v.as_ref().map(|v| v.formula.to_string());
+    let settings = [
+        ("end_time", value(&block.end_time)),
+        ("sample_every", value(&block.sample_every)),
+        ("seed", block.seed.map(|s| s.to_string())),
+        ("dt", value(&block.dt)),
+        ("radius", value(&block.radius)),
+    ]
+    .into_iter()
+    .filter_map(|(name, value)| value.map(|v| format!("    {name} = {v};\n")))
+    .collect::<String>();
+    if settings.is_empty() {
+        "simulation {}".to_string()
+    } else {
+        format!("simulation {{\n{settings}}}")
+    }
+}
+
+fn sweep(s: &SweepDeclaration) -> String {
+    let values = match &s.values {
+        SweepValues::Range { from, to, step } => {
+            format!("{}..{} step {}", from.formula, to.formula, step.formula)
+        }
+        SweepValues::List(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(|v| v.formula.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    };
+    format!("sweep {} in {values};", identifier(&s.identifier))
+}
+
+fn compartment(c: &CompartmentDeclaration) -> String {
+    let mut settings = format!("    volume = {};\n", c.volume.formula);
+    if let Some(inside) = &c.inside {
+        settings += &format!("    inside = {};\n", identifier(inside));
+    }
+    format!("compartment {} {{\n{settings}}}", identifier(&c.identifier))
+}
+
+/// Lines of a run of expressions of the same section, keywords spelt in `language`.
+fn lines(expressions: &[&Expression], language: Language) -> Vec<String> {
+    let assignment = |e: &Expression| match e {
+        Expression::Param(p) => Some((
+            format!("param {}", identifier(&p.identifier)),
+            p.value.formula.to_string(),
+        )),
+        Expression::InitDeclaration(i) => Some((
+            format!(
+                "{}({})",
+                Keyword::Init.spelling(language),
+                species_identifier(&i.identifier)
+            ),
+            i.number.to_string(),
+        )),
+        Expression::SpeedDeclaration(s) => {
+            // A diffusion coefficient without unit would be read back as a distance.
+            let keyword = match &s.speed {
+                Speed::Diffusion(x) if !matches!(x.formula, Formula::Unit(..)) => {
+                    Keyword::Diffusion
+                }
+                _ => Keyword::Speed,
+            };
+            let (Speed::Step(x) | Speed::Diffusion(x)) = &s.speed;
+            Some((
+                format!(
+                    "{}({})",
+                    keyword.spelling(language),
+                    species_identifier(&s.identifier)
+                ),
+                x.formula.to_string(),
+            ))
+        }
+        Expression::DiameterDeclaration(d) => Some((
+            format!(
+                "{}({})",
+                Keyword::Diameter.spelling(language),
+                species_identifier(&d.identifier)
+            ),
+            d.diameter.formula.to_string(),
+        )),
+        _ => None,
+    };
+    match expressions[0] {
+        Expression::Reaction(_) => reactions(
+            &expressions
+                .iter()
+                .filter_map(|e| match *e {
+                    Expression::Reaction(r) => Some(r.as_ref()),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Expression::MassAction(_) => mass_actions(
+            &expressions
+                .iter()
+                .filter_map(|e| match *e {
+                    Expression::MassAction(r) => Some(r),
+                    _ => None,
+                })
+                .collect::<Vec<_>>(),
+        ),
+        Expression::Include(_)
+        | Expression::Sweep(_)
+        | Expression::Simulation(_)
+        | Expression::Compartment(_)
+        | Expression::Event(_) => expressions
+            .iter()
+            .map(|e| match e {
+                Expression::Include(i) => format!("include \"{}\";", i.path),
+                Expression::Sweep(s) => sweep(s),
+                Expression::Simulation(s) => simulation(s),
+                Expression::Compartment(c) => compartment(c),
+                Expression::Event(e) => format!("{e};"),
+                _ => unreachable!("a run only holds expressions of one section"),
+            })
+            .collect(),
+        _ => assignments(
+            &expressions
+                .iter()
+                .filter_map(|e| assignment(e))
+                .collect::<Vec<_>>(),
+        ),
+    }
+}
+
+fn print_items(items: &[Item], language: Language, out: &mut String) {
+    for (i, run) in items
+        .chunk_by(|a, b| section(a.expression) == section(b.expression))
+        .enumerate()
+    {
+        if i > 0 {
+            out.push('\n');
+        }
+        let expressions = run.iter().map(|item| item.expression).collect::<Vec<_>>();
+        for (item, line) in run.iter().zip(lines(&expressions, language)) {
+            for comment in &item.leading {
+                out.push_str(comment);
+                out.push('\n');
+            }
+            out.push_str(&line);
+            for comment in &item.trailing {
+                out.push(' ');
+                out.push_str(comment);
+            }
+            out.push('\n');
+        }
+    }
+}
+
+/// The model as text, one expression per line in the order of `ast`, such that parsing it
+/// gives `ast` back. Consecutive expressions of the same kind are aligned, and keywords
+/// are spelt in `language`.
+pub fn print(Ast(expressions): &Ast, language: Language) -> String {
+    let items = expressions
+        .iter()
+        .map(|expression| Item {
+            expression,
+            leading: vec![],
+            trailing: vec![],
+        })
+        .collect::<Vec<_>>();
+    let mut out = String::new();
+    print_items(&items, language, &mut out);
+    out
+}
+
+/// Byte ranges of the comments of `text`, ignoring what looks like one in a quoted string.
+fn comments(text: &str) -> Vec<(usize, usize)> {
+    let bytes = text.as_bytes();
+    let mut comments = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], bytes.get(i + 1)) {
+            (b'"', _) => i = text[i + 1..].find('"').map_or(text.len(), |j| i + j + 2),
+            (b'/', Some(b'/')) => {
+                let end = text[i..].find('\n').map_or(text.len(), |j| i + j);
+                comments.push((i, end));
+                i = end;
+            }
+            (b'/', Some(b'*')) => {
+                let mut depth = 0;
+                let mut end = i;
+                while end < bytes.len() {
+                    match (bytes[end], bytes.get(end + 1)) {
+                        (b'/', Some(b'*')) => {
+                            depth += 1;
+                            end += 2;
+                        }
+                        (b'*', Some(b'/')) => {
+                            depth -= 1;
+                            end += 2;
+                            if depth == 0 {
+                                break;
+                            }
+                        }
+                        _ => end += 1,
+                    }
+                }
+                comments.push((i, end));
+                i = end;
+            }
+            _ => i += 1,
+        }
+    }
+    comments
+}
+
+/// Offset of the end of the last blank line of `text`.
+fn last_blank_line(text: &str) -> Option<usize> {
+    let mut offset = 0;
+    let mut last = None;
+    let mut lines = text.split('\n').peekable();
+    while let Some(line) = lines.next() {
+        offset += line.len() + 1;
+        if lines.peek().is_some() && line.trim().is_empty() {
+            last = Some(offset);
+        }
+    }
+    last
+}
+
+/// Formats the model `text` canonically: expressions are grouped into sections, includes
+/// first, then parameters, simulation settings, reactions and the declarations of species
+/// sorted by name; each section is aligned, keywords are spelt in `language` and numbers
+/// are written the same way. Comments move with the expression they precede or end the
+/// line of, and those heading the file and separated from it by a blank line stay on top.
+///
+/// Parameters are not resolved, so a model using parameters of the files it includes can
+/// be formatted on its own.
+pub fn format(text: &str, language: Language) -> Result<String, Diagnostic> {
+    let expressions = parse_expressions(text.into())
+        .map_err(|e| e.to_diagnostic(text))?
+        .content;
+    let mut items = expressions
+        .iter()
+        .map(|expression| Item {
+            expression,
+            leading: vec![],
+            trailing: vec![],
+        })
+        .collect::<Vec<_>>();
+    let header_end = last_blank_line(&text[..expressions[0].span().start]).unwrap_or(0);
+    let (mut header, mut footer) = (vec![], vec![]);
+    for (start, end) in comments(text) {
+        let comment = &text[start..end];
+        let next = items
+            .iter()
+            .position(|item| item.expression.span().end > start);
+        let previous = next.unwrap_or(items.len()).checked_sub(1);
+        match (previous, next) {
+            (_, Some(n)) if items[n].expression.span().start <= start => {
+                items[n].leading.push(comment)
+            }
+            (Some(p), _) if !text[items[p].expression.span().end..start].contains('\n') => {
+                items[p].trailing.push(comment)
+            }
+            (None, _) if end <= header_end => header.push(comment),
+            (_, Some(n)) => items[n].leading.push(comment),
+            (_, None) => footer.push(comment),
+        }
+    }
+    items.sort_by_key(|item| {
+        let name = match item.expression {
+            Expression::InitDeclaration(i) => i.identifier.as_str(),
+            Expression::SpeedDeclaration(s) => &s.identifier,
+            Expression::DiameterDeclaration(d) => &d.identifier,
+            _ => "",
+        };
+        (section(item.expression), name)
+    });
+
+    let mut out = String::new();
+    for comment in &header {
+        out.push_str(comment);
+        out.push('\n');
+    }
+    if !header.is_empty() {
+        out.push('\n');
+    }
+    print_items(&items, language, &mut out);
+    if !footer.is_empty() {
+        out.push('\n');
+    }
+    for comment in &footer {
+        out.push_str(comment);
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::generator::Generator;
+    use crate::Parsable;
+
+    #[test]
+    fn t_identifiers() {
+        assert_eq!("glc", identifier("glc"));
+        assert_eq!("µ2", identifier("µ2"));
+        assert_eq!("\"2pg\"", identifier("2pg"));
+        assert_eq!("\"E1--s\"", identifier("E1--s"));
+        assert_eq!(
+            "2 s[\"outer membrane\"] + \"E1--s\"",
+            species(&[("s[outer membrane]".into(), 2), ("E1--s".into(), 1)])
+        );
+        assert_eq!("0", species(&[]));
+    }
+
+    #[test]
+    fn t_format() {
+        let file = "// Header.
+
+init(s)=2000; // substrate
+HK : glc + atp -> g6p | 100uM - 10 /s;
+/* decay */ A -> 0 @ k = 6 /min;
+E2 : i <-> p | (300-1)mM -80, 2 uM - 3 * 2, keq = 1.50;
+init(E1) = 30;
+param km1 = 120 uM;
+// end
+";
+        let formatted = format(file, Language::French).unwrap();
+        assert_eq!(
+            "// Header.
+
+param km1 = 120 uM;
+
+HK : glc + atp  -> g6p | 100 uM - 10 /s;
+E2 : i         <-> p   | (300 - 1) mM - 80, 2 uM - 3 * 2, keq = 1.5;
+
+/* decay */
+A -> 0 @ k = 6 /min;
+
+init(E1) = 30;
+init(s)  = 2000; // substrate
+
+// end
+",
+            formatted
+        );
+        assert_eq!(formatted, format(&formatted, Language::French).unwrap());
+    }
+
+    #[test]
+    fn t_print_round_trip() {
+        let mut generator = Generator::new(12);
+        for i in 0..500 {
+            let ast = generator.ast();
+            let language = match i % 2 {
+                0 => Language::French,
+                _ => Language::English,
+            };
+            let text = print(&ast, language);
+            let parsed = Ast::parse(text.as_str().into());
+            assert_eq!(
+                Ok(ast.without_spans()),
+                parsed.as_ref().map(|p| p.content.without_spans()),
+                "{text}"
+            );
+
+            let formatted = format(&text, language).unwrap();
+            assert_eq!(formatted, format(&formatted, language).unwrap(), "{text}");
+            // Formatting only reorders the expressions.
+            let formatted_ast = Ast::parse(formatted.as_str().into()).unwrap().content;
+            let mut reordered = formatted_ast.without_spans().0;
+            for expression in ast.without_spans().0 {
+                let i = reordered.iter().position(|e| *e == expression);
+                reordered.swap_remove(i.expect(&formatted));
+            }
+            assert!(reordered.is_empty());
+        }
+    }
+}
@@ -0,0 +1,1146 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write;
+
+use roxmltree::{Document, Node};
+
+use crate::compartments::{compartment_of, Compartments};
+use crate::diagnostic::{Diagnostic, Span};
+use crate::expanded::ExpandedModel;
+use crate::include::Sources;
+use crate::kinetics;
+use crate::settings::{Settings, SimulationBlock};
+use crate::{Ast, Expression, InitDeclaration, MassActionReaction, Reaction};
+
+/// Molecules in a mole, with the precision the simulations use for concentrations.
+const AVOGADRO: f64 = 6.022e23;
+/// µM in a mol/l.
+const MICROMOLAR_PER_MOLAR: f64 = 1e6;
+/// nm³ in a litre.
+const NM3_PER_LITRE: f64 = 1e24;
+
+/// Unit of an SBML quantity: `factor` mol^`mole` l^`litre` s^`second`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct SbmlUnit {
+    factor: f64,
+    mole: i32,
+    litre: i32,
+    second: i32,
+}
+
+impl SbmlUnit {
+    const DIMENSIONLESS: SbmlUnit = SbmlUnit {
+        factor: 1.,
+        mole: 0,
+        litre: 0,
+        second: 0,
+    };
+
+    fn base(kind: &str) -> Option<Self> {
+        let unit = |factor, mole, litre, second| SbmlUnit {
+            factor,
+            mole,
+            litre,
+            second,
+        };
+        match kind {
+            "mole" => Some(unit(1., 1, 0, 0)),
+            "item" => Some(unit(1. / AVOGADRO, 1, 0, 0)),
+            "litre" => Some(unit(1., 0, 1, 0)),
+            "second" => Some(unit(1., 0, 0, 1)),
+            "dimensionless" => Some(SbmlUnit::DIMENSIONLESS),
+            _ => None,
+        }
+    }
+    fn times(self, other: Self) -> Self {
+        SbmlUnit {
+            factor: self.factor * other.factor,
+            mole: self.mole + other.mole,
+            litre: self.litre + other.litre,
+            second: self.second + other.second,
+        }
+    }
+    fn pow(self, exponent: i32) -> Self {
+        SbmlUnit {
+            factor: self.factor.powi(exponent),
+            mole: self.mole * exponent,
+            litre: self.litre * exponent,
+            second: self.second * exponent,
+        }
+    }
+    /// Value of `value`, given in this unit, in µM^`concentration` s^`time`, or `None` when
+    /// the unit has other dimensions.
+    fn canonical(self, value: f64, concentration: i32, time: i32) -> Option<f32> {
+        (self.mole == concentration && self.litre == -concentration && self.second == time)
+            .then(|| (value * self.factor * MICROMOLAR_PER_MOLAR.powi(concentration)) as f32)
+    }
+}
+
+/// MathML expression of a kinetic law.
+#[derive(Debug, Clone, PartialEq)]
+enum Math {
+    Number(f64),
+    Name(String),
+    Apply(String, Vec<Math>),
+}
+
+/// Splits a product into the factors of its numerator and of its denominator. Integer
+/// powers count as repeated factors.
+fn factors(math: &Math, numerator: &mut Vec<Math>, denominator: &mut Vec<Math>) {
+    match math {
+        Math::Apply(op, args) if op == "times" => {
+            for arg in args {
+                factors(arg, numerator, denominator);
+            }
+        }
+        Math::Apply(op, args) if op == "divide" && args.len() == 2 => {
+            factors(&args[0], numerator, denominator);
+            factors(&args[1], denominator, numerator);
+        }
+        Math::Apply(op, args)
+            if op == "power"
+                && matches!(args[..], [_, Math::Number(n)] if n > 0. && n.fract() == 0.) =>
+        {
+            let Math::Number(n) = args[1] else {
+                unreachable!()
+            };
+            for _ in 0..n as usize {
+                factors(&args[0], numerator, denominator);
+            }
+        }
+        _ => numerator.push(math.clone()),
+    }
+}
+
+/// The terms of `math` when it is a difference, possibly multiplied or divided by other
+/// factors: `c * (a - b)` is `(c * a, c * b)`.
+fn difference(math: &Math) -> Option<(Math, Math)> {
+    let (mut numerator, mut denominator) = (vec![], vec![]);
+    factors(math, &mut numerator, &mut denominator);
+    let i = numerator
+        .iter()
+        .position(|f| matches!(f, Math::Apply(op, args) if op == "minus" && args.len() == 2))?;
+    let Math::Apply(_, terms) = numerator.remove(i) else {
+        unreachable!()
+    };
+    let term = |t: &Math| {
+        let mut numerator = numerator.clone();
+        numerator.push(t.clone());
+        Math::Apply(
+            "divide".into(),
+            vec![
+                Math::Apply("times".into(), numerator),
+                Math::Apply("times".into(), denominator.clone()),
+            ],
+        )
+    };
+    Some((term(&terms[0]), term(&terms[1])))
+}
+
+struct Importer<'a> {
+    file: usize,
+    units: HashMap<&'a str, SbmlUnit>,
+    /// Units of the model quantities that do not give theirs.
+    substance: SbmlUnit,
+    volume: SbmlUnit,
+    time: SbmlUnit,
+    /// Volumes, in l.
+    compartments: HashMap<&'a str, f64>,
+    /// Whether kinetic laws use the amount of each species rather than its concentration.
+    species: HashMap<&'a str, bool>,
+    /// Values and units of the global parameters.
+    parameters: HashMap<&'a str, (f64, Option<&'a str>)>,
+    diagnostics: Vec<Diagnostic>,
+    expressions: Vec<Expression>,
+}
+
+/// A constant of a kinetic law with its unit, `None` when it does not declare one.
+type Constant = (f64, Option<SbmlUnit>);
+
+/// The species names and constants multiplied in a kinetic law, with its denominator.
+type Product = (Vec<String>, Vec<Constant>, Vec<Math>);
+
+const SUPPORTED_LAWS: &str = "supported kinetic laws are Michaelis–Menten `kcat * E * S / (Km + S)`, with the enzyme `E` a modifier, and mass action `k * A * B`, both optionally multiplied by the compartment";
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.has_tag_name(name))
+}
+
+fn elements<'a, 'input>(node: Node<'a, 'input>) -> impl Iterator<Item = Node<'a, 'input>> {
+    node.children().filter(Node::is_element)
+}
+
+impl<'a> Importer<'a> {
+    fn span(&self, node: Node) -> Span {
+        let range = node.range();
+        Span {
+            start: range.start,
+            end: range.end,
+            file: self.file,
+        }
+    }
+    fn error(&mut self, message: String, node: Node) -> Diagnostic {
+        Diagnostic::error(message, self.span(node))
+    }
+    fn unsupported(&mut self, node: Node, note: &str) {
+        let diagnostic = self
+            .error(
+                format!("`{}` is not supported", node.tag_name().name()),
+                node,
+            )
+            .with_note(note.to_string());
+        self.diagnostics.push(diagnostic);
+    }
+    fn number(&mut self, node: Node, attribute: &str) -> Result<Option<f64>, Diagnostic> {
+        node.attribute(attribute)
+            .map(|x| {
+                x.trim()
+                    .parse::<f64>()
+                    .map_err(|_| self.error(format!("`{attribute}` is not a number: `{x}`"), node))
+            })
+            .transpose()
+    }
+    fn unit(&mut self, id: &str, node: Node) -> Result<SbmlUnit, Diagnostic> {
+        match self.units.get(id).copied().or_else(|| SbmlUnit::base(id)) {
+            Some(unit) => Ok(unit),
+            None => Err(self
+                .error(format!("unit `{id}` is not supported"), node)
+                .with_note(
+                    "units must be made of mole, item, litre, second and dimensionless".into(),
+                )),
+        }
+    }
+    /// The unit of `node` given by its `units` attribute, or `default`.
+    fn unit_of(&mut self, node: Node, default: SbmlUnit) -> Result<SbmlUnit, Diagnostic> {
+        match node.attribute("units") {
+            Some(id) => self.unit(id, node),
+            None => Ok(default),
+        }
+    }
+
+    fn unit_definitions(&mut self, list: Node<'a, '_>) -> Result<(), Diagnostic> {
+        for definition in elements(list).filter(|n| n.has_tag_name("unitDefinition")) {
+            let mut unit = SbmlUnit::DIMENSIONLESS;
+            for u in child(definition, "listOfUnits")
+                .into_iter()
+                .flat_map(elements)
+            {
+                let kind = u.attribute("kind").unwrap_or_default();
+                let base = self.unit(kind, u)?;
+                let exponent = self.number(u, "exponent")?.unwrap_or(1.);
+                let scale = self.number(u, "scale")?.unwrap_or(0.);
+                let multiplier = self.number(u, "multiplier")?.unwrap_or(1.);
+                if exponent.fract() != 0. {
+                    return Err(self.error("unit exponents must be integers".into(), u));
+                }
+                let factor = SbmlUnit {
+                    factor: multiplier * 10f64.powf(scale),
+                    ..SbmlUnit::DIMENSIONLESS
+                };
+                unit = unit.times(factor.times(base).pow(exponent as i32));
+            }
+            if let Some(id) = definition.attribute("id") {
+                self.units.insert(id, unit);
+            }
+        }
+        Ok(())
+    }
+
+    fn compartments(&mut self, list: Node<'a, '_>) -> Result<(), Diagnostic> {
+        for compartment in elements(list) {
+            let id = compartment.attribute("id").unwrap_or_default();
+            if !self.compartments.is_empty() {
+                let diagnostic = self
+                    .error(
+                        format!("compartment `{id}` is one compartment too many"),
+                        compartment,
+                    )
+                    .with_note("models are simulated in a single compartment".into());
+                self.diagnostics.push(diagnostic);
+                continue;
+            }
+            if let Some(d) = self.number(compartment, "spatialDimensions")? {
+                if d != 3. {
+                    return Err(self.error(
+                        format!("compartment `{id}` has {d} dimensions instead of 3"),
+                        compartment,
+                    ));
+                }
+            }
+            let Some(size) = self.number(compartment, "size")? else {
+                return Err(self.error(format!("compartment `{id}` has no size"), compartment));
+            };
+            let unit = self.unit_of(compartment, self.volume)?;
+            if (unit.mole, unit.litre, unit.second) != (0, 1, 0) {
+                return Err(self.error(
+                    format!("the size of compartment `{id}` is not a volume"),
+                    compartment,
+                ));
+            }
+            let litres = size * unit.factor;
+            self.compartments.insert(id, litres);
+            let radius = (3. * litres * NM3_PER_LITRE / (4. * std::f64::consts::PI)).cbrt();
+            self.expressions
+                .push(Expression::Simulation(SimulationBlock {
+                    radius: Some((radius as f32).into()),
+                    span: self.span(compartment),
+                    ..Default::default()
+                }));
+        }
+        Ok(())
+    }
+
+    fn species(&mut self, list: Node<'a, '_>) -> Result<(), Diagnostic> {
+        for species in elements(list) {
+            let id = species.attribute("id").unwrap_or_default();
+            let compartment = species.attribute("compartment").unwrap_or_default();
+            if species.attribute("boundaryCondition") == Some("true") {
+                let diagnostic = self
+                    .error(format!("boundary species `{id}` is not supported"), species)
+                    .with_note("every species is consumed and produced by its reactions".into());
+                self.diagnostics.push(diagnostic);
+            }
+            let substance = self.substance;
+            let substance = match species.attribute("substanceUnits") {
+                Some(units) => self.unit(units, species)?,
+                None => substance,
+            };
+            let volume = self.compartments.get(compartment).copied();
+            let moles = match (
+                self.number(species, "initialAmount")?,
+                self.number(species, "initialConcentration")?,
+                volume,
+            ) {
+                (Some(amount), _, _) => Some(amount * substance.factor),
+                (None, Some(concentration), Some(volume)) => {
+                    Some(concentration * substance.factor / self.volume.factor * volume)
+                }
+                (None, Some(_), None) => {
+                    return Err(self.error(
+                        format!("species `{id}` is in unknown compartment `{compartment}`"),
+                        species,
+                    ))
+                }
+                (None, None, _) => None,
+            };
+            self.species.insert(
+                id,
+                species.attribute("hasOnlySubstanceUnits") == Some("true"),
+            );
+            let Some(moles) = moles else {
+                let diagnostic = Diagnostic::warning(
+                    format!("species `{id}` has no initial amount"),
+                    self.span(species),
+                )
+                .with_note("it starts with no molecules".into());
+                self.diagnostics.push(diagnostic);
+                continue;
+            };
+            let molecules = (moles * AVOGADRO).round();
+            if molecules > u64::MAX as f64 {
+                return Err(self
+                    .error(format!("species `{id}` has {molecules} molecules"), species)
+                    .with_note(format!("at most {} molecules can be simulated", u64::MAX)));
+            }
+            self.expressions
+                .push(Expression::InitDeclaration(InitDeclaration {
+                    identifier: id.to_string(),
+                    number: molecules as u64,
+                    span: self.span(species),
+                }));
+        }
+        Ok(())
+    }
+
+    fn parameters(&mut self, list: Node<'a, '_>) -> Result<(), Diagnostic> {
+        for parameter in elements(list) {
+            let id = parameter.attribute("id").unwrap_or_default();
+            let Some(value) = self.number(parameter, "value")? else {
+                return Err(self.error(format!("parameter `{id}` has no value"), parameter));
+            };
+            self.parameters
+                .insert(id, (value, parameter.attribute("units")));
+        }
+        Ok(())
+    }
+
+    fn math(&mut self, node: Node) -> Result<Math, Diagnostic> {
+        let text = node.text().unwrap_or_default().trim();
+        match node.tag_name().name() {
+            "ci" => Ok(Math::Name(text.to_string())),
+            "cn" => {
+                let parts = node
+                    .children()
+                    .filter_map(|c| c.text())
+                    .map(str::trim)
+                    .filter(|t| !t.is_empty())
+                    .map(str::parse::<f64>)
+                    .collect::<Result<Vec<_>, _>>();
+                match (node.attribute("type"), parts.as_deref()) {
+                    (None | Some("real" | "integer"), Ok([x])) => Ok(Math::Number(*x)),
+                    (Some("e-notation"), Ok([m, e])) => Ok(Math::Number(m * 10f64.powf(*e))),
+                    (Some("rational"), Ok([n, d])) => Ok(Math::Number(n / d)),
+                    _ => Err(self.error("unsupported MathML number".into(), node)),
+                }
+            }
+            "apply" => {
+                let mut children = elements(node);
+                let operator = children
+                    .next()
+                    .ok_or_else(|| self.error("empty MathML `apply`".into(), node))?;
+                let op = operator.tag_name().name();
+                if !["times", "divide", "plus", "minus", "power"].contains(&op) {
+                    return Err(self.error(format!("MathML `{op}` is not supported"), operator));
+                }
+                let args = children
+                    .map(|c| self.math(c))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Math::Apply(op.to_string(), args))
+            }
+            other => Err(self.error(format!("MathML `{other}` is not supported"), node)),
+        }
+    }
+
+    /// Value and unit of the parameter or number `math`.
+    fn constant(
+        &mut self,
+        math: &Math,
+        locals: &HashMap<&str, (f64, Option<&str>)>,
+        node: Node,
+    ) -> Result<Option<Constant>, Diagnostic> {
+        let (value, units) = match math {
+            Math::Number(x) => return Ok(Some((*x, Some(SbmlUnit::DIMENSIONLESS)))),
+            Math::Name(name) => match locals
+                .get(name.as_str())
+                .or(self.parameters.get(name.as_str()))
+            {
+                Some(&parameter) => parameter,
+                None => return Ok(None),
+            },
+            Math::Apply(..) => return Ok(None),
+        };
+        let unit = units.map(|id| self.unit(id, node)).transpose()?;
+        Ok(Some((value, unit)))
+    }
+
+    /// Product of the `constants`, with its unit when they all declare one.
+    fn product(constants: &[Constant]) -> Constant {
+        constants.iter().fold(
+            (1., Some(SbmlUnit::DIMENSIONLESS)),
+            |(value, unit), (x, u)| (value * x, unit.zip(*u).map(|(a, b)| a.times(b))),
+        )
+    }
+
+    /// Value of a kinetic constant in µM^`concentration` s^`time`. Without a declared
+    /// unit, the constant is in the substance, volume and time units of the model.
+    fn kinetic_constant(
+        &mut self,
+        (value, unit): Constant,
+        concentration: i32,
+        time: i32,
+        what: &str,
+        node: Node,
+    ) -> Result<f32, Diagnostic> {
+        let unit = unit.unwrap_or_else(|| {
+            self.substance
+                .times(self.volume.pow(-1))
+                .pow(concentration)
+                .times(self.time.pow(time))
+        });
+        unit.canonical(value, concentration, time).ok_or_else(|| {
+            self.error(
+                format!(
+                    "{what} has units of mol^{} l^{} s^{} instead of µM^{concentration} s^{time}",
+                    unit.mole, unit.litre, unit.second
+                ),
+                node,
+            )
+        })
+    }
+
+    fn stoichiometry(&mut self, list: Option<Node>) -> Result<Vec<(String, u32)>, Diagnostic> {
+        let mut species: Vec<(String, u32)> = vec![];
+        for reference in list.into_iter().flat_map(elements) {
+            let id = reference.attribute("species").unwrap_or_default();
+            let n = self.number(reference, "stoichiometry")?.unwrap_or(1.);
+            if n <= 0. || n.fract() != 0. {
+                return Err(self.error(
+                    format!("stoichiometry of `{id}` is {n}, not a positive integer"),
+                    reference,
+                ));
+            }
+            match species.iter_mut().find(|(s, _)| s == id) {
+                Some((_, count)) => *count += n as u32,
+                None => species.push((id.to_string(), n as u32)),
+            }
+        }
+        Ok(species)
+    }
+
+    /// The species and constants multiplied in `term`, compartments left out, or `None`
+    /// when it multiplies something else.
+    fn split(
+        &mut self,
+        term: &Math,
+        locals: &HashMap<&str, (f64, Option<&str>)>,
+        node: Node,
+    ) -> Result<Option<Product>, Diagnostic> {
+        let (mut numerator, mut denominator) = (vec![], vec![]);
+        factors(term, &mut numerator, &mut denominator);
+        let (mut species, mut constants) = (vec![], vec![]);
+        for factor in &numerator {
+            match factor {
+                Math::Name(name) if self.species.contains_key(name.as_str()) => {
+                    species.push(name.clone())
+                }
+                Math::Name(name) if self.compartments.contains_key(name.as_str()) => (),
+                _ => match self.constant(factor, locals, node)? {
+                    Some(constant) => constants.push(constant),
+                    None => return Ok(None),
+                },
+            }
+        }
+        Ok(Some((species, constants, denominator)))
+    }
+
+    fn reaction(&mut self, reaction: Node<'a, '_>) -> Result<Vec<Expression>, Diagnostic> {
+        let id = reaction.attribute("id").unwrap_or_default();
+        let reactants = self.stoichiometry(child(reaction, "listOfReactants"))?;
+        let products = self.stoichiometry(child(reaction, "listOfProducts"))?;
+        let modifiers = child(reaction, "listOfModifiers")
+            .into_iter()
+            .flat_map(elements)
+            .filter_map(|m| m.attribute("species"))
+            .collect::<Vec<_>>();
+        let Some(law) = child(reaction, "kineticLaw") else {
+            return Err(self.error(format!("reaction `{id}` has no kinetic law"), reaction));
+        };
+        let Some(math) = child(law, "math").and_then(|m| elements(m).next()) else {
+            return Err(self.error(format!("kinetic law of `{id}` has no math"), law));
+        };
+        let math = self.math(math)?;
+        let mut locals = HashMap::new();
+        for parameter in ["listOfLocalParameters", "listOfParameters"]
+            .into_iter()
+            .filter_map(|list| child(law, list))
+            .flat_map(elements)
+        {
+            let name = parameter.attribute("id").unwrap_or_default();
+            let value = self.number(parameter, "value")?.unwrap_or(f64::NAN);
+            locals.insert(name, (value, parameter.attribute("units")));
+        }
+        let in_amounts = |species: &[String], importer: &Self| {
+            species
+                .iter()
+                .find(|s| importer.species.get(s.as_str()) == Some(&true))
+                .cloned()
+        };
+        let span = self.span(reaction);
+        let unrecognized = || {
+            Diagnostic::error(
+                format!(
+                    "kinetic law of reaction `{id}` is neither Michaelis–Menten nor mass action"
+                ),
+                span,
+            )
+            .with_note(SUPPORTED_LAWS.into())
+        };
+
+        // Michaelis–Menten: kcat * E * S / (Km + S)
+        if let Some((species, constants, denominator)) = self.split(&math, &locals, law)? {
+            if let ([(substrate, 1)], [enzyme], [Math::Apply(plus, terms)]) =
+                (&reactants[..], &modifiers[..], &denominator[..])
+            {
+                let mut bound = species.clone();
+                bound.sort();
+                let mut expected = vec![enzyme.to_string(), substrate.clone()];
+                expected.sort();
+                let km = match &terms[..] {
+                    [Math::Name(s), km] | [km, Math::Name(s)] if s == substrate => Some(km),
+                    _ => None,
+                };
+                if plus == "plus" && bound == expected {
+                    let km = match km {
+                        Some(km) => self.constant(km, &locals, law)?,
+                        None => None,
+                    };
+                    let Some(km) = km else {
+                        return Err(unrecognized());
+                    };
+                    if let Some(s) = in_amounts(&species, self) {
+                        return Err(self
+                            .error(
+                                format!("kinetic law of reaction `{id}` uses the amount of `{s}`"),
+                                reaction,
+                            )
+                            .with_note(
+                                "only concentrations are supported in kinetic laws".into(),
+                            ));
+                    }
+                    let km = self.kinetic_constant(km, 1, 0, &format!("Km of `{id}`"), law)?;
+                    let kcat = Self::product(&constants);
+                    let kcat =
+                        self.kinetic_constant(kcat, 0, -1, &format!("kcat of `{id}`"), law)?;
+                    return Ok(vec![Expression::Reaction(Box::new(Reaction {
+                        enzhym: enzyme.to_string(),
+                        solubes: reactants,
+                        results: products,
+                        km: km.into(),
+                        kcat: kcat.into(),
+                        reverse: None,
+                        brenda: None,
+                        modifiers: vec![],
+                        span,
+                    }))]);
+                }
+            }
+        }
+
+        // Mass action: k * A * B, minus the reverse reaction when it is reversible.
+        let terms = match difference(&math) {
+            Some((forward, backward)) => vec![
+                (forward, &reactants, &products),
+                (backward, &products, &reactants),
+            ],
+            None => vec![(math, &reactants, &products)],
+        };
+        let mut expressions = vec![];
+        for (term, consumed, produced) in terms {
+            let Some((mut species, constants, denominator)) = self.split(&term, &locals, law)?
+            else {
+                return Err(unrecognized());
+            };
+            let mut expected = crate::stoichiometric_units(consumed)
+                .cloned()
+                .collect::<Vec<_>>();
+            species.sort();
+            expected.sort();
+            if !denominator.is_empty() || species != expected || expected.len() > 2 {
+                return Err(unrecognized());
+            }
+            if let Some(s) = in_amounts(&species, self).filter(|_| species.len() > 1) {
+                return Err(self
+                    .error(
+                        format!("kinetic law of reaction `{id}` uses the amount of `{s}`"),
+                        reaction,
+                    )
+                    .with_note("only concentrations are supported in kinetic laws".into()));
+            }
+            let order = species.len() as i32;
+            let k = Self::product(&constants);
+            let k = self.kinetic_constant(k, 1 - order, -1, &format!("k of `{id}`"), law)?;
+            expressions.push(Expression::MassAction(MassActionReaction {
+                reactants: consumed.clone(),
+                products: produced.clone(),
+                k: k.into(),
+                span,
+            }));
+        }
+        Ok(expressions)
+    }
+
+    fn model(&mut self, model: Node<'a, '_>) -> Result<(), Diagnostic> {
+        if let Some(list) = child(model, "listOfUnitDefinitions") {
+            self.unit_definitions(list)?;
+        }
+        for (attribute, default) in [
+            ("substanceUnits", "mole"),
+            ("volumeUnits", "litre"),
+            ("timeUnits", "second"),
+        ] {
+            let unit = self.unit(model.attribute(attribute).unwrap_or(default), model)?;
+            match attribute {
+                "substanceUnits" => self.substance = unit,
+                "volumeUnits" => self.volume = unit,
+                _ => self.time = unit,
+            }
+        }
+        for list in elements(model) {
+            match list.tag_name().name() {
+                "listOfUnitDefinitions" | "notes" | "annotation" => (),
+                "listOfCompartments" => self.compartments(list)?,
+                "listOfSpecies" => self.species(list)?,
+                "listOfParameters" => self.parameters(list)?,
+                "listOfReactions" => {
+                    for reaction in elements(list) {
+                        match self.reaction(reaction) {
+                            Ok(expressions) => self.expressions.extend(expressions),
+                            Err(diagnostic) => self.diagnostics.push(diagnostic),
+                        }
+                    }
+                }
+                _ => self.unsupported(list, "the model is imported without it"),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Ast {
+    /// Imports the SBML Level 3 core model at `path`, whose content is `text`, added to
+    /// `sources`. Compartments set the volume of the simulation, initial amounts and
+    /// concentrations become molecule counts, and Michaelis–Menten and mass-action kinetic
+    /// laws become reactions.
+    ///
+    /// What cannot be imported is reported in the diagnostics returned with the model, as
+    /// errors when the model does not behave as the SBML one without it.
+    pub fn load_sbml(
+        path: &str,
+        text: String,
+        sources: &mut Sources,
+    ) -> Result<(Ast, Vec<Diagnostic>), Diagnostic> {
+        let file = sources.add(path.to_string(), text);
+        let text = sources.text(file);
+        let document = Document::parse(text).map_err(|e| {
+            let pos = e.pos();
+            Diagnostic::error(
+                e.to_string(),
+                Span::at(text, pos.row as usize, pos.col as usize, file),
+            )
+        })?;
+        let root = document.root_element();
+        let whole = Span {
+            start: 0,
+            end: 0,
+            file,
+        };
+        if !root.has_tag_name("sbml") {
+            return Err(Diagnostic::error("not an SBML document".into(), whole));
+        }
+        if root.attribute("level") != Some("3") {
+            return Err(Diagnostic::error(
+                format!(
+                    "SBML level {} is not supported",
+                    root.attribute("level").unwrap_or("?")
+                ),
+                whole,
+            )
+            .with_note("only SBML Level 3 core models can be imported".into()));
+        }
+        let Some(model) = child(root, "model") else {
+            return Err(Diagnostic::error(
+                "SBML document without a model".into(),
+                whole,
+            ));
+        };
+        let mut importer = Importer {
+            file,
+            units: HashMap::new(),
+            substance: SbmlUnit::base("mole").unwrap(),
+            volume: SbmlUnit::base("litre").unwrap(),
+            time: SbmlUnit::base("second").unwrap(),
+            compartments: HashMap::new(),
+            species: HashMap::new(),
+            parameters: HashMap::new(),
+            diagnostics: vec![],
+            expressions: vec![],
+        };
+        importer.model(model)?;
+        let mut diagnostics = importer.diagnostics;
+        diagnostics.sort_by_key(|d| d.span.start);
+        Ok((Ast(importer.expressions), diagnostics))
+    }
+}
+
+/// Whether the model at `path` is an SBML file, from its extension.
+pub fn is_sbml(path: &str) -> bool {
+    std::path::Path::new(path)
+        .extension()
+        .is_some_and(|extension| extension == "xml" || extension == "sbml")
+}
+
+/// `name` with the characters SBML identifiers cannot hold replaced by `_`, made distinct
+/// from the identifiers already `taken`.
+fn sbml_id(name: &str, taken: &mut HashSet<String>) -> String {
+    let mut id = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect::<String>();
+    if !id.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        id.insert(0, '_');
+    }
+    let mut unique = id.clone();
+    for i in 1.. {
+        if !taken.contains(&unique) {
+            break;
+        }
+        unique = format!("{id}_{i}");
+    }
+    taken.insert(unique.clone());
+    unique
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const UNIT_DEFINITIONS: &str = r#"    <listOfUnitDefinitions>
+      <unitDefinition id="micromole">
+        <listOfUnits>
+          <unit kind="mole" exponent="1" scale="-6" multiplier="1"/>
+        </listOfUnits>
+      </unitDefinition>
+      <unitDefinition id="uM_per_second">
+        <listOfUnits>
+          <unit kind="mole" exponent="1" scale="-6" multiplier="1"/>
+          <unit kind="litre" exponent="-1" scale="0" multiplier="1"/>
+          <unit kind="second" exponent="-1" scale="0" multiplier="1"/>
+        </listOfUnits>
+      </unitDefinition>
+      <unitDefinition id="per_second">
+        <listOfUnits>
+          <unit kind="second" exponent="-1" scale="0" multiplier="1"/>
+        </listOfUnits>
+      </unitDefinition>
+      <unitDefinition id="per_uM_per_second">
+        <listOfUnits>
+          <unit kind="mole" exponent="-1" scale="-6" multiplier="1"/>
+          <unit kind="litre" exponent="1" scale="0" multiplier="1"/>
+          <unit kind="second" exponent="-1" scale="0" multiplier="1"/>
+        </listOfUnits>
+      </unitDefinition>
+    </listOfUnitDefinitions>
+"#;
+
+/// The model `ast`, simulated with `settings`, as an SBML Level 3 Version 2 core document
+/// that deterministic solvers can run. The simulated sphere around the compartments of the
+/// model is the `cell` compartment, and concentrations are in µM.
+///
+/// Reactions are those of the [`ExpandedModel`], with the enzyme-substrate complexes as
+/// species. Speeds and diameters have no SBML counterpart and are left out.
+pub fn export(ast: &Ast, settings: &Settings) -> String {
+    let ExpandedModel { species, reactions } = ExpandedModel::new(ast, settings);
+    let compartments = Compartments::new(ast, settings);
+    let mut taken = HashSet::from(["cell".to_string()]);
+    let compartment_ids = compartments
+        .iter()
+        .map(|c| (c.identifier.as_str(), sbml_id(&c.identifier, &mut taken)))
+        .collect::<HashMap<_, _>>();
+    let compartment_id = |species: &str| {
+        compartment_of(species)
+            .and_then(|c| compartment_ids.get(c))
+            .map_or("cell", String::as_str)
+    };
+    let ids = species
+        .iter()
+        .map(|(name, _)| (name.as_str(), sbml_id(name, &mut taken)))
+        .collect::<HashMap<_, _>>();
+
+    let mut out = String::new();
+    out += "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n";
+    out += "<sbml xmlns=\"http://www.sbml.org/sbml/level3/version2/core\" level=\"3\" version=\"2\">\n";
+    out += "  <model substanceUnits=\"micromole\" volumeUnits=\"litre\" timeUnits=\"second\" extentUnits=\"micromole\">\n";
+    out += UNIT_DEFINITIONS;
+    out += "    <listOfCompartments>\n";
+    let sizes =
+        std::iter::once(("cell", compartments.volume(None))).chain(compartments.iter().map(|c| {
+            (
+                compartment_ids[c.identifier.as_str()].as_str(),
+                compartments.volume(Some(&c.identifier)),
+            )
+        }));
+    for (id, volume) in sizes {
+        let _ = writeln!(
+            out,
+            "      <compartment id=\"{id}\" spatialDimensions=\"3\" size=\"{:e}\" units=\"litre\" constant=\"true\"/>",
+            volume as f64 / NM3_PER_LITRE
+        );
+    }
+    out += "    </listOfCompartments>\n";
+    out += "    <listOfSpecies>\n";
+    for (name, number) in &species {
+        let _ = writeln!(
+            out,
+            "      <species id=\"{}\" name=\"{}\" compartment=\"{}\" initialConcentration=\"{}\" hasOnlySubstanceUnits=\"false\" boundaryCondition=\"false\" constant=\"false\"/>",
+            ids[name.as_str()],
+            escape(name),
+            compartment_id(name),
+            *number as f32 / kinetics::molecules_per_micromolar_in(compartments.volume_of(name))
+        );
+    }
+    out += "    </listOfSpecies>\n";
+    out += "    <listOfReactions>\n";
+    let references = |out: &mut String, list: &str, species: &[(String, u32)]| {
+        if species.is_empty() {
+            return;
+        }
+        let _ = writeln!(out, "        <{list}>");
+        for (name, n) in species {
+            let _ = writeln!(
+                out,
+                "          <speciesReference species=\"{}\" stoichiometry=\"{n}\" constant=\"true\"/>",
+                ids[name.as_str()]
+            );
+        }
+        let _ = writeln!(out, "        </{list}>");
+    };
+    for r in &reactions {
+        let id = sbml_id(&r.id, &mut taken);
+        let _ = writeln!(
+            out,
+            "      <reaction id=\"{id}\" name=\"{}\" reversible=\"false\">",
+            escape(&r.name)
+        );
+        references(&mut out, "listOfReactants", &r.reactants);
+        references(&mut out, "listOfProducts", &r.products);
+        out += "        <kineticLaw>\n";
+        out += "          <math xmlns=\"http://www.w3.org/1998/Math/MathML\">\n";
+        // The reaction happens in the compartment of its reactants, or of its products for
+        // a synthesis.
+        let compartment = r
+            .reactants
+            .iter()
+            .chain(&r.products)
+            .next()
+            .map_or("cell", |(name, _)| compartment_id(name));
+        let _ = write!(
+            out,
+            "            <apply>\n              <times/>\n              <ci> {compartment} </ci>\n              <ci> k </ci>\n"
+        );
+        for (name, n) in &r.reactants {
+            for _ in 0..*n {
+                let _ = writeln!(out, "              <ci> {} </ci>", ids[name.as_str()]);
+            }
+        }
+        out += "            </apply>\n          </math>\n";
+        let units = match r.order() {
+            0 => "uM_per_second",
+            1 => "per_second",
+            _ => "per_uM_per_second",
+        };
+        let _ = writeln!(
+            out,
+            "          <listOfLocalParameters>\n            <localParameter id=\"k\" value=\"{}\" units=\"{units}\"/>\n          </listOfLocalParameters>",
+            r.k
+        );
+        out += "        </kineticLaw>\n      </reaction>\n";
+    }
+    out += "    </listOfReactions>\n  </model>\n</sbml>\n";
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::diagnostic::Severity;
+    use crate::Parsable;
+
+    #[test]
+    fn t_units() {
+        let millimolar = SbmlUnit::base("mole")
+            .unwrap()
+            .times(SbmlUnit::base("litre").unwrap().pow(-1))
+            .times(SbmlUnit {
+                factor: 1e-3,
+                ..SbmlUnit::DIMENSIONLESS
+            });
+        assert_eq!(Some(2000.), millimolar.canonical(2., 1, 0));
+        assert_eq!(None, millimolar.canonical(2., 1, -1));
+        let per_minute = SbmlUnit {
+            factor: 60.,
+            ..SbmlUnit::base("second").unwrap()
+        }
+        .pow(-1);
+        assert_eq!(Some(0.5), per_minute.canonical(30., 0, -1));
+        assert_eq!(None, SbmlUnit::base("metre"));
+    }
+
+    #[test]
+    fn t_difference() {
+        let name = |n: &str| Math::Name(n.into());
+        let apply = |op: &str, args: Vec<Math>| Math::Apply(op.into(), args);
+        // compartment * (kf * A - kr * B) / K^2
+        let law = apply(
+            "divide",
+            vec![
+                apply(
+                    "times",
+                    vec![
+                        name("c"),
+                        apply(
+                            "minus",
+                            vec![
+                                apply("times", vec![name("kf"), name("A")]),
+                                apply("times", vec![name("kr"), name("B")]),
+                            ],
+                        ),
+                    ],
+                ),
+                apply("power", vec![name("K"), Math::Number(2.)]),
+            ],
+        );
+        let (mut numerator, mut denominator) = (vec![], vec![]);
+        factors(&law, &mut numerator, &mut denominator);
+        assert_eq!(2, numerator.len());
+        assert_eq!(vec![name("K"), name("K")], denominator);
+
+        let (forward, _) = difference(&law).unwrap();
+        let (mut numerator, mut denominator) = (vec![], vec![]);
+        factors(&forward, &mut numerator, &mut denominator);
+        assert_eq!(vec![name("c"), name("kf"), name("A")], numerator);
+        assert_eq!(vec![name("K"), name("K")], denominator);
+        assert_eq!(
+            None,
+            difference(&apply("times", vec![name("k"), name("A")]))
+        );
+    }
+
+    #[test]
+    fn t_sbml() {
+        let mut sources = Sources::default();
+        let text = include_str!("./test_input.xml").to_string();
+        let (Ast(expressions), diagnostics) =
+            Ast::load_sbml("model.xml", text, &mut sources).unwrap();
+        let Expression::Simulation(SimulationBlock {
+            radius: Some(radius),
+            ..
+        }) = &expressions[0]
+        else {
+            panic!("expected the volume of the compartment")
+        };
+        assert!((radius.value - 100.).abs() < 1e-3);
+        let inits = expressions[1..4]
+            .iter()
+            .map(|e| match e {
+                Expression::InitDeclaration(i) => (i.identifier.as_str(), i.number),
+                _ => panic!("expected initial amounts"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(vec![("E1", 30), ("glc", 252), ("g6p", 0)], inits);
+        let Expression::Reaction(hexokinase) = &expressions[4] else {
+            panic!("expected a Michaelis–Menten reaction")
+        };
+        assert_eq!(
+            ("E1", 100., 10.),
+            (
+                hexokinase.enzhym.as_str(),
+                hexokinase.km.value,
+                hexokinase.kcat.value
+            )
+        );
+        let mass_actions = expressions[5..]
+            .iter()
+            .map(|e| match e {
+                Expression::MassAction(r) => (r.reactants.clone(), r.products.clone(), r.k.value),
+                _ => panic!("expected mass-action reactions"),
+            })
+            .collect::<Vec<_>>();
+        let species = |s: &str| vec![(s.to_string(), 1)];
+        assert_eq!(
+            vec![
+                (species("g6p"), species("f6p"), 2.),
+                (species("f6p"), species("g6p"), 1.),
+                (vec![], species("glc"), 50.),
+            ],
+            mass_actions
+        );
+
+        let diagnostics = diagnostics
+            .iter()
+            .map(|d| (d.severity, d.message.as_str(), sources.render(d)))
+            .collect::<Vec<_>>();
+        assert_eq!(3, diagnostics.len());
+        assert_eq!(
+            (Severity::Warning, "species `f6p` has no initial amount"),
+            (diagnostics[0].0, diagnostics[0].1)
+        );
+        assert_eq!(
+            (Severity::Error, "`listOfRules` is not supported"),
+            (diagnostics[1].0, diagnostics[1].1)
+        );
+        assert!(diagnostics[1].2.contains("model.xml:37:5"));
+        assert_eq!(
+            (
+                Severity::Error,
+                "kinetic law of reaction `hill` is neither Michaelis–Menten nor mass action"
+            ),
+            (diagnostics[2].0, diagnostics[2].1)
+        );
+
+        let mut sources = Sources::default();
+        let error = Ast::load_sbml(
+            "model.xml",
+            "<sbml level=\"2\" version=\"4\"><model/></sbml>".into(),
+            &mut sources,
+        )
+        .unwrap_err();
+        assert_eq!("SBML level 2 is not supported", error.message);
+    }
+
+    #[test]
+    fn t_ids() {
+        let mut taken = HashSet::new();
+        assert_eq!("E1__s", sbml_id("E1--s", &mut taken));
+        assert_eq!("E1__s_1", sbml_id("E1__s", &mut taken));
+        assert_eq!("_2pg", sbml_id("2pg", &mut taken));
+        assert_eq!("_", sbml_id("µ", &mut taken));
+        assert_eq!("a &lt;&amp;&gt; &quot;b&quot;", escape("a <&> \"b\""));
+        assert!(is_sbml("models/glycolysis.xml"));
+        assert!(!is_sbml("models/xml.txt"));
+    }
+
+    #[test]
+    fn t_sbml_export() {
+        let model = "E1 : s -> \"E1--x\" | 200 uM - 100;\n2 A -> 0 @ k = 0.5;\ninit(E1) = 30;\ninit(s) = 2000;";
+        let ast = Ast::parse(model.into()).unwrap().content;
+        let settings = Settings::default();
+        let exported = export(&ast, &settings);
+        let mut sources = Sources::default();
+        let (Ast(expressions), diagnostics) =
+            Ast::load_sbml("model.xml", exported, &mut sources).unwrap();
+        assert!(diagnostics.is_empty());
+
+        // The complex is a species of its own, whose name is not an SBML identifier.
+        let inits = expressions
+            .iter()
+            .filter_map(|e| match e {
+                Expression::InitDeclaration(i) => Some((i.identifier.as_str(), i.number)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("E1", 30),
+                ("s", 2000),
+                ("E1__s", 0),
+                ("E1__x", 0),
+                ("A", 0)
+            ],
+            inits
+        );
+        let (k_on, k_off, kcat) = kinetics::elementary_rate_constants(200., 100., &settings);
+        let reactions = expressions
+            .iter()
+            .filter_map(|e| match e {
+                Expression::MassAction(r) => Some((&r.reactants, &r.products, r.k.value)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let one = |s: &str| vec![(s.to_string(), 1)];
+        let expected = [
+            (
+                vec![("E1".to_string(), 1), ("s".to_string(), 1)],
+                one("E1__s"),
+                k_on,
+            ),
+            (
+                one("E1__s"),
+                vec![("E1".to_string(), 1), ("s".to_string(), 1)],
+                k_off,
+            ),
+            (
+                one("E1__s"),
+                vec![("E1".to_string(), 1), ("E1__x".to_string(), 1)],
+                kcat,
+            ),
+            (vec![("A".to_string(), 2)], vec![], 0.5),
+        ];
+        assert_eq!(expected.len(), reactions.len());
+        for ((reactants, products, k), (r, p, imported)) in expected.iter().zip(reactions) {
+            assert_eq!((reactants, products), (r, p));
+            assert!((imported - k).abs() <= 1e-5 * k, "{imported} != {k}");
+        }
+    }
+}
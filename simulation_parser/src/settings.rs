@@ -0,0 +1,275 @@
+use serde::{Deserialize, Serialize};
+
+use crate::brenda_parser_helpers::*;
+use crate::diagnostic::Span;
+use crate::formula::Value;
+use crate::parser_combinator::*;
+use crate::units::{quantity, Dimension};
+use crate::Parsable;
+
+/// Edge of the cells of the spatial hash the entity-centered simulation finds collisions
+/// with, in nm. Only molecules in the same cell can collide.
+pub const HASH_CELL_SIZE: f32 = 10.;
+
+/// `simulation { end_time = 60 s; sample_every = 50 ms; seed = 42; dt = 100 us; radius = 500 nm; }`
+///
+/// Every setting is optional, see [`Settings`] for the values used when they are missing.
+#[derive(Debug, PartialEq, Default, Clone, Serialize, Deserialize)]
+pub struct SimulationBlock {
+    /// In s.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub end_time: Option<Value>,
+    /// In s.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sample_every: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub seed: Option<u64>,
+    /// In s.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dt: Option<Value>,
+    /// In nm.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub radius: Option<Value>,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+enum Setting {
+    EndTime(Value),
+    SampleEvery(Value),
+    Seed(u64),
+    Dt(Value),
+    Radius(Value),
+}
+
+fn setting<'a>() -> impl Parser<'a, Setting> {
+    let named = |name: &'static str| between_spaces(literal(name)).skip_next(parse_equal());
+    named("end_time")
+        .skip_me(parse_time())
+        .map(Setting::EndTime)
+        .or_else(
+            named("sample_every")
+                .skip_me(parse_time())
+                .map(Setting::SampleEvery),
+        )
+        .or_else(named("seed").skip_me(parse_count()).map(Setting::Seed))
+        .or_else(named("dt").skip_me(parse_time()).map(Setting::Dt))
+        .or_else(named("radius").skip_me(parse_length()).map(Setting::Radius))
+        .skip_next(parse_semicolon())
+}
+
+impl Parsable for SimulationBlock {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess { next_input, .. } = parse_simulation().parse(start)?;
+        let ParserSuccess { mut next_input, .. } = parse_lbrace().parse(next_input)?;
+        let mut block = SimulationBlock::default();
+        while let Err(rbrace_error) = parse_rbrace().parse(next_input) {
+            let ParserSuccess {
+                content,
+                next_input: rest,
+            } = setting()
+                .parse(next_input)
+                .map_err(|e| e.merge(rbrace_error))?;
+            let ParserSuccess { next_input: at, .. } = space0().parse(next_input)?;
+            let duplicate = match content {
+                Setting::EndTime(x) => block.end_time.replace(x).is_some(),
+                Setting::SampleEvery(x) => block.sample_every.replace(x).is_some(),
+                Setting::Seed(x) => block.seed.replace(x).is_some(),
+                Setting::Dt(x) => block.dt.replace(x).is_some(),
+                Setting::Radius(x) => block.radius.replace(x).is_some(),
+            };
+            if duplicate {
+                return Err(at.generate_error("Setting already given".to_string()));
+            }
+            next_input = rest;
+        }
+        let ParserSuccess { next_input, .. } = parse_rbrace().parse(next_input)?;
+        Ok(ParserSuccess {
+            content: SimulationBlock {
+                span: next_input.span_from(start),
+                ..block
+            },
+            next_input,
+        })
+    }
+}
+
+impl SimulationBlock {
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        [
+            &mut self.end_time,
+            &mut self.sample_every,
+            &mut self.dt,
+            &mut self.radius,
+        ]
+        .into_iter()
+        .filter_map(Option::as_mut)
+        .collect()
+    }
+}
+
+/// How a model is simulated, from its `simulation` block, with defaults for what is not
+/// set there.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Settings {
+    /// Simulated duration, in s. Defaults to 60 s.
+    pub end_time: f32,
+    /// Interval between two rows of the results, in s. Defaults to 50 ms.
+    pub sample_every: f32,
+    /// Seed of the random number generator. A random seed is used when there is none.
+    pub seed: Option<u64>,
+    /// Duration of a step, in s. Defaults to 100 µs, the step the probabilities of
+    /// enzymatic reactions were fitted for (see proba.txt).
+    pub dt: f32,
+    /// Radius of the simulated sphere, in nm. Defaults to 500 nm.
+    pub radius: f32,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            end_time: 60.,
+            sample_every: 0.05,
+            seed: None,
+            dt: 1e-4,
+            radius: 500.,
+        }
+    }
+}
+
+impl Settings {
+    /// Overrides the settings given in `block`.
+    pub fn apply(&mut self, block: &SimulationBlock) {
+        let value = |v: &Option<Value>| v.as_ref().map(|v| v.value);
+        self.end_time = value(&block.end_time).unwrap_or(self.end_time);
+        self.sample_every = value(&block.sample_every).unwrap_or(self.sample_every);
+        self.seed = block.seed.or(self.seed);
+        self.dt = value(&block.dt).unwrap_or(self.dt);
+        self.radius = value(&block.radius).unwrap_or(self.radius);
+    }
+    /// Volume of the simulated sphere, in nm³.
+    pub fn volume(&self) -> f32 {
+        4. / 3. * std::f32::consts::PI * self.radius.powi(3)
+    }
+    /// Number of steps in the simulation.
+    pub fn steps(&self) -> u64 {
+        (self.end_time / self.dt).round() as u64
+    }
+    /// Number of steps between two rows of the results.
+    pub fn steps_per_sample(&self) -> u64 {
+        ((self.sample_every / self.dt).round() as u64).max(1)
+    }
+}
+
+fn parse_positive(text: &str, accepted: &'static [Dimension]) -> Result<f32, String> {
+    let ParserSuccess {
+        content: (value, _),
+        next_input,
+    } = quantity(accepted, true)
+        .parse(text.into())
+        .map_err(|e| e.message())?;
+    if next_input.offset() != text.len() {
+        return Err(format!("unexpected `{}`", &text[next_input.offset()..]));
+    }
+    match value.value {
+        x if x > 0. => Ok(x),
+        x if x.is_nan() => Err("parameters cannot be used on the command line".to_string()),
+        _ => Err("must be positive".to_string()),
+    }
+}
+
+/// Parses a command line duration such as `100us`, in s.
+pub fn parse_duration(text: &str) -> Result<f32, String> {
+    parse_positive(text, &[Dimension::Time])
+}
+
+/// Parses a command line length such as `1 um`, in nm.
+pub fn parse_distance(text: &str) -> Result<f32, String> {
+    parse_positive(text, &[Dimension::Length])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Ast;
+
+    #[test]
+    fn t_steps() {
+        let settings = Settings {
+            end_time: 1.,
+            sample_every: 0.3,
+            dt: 0.1,
+            ..Settings::default()
+        };
+        assert_eq!(10, settings.steps());
+        assert_eq!(3, settings.steps_per_sample());
+        // A sample at least every step, even when asked for more often.
+        let settings = Settings {
+            sample_every: 0.01,
+            ..settings
+        };
+        assert_eq!(1, settings.steps_per_sample());
+        let settings = Settings {
+            end_time: 0.04,
+            ..settings
+        };
+        assert_eq!(0, settings.steps());
+    }
+
+    #[test]
+    fn t_apply() {
+        let mut settings = Settings::default();
+        let block = SimulationBlock::parse("simulation { seed = 1; dt = 1 ms; }".into())
+            .unwrap()
+            .content;
+        settings.apply(&block);
+        assert_eq!(
+            (Some(1), 1e-3, 60.),
+            (settings.seed, settings.dt, settings.end_time)
+        );
+        let block = SimulationBlock::parse("simulation { end_time = 1 s; }".into())
+            .unwrap()
+            .content;
+        settings.apply(&block);
+        assert_eq!(
+            (Some(1), 1e-3, 1.),
+            (settings.seed, settings.dt, settings.end_time)
+        );
+        assert_eq!(Err("must be positive".to_string()), parse_distance("0 nm"));
+        assert_eq!(Ok(1000.), parse_distance("1 um"));
+    }
+
+    #[test]
+    fn t_settings() {
+        let file = "param step = 50;
+                    simulation {
+                        end_time = 2 min;
+                        seed = 42;
+                        dt = step us;
+                    }
+                    simulation { radius = 1 um; }";
+        let ast = Ast::parse(file.into()).unwrap().content;
+        let settings = ast.settings();
+        assert_eq!(120., settings.end_time);
+        assert_eq!(0.05, settings.sample_every);
+        assert_eq!(Some(42), settings.seed);
+        assert_eq!(5e-5, settings.dt);
+        assert_eq!(1000., settings.radius);
+        assert_eq!(2_400_000, settings.steps());
+        assert_eq!(1000, settings.steps_per_sample());
+
+        let error = Ast::parse("simulation { dt = 1 us; dt = 2 us; }".into()).unwrap_err();
+        assert_eq!("Setting already given", error.message());
+        let error = Ast::parse("simulation { dt = 1 nm; }".into()).unwrap_err();
+        assert_eq!(
+            "Expected a time unit, found length unit nm",
+            error.message()
+        );
+        assert!(Ast::parse("simulation { steps = 10; }".into()).is_err());
+        assert_eq!(Ok(1e-4), parse_duration("100us"));
+        assert!(parse_duration("-1 s").is_err());
+    }
+}
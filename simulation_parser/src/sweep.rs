@@ -0,0 +1,343 @@
+use serde::{Deserialize, Serialize};
+
+use crate::brenda_parser_helpers::*;
+use crate::diagnostic::Span;
+use crate::formula::Value;
+use crate::parser_combinator::*;
+use crate::units::{quantity_before, Dimension};
+use crate::Parsable;
+
+/// `sweep km1 in 10..200 step 10;` or `sweep kcat in [50, 100, 200];`: values of a
+/// parameter to simulate the model with, one simulation per value. Several sweeps are
+/// combined, every value of one with every value of the others. Values are in the
+/// canonical unit of their dimension, like those of the parameter.
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+pub struct SweepDeclaration {
+    pub identifier: String,
+    /// Serialized as a `range` or `list` field.
+    #[serde(flatten)]
+    pub values: SweepValues,
+    #[serde(skip)]
+    pub span: Span,
+}
+
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SweepValues {
+    /// `10..200 step 10`, both ends included.
+    Range { from: Value, to: Value, step: Value },
+    /// `[50, 100, 200]`
+    List(Vec<Value>),
+}
+
+/// A value of a sweep, which may be followed by the `step` of a range.
+fn parse_value<'a>() -> impl Parser<'a, Value> {
+    quantity_before(Dimension::ALL, true, &["step"]).map(|(x, _)| x)
+}
+
+fn range(text: ParserInput<'_>) -> ParserResult<'_, SweepValues> {
+    let ParserSuccess {
+        content: from,
+        next_input,
+    } = parse_value().parse(text)?;
+    let ParserSuccess { next_input, .. } = between_spaces(literal("..")).parse(next_input)?;
+    let ParserSuccess {
+        content: to,
+        next_input,
+    } = parse_value().parse(next_input)?;
+    let ParserSuccess { next_input, .. } = between_spaces(literal("step")).parse(next_input)?;
+    let ParserSuccess {
+        content: step,
+        next_input,
+    } = parse_value().parse(next_input)?;
+    Ok(ParserSuccess {
+        content: SweepValues::Range { from, to, step },
+        next_input,
+    })
+}
+
+/// `km1 in 10..200 step 10`, the sweep without its keyword and `;`.
+fn sweep(text: ParserInput<'_>) -> ParserResult<'_, (String, SweepValues)> {
+    let ParserSuccess {
+        content: identifier,
+        next_input,
+    } = parse_identifier().parse(text)?;
+    let ParserSuccess { next_input, .. } = between_spaces(literal("in")).parse(next_input)?;
+    let ParserSuccess {
+        content: values,
+        next_input,
+    } = delimited(
+        parse_lbracket(),
+        parse_value().sep_by1(parse_comma()),
+        parse_rbracket(),
+    )
+    .map(SweepValues::List)
+    .or_else(range)
+    .parse(next_input)?;
+    Ok(ParserSuccess {
+        content: (identifier, values),
+        next_input,
+    })
+}
+
+impl Parsable for SweepDeclaration {
+    fn parse<'a>(text: ParserInput<'a>) -> ParserResult<'a, Self> {
+        let ParserSuccess {
+            next_input: start, ..
+        } = space0().parse(text)?;
+        let ParserSuccess { next_input, .. } = between_spaces(literal("sweep")).parse(start)?;
+        let ParserSuccess {
+            content: (identifier, values),
+            next_input,
+        } = sweep(next_input)?;
+        let ParserSuccess { next_input, .. } = parse_semicolon().parse(next_input)?;
+        Ok(ParserSuccess {
+            content: Self {
+                identifier,
+                values,
+                span: next_input.span_from(start),
+            },
+            next_input,
+        })
+    }
+}
+
+/// Parses a command line sweep such as `km1 in 10..200 step 10`, whose values are formulas
+/// without parameters.
+pub fn parse_sweep(text: &str) -> Result<SweepDeclaration, String> {
+    let ParserSuccess {
+        content: (identifier, values),
+        next_input,
+    } = sweep(text.into()).map_err(|e| e.message())?;
+    if next_input.offset() != text.len() {
+        return Err(format!("unexpected `{}`", &text[next_input.offset()..]));
+    }
+    let mut sweep = SweepDeclaration {
+        identifier,
+        values,
+        span: Span::default(),
+    };
+    for value in sweep.values_mut() {
+        value.resolve(&Default::default()).map_err(|d| d.message)?;
+    }
+    Ok(sweep)
+}
+
+impl SweepDeclaration {
+    pub fn values_mut(&mut self) -> Vec<&mut Value> {
+        match &mut self.values {
+            SweepValues::Range { from, to, step } => vec![from, to, step],
+            SweepValues::List(values) => values.iter_mut().collect(),
+        }
+    }
+    /// The values the parameter takes, once the formulas are resolved. A range whose step
+    /// is not positive only has its start.
+    pub fn points(&self) -> Vec<f64> {
+        match &self.values {
+            SweepValues::Range { from, to, step } => {
+                let (from, to, step) = (from.value as f64, to.value as f64, step.value as f64);
+                if step.is_nan() || step <= 0. || to < from {
+                    return vec![from];
+                }
+                // The end is included even when rounding puts it slightly past a step.
+                let n = ((to - from) / step + 1e-6).floor() as usize;
+                (0..=n).map(|i| from + i as f64 * step).collect()
+            }
+            SweepValues::List(values) => values.iter().map(|v| v.value as f64).collect(),
+        }
+    }
+}
+
+/// The parameter values of each simulation of the sweeps, the first sweep varying the
+/// slowest. A parameter swept twice takes the values of its last sweep, such as one given
+/// on the command line.
+pub fn grid<'s>(sweeps: impl IntoIterator<Item = &'s SweepDeclaration>) -> Vec<Vec<(String, f64)>> {
+    let mut sweeps = sweeps.into_iter().collect::<Vec<_>>();
+    let mut i = 0;
+    while i < sweeps.len() {
+        let identifier = &sweeps[i].identifier;
+        if sweeps[i + 1..].iter().any(|s| s.identifier == *identifier) {
+            sweeps.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+    let mut grid = vec![vec![]];
+    for sweep in sweeps {
+        grid = grid
+            .into_iter()
+            .flat_map(|point: Vec<(String, f64)>| {
+                sweep.points().into_iter().map(move |x| {
+                    let mut point = point.clone();
+                    point.push((sweep.identifier.clone(), x));
+                    point
+                })
+            })
+            .collect();
+    }
+    grid
+}
+
+/// Path of the results of the simulation `i` of a sweep writing its results to `output`:
+/// `results.3.csv` for `results.csv`.
+pub fn point_path(output: &str, i: usize) -> String {
+    let stem = output.strip_suffix(".csv").unwrap_or(output);
+    format!("{stem}.{i}.csv")
+}
+
+/// Path of the index of the results of a sweep writing its results to `output`:
+/// `results.index.csv` for `results.csv`.
+pub fn index_path(output: &str) -> String {
+    let stem = output.strip_suffix(".csv").unwrap_or(output);
+    format!("{stem}.index.csv")
+}
+
+/// Index of the results of a sweep, as CSV rows of the parameter values of each simulation
+/// followed by the file its results are in.
+pub fn index_csv(grid: &[Vec<(String, f64)>], files: &[String]) -> String {
+    let mut columns = grid
+        .first()
+        .map(|point| {
+            point
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect::<Vec<_>>()
+        })
+        .unwrap_or_default();
+    columns.push("file".to_string());
+    let mut csv = columns.join(", ");
+    for (point, file) in grid.iter().zip(files) {
+        csv.push('\n');
+        // Values were read as `f32`, whose digits are those worth printing.
+        for (_, x) in point {
+            csv.push_str(&format!("{}, ", *x as f32));
+        }
+        csv.push_str(file);
+    }
+    csv
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::check::check;
+    use crate::diagnostic::Severity;
+    use crate::keywords::Language;
+    use crate::settings::Settings;
+    use crate::{printer, Ast};
+
+    fn range(from: f32, to: f32, step: f32) -> SweepDeclaration {
+        SweepDeclaration {
+            identifier: "x".to_string(),
+            values: SweepValues::Range {
+                from: from.into(),
+                to: to.into(),
+                step: step.into(),
+            },
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn t_points() {
+        let points = range(10., 200., 10.).points();
+        assert_eq!(
+            (20, Some(&10.), Some(&200.)),
+            (points.len(), points.first(), points.last())
+        );
+        assert_eq!(11, range(0., 1., 0.1).points().len());
+        assert_eq!(vec![0., 0.25, 0.5], range(0., 0.6, 0.25).points());
+        assert_eq!(vec![5.], range(5., 5., 1.).points());
+        // Ranges without a positive step or going down only have their start.
+        assert_eq!(vec![5.], range(5., 1., 1.).points());
+        assert_eq!(vec![1.], range(1., 5., 0.).points());
+        assert_eq!(vec![1.], range(1., 5., -1.).points());
+        assert_eq!(vec![1.], range(1., 5., f32::NAN).points());
+        let list = SweepDeclaration {
+            values: SweepValues::List(vec![200.0.into(), 50.0.into(), 200.0.into()]),
+            ..range(0., 0., 1.)
+        };
+        assert_eq!(vec![200., 50., 200.], list.points());
+    }
+
+    #[test]
+    fn t_grid() {
+        let point = |a, b| vec![("a".to_string(), a), ("b".to_string(), b)];
+        assert_eq!(
+            vec![point(1., 3.), point(1., 4.), point(2., 3.), point(2., 4.)],
+            grid(&[
+                parse_sweep("a in [1, 2]").unwrap(),
+                parse_sweep("b in [3, 4]").unwrap(),
+            ])
+        );
+        let points = grid(&[
+            parse_sweep("a in [1, 2]").unwrap(),
+            parse_sweep("b in [3, 4, 5]").unwrap(),
+            parse_sweep("a in [6]").unwrap(),
+        ]);
+        assert_eq!(
+            vec![
+                vec![("b".to_string(), 3.), ("a".to_string(), 6.)],
+                vec![("b".to_string(), 4.), ("a".to_string(), 6.)],
+                vec![("b".to_string(), 5.), ("a".to_string(), 6.)],
+            ],
+            points
+        );
+        let files = (0..3)
+            .map(|i| point_path("out/results.csv", i))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            "b, a, file\n3, 6, out/results.0.csv\n4, 6, out/results.1.csv\n5, 6, out/results.2.csv",
+            index_csv(&points, &files)
+        );
+        assert_eq!("out/results.index.csv", index_path("out/results.csv"));
+        assert_eq!(vec![Vec::<(String, f64)>::new()], grid(&[]));
+    }
+
+    #[test]
+    fn t_sweeps() {
+        let parse = |text: &str| SweepDeclaration::parse(text.into()).map(|s| s.content);
+        let km = parse("sweep km1 in 10..200 step 10;").unwrap();
+        assert_eq!(
+            parse_sweep("km1 in 10..200 step 10").unwrap().values,
+            km.values
+        );
+        assert_eq!(
+            vec![2e3, 3e3],
+            parse_sweep("km1 in 2 mM..3 mM step 1 mM").unwrap().points()
+        );
+        assert!(parse("sweep km1 in 10..200;").is_err());
+        assert!(parse("sweep km1 in [];").is_err());
+        assert!(parse_sweep("km1 in [1, 2] 3").is_err());
+        assert!(parse_sweep("km1 in [p]").is_err());
+
+        let model = "param km1 = 100 uM;
+
+sweep km1 in 10 uM..200 uM step 10 uM;
+sweep kcat in [50, 100];
+
+E1 : s -> p | km1 - 100;
+";
+        let ast = Ast::parse(model.into()).unwrap().content;
+        assert_eq!(model, printer::print(&ast, Language::French));
+        assert_eq!(20, ast.sweeps().next().unwrap().points().len());
+        let messages = check(&ast, &Settings::default())
+            .into_iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert_eq!(vec!["`kcat` is swept but is not a parameter"], messages);
+
+        let ast = Ast::parse("param k = 1; sweep k in 5..1 step 0; 0 -> s @ k = k;".into())
+            .unwrap()
+            .content;
+        let messages = check(&ast, &Settings::default())
+            .into_iter()
+            .map(|d| d.message)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec!["the step of the sweep is 0, but must be positive"],
+            messages
+        );
+    }
+}
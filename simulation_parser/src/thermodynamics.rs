@@ -0,0 +1,101 @@
+use crate::diagnostic::Diagnostic;
+use crate::{Ast, Expression, Reaction};
+
+/// Relative difference above which two equilibrium constants are considered different.
+const KEQ_TOLERANCE: f32 = 0.01;
+
+fn differ(a: f32, b: f32) -> bool {
+    (a - b).abs() > KEQ_TOLERANCE * a.abs().max(b.abs())
+}
+
+fn sorted(species: &[(String, u32)]) -> Vec<(String, u32)> {
+    let mut species = species.to_vec();
+    species.sort();
+    species
+}
+
+/// Checks that the kinetics of reversible reactions satisfy the Haldane relationship: the
+/// equilibrium constant they imply must match the declared `keq`, and every enzyme
+/// catalysing the same conversion must imply the same equilibrium constant.
+pub fn haldane_warnings(Ast(expressions): &Ast) -> Vec<Diagnostic> {
+    let reversible = expressions
+        .iter()
+        .filter_map(|e| match e {
+            Expression::Reaction(r) => r.haldane_keq().map(|keq| (r, keq)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let mut warnings = vec![];
+
+    for (r, keq) in &reversible {
+        if let Some(declared) = r.reverse.as_ref().and_then(|reverse| reverse.keq.as_ref()) {
+            let declared = declared.value;
+            if differ(*keq, declared) {
+                warnings.push(
+                    Diagnostic::warning(
+                        format!(
+                            "kinetics of {} imply Keq = {} but keq = {} was declared",
+                            r.enzhym, keq, declared
+                        ),
+                        r.span,
+                    )
+                    .with_note("Haldane relationship: Keq = (kcat_f Km_r) / (kcat_r Km_f)".into()),
+                );
+            }
+        }
+    }
+
+    for (i, (r1, keq1)) in reversible.iter().enumerate() {
+        for (r2, keq2) in &reversible[..i] {
+            let keq2 = if same_conversion(r1, r2) {
+                *keq2
+            } else if same_conversion(r1, &r2.reversed().unwrap()) {
+                1. / keq2
+            } else {
+                continue;
+            };
+            if differ(*keq1, keq2) {
+                warnings.push(
+                    Diagnostic::warning(
+                        format!(
+                            "kinetics of {} imply Keq = {} for a conversion {} catalyses with Keq = {}",
+                            r1.enzhym, keq1, r2.enzhym, keq2
+                        ),
+                        r1.span,
+                    )
+                    .with_note("an equilibrium constant does not depend on the enzyme".into()),
+                );
+            }
+        }
+    }
+    warnings
+}
+
+fn same_conversion(r1: &Reaction, r2: &Reaction) -> bool {
+    sorted(&r1.solubes) == sorted(&r2.solubes) && sorted(&r1.results) == sorted(&r2.results)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::Parsable;
+
+    #[test]
+    fn t_haldane() {
+        assert!(!differ(1., 1.005));
+        assert!(differ(-1., -1.02));
+        let file = "E1 : s <-> p | 100 uM - 10, 50 uM - 10, keq = 1;
+                    E2 : p <-> s | 100 uM - 10, 100 uM - 10;";
+        let ast = Ast::parse(file.into()).unwrap().content;
+        let warnings = haldane_warnings(&ast);
+        assert_eq!(2, warnings.len());
+        assert_eq!(
+            "kinetics of E1 imply Keq = 0.5 but keq = 1 was declared",
+            warnings[0].message
+        );
+        assert_eq!(
+            "kinetics of E2 imply Keq = 1 for a conversion E1 catalyses with Keq = 2",
+            warnings[1].message
+        );
+    }
+}
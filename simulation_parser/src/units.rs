@@ -0,0 +1,308 @@
+use std::fmt::Display;
+
+use crate::formula::{formula, Formula, Value};
+use crate::parser_combinator::{anychar, between_spaces, Parser, ParserInput, ParserSuccess};
+
+/// Physical dimension of a quantity written in a model file.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Dimension {
+    /// Canonical unit: µM
+    Concentration,
+    /// Canonical unit: µM/s
+    Flux,
+    /// Canonical unit: s⁻¹
+    Rate,
+    /// Canonical unit: µM⁻¹·s⁻¹
+    BimolecularRate,
+    /// Canonical unit: nm
+    Length,
+    /// Canonical unit: µm²/s
+    Diffusion,
+    /// Canonical unit: s
+    Time,
+    /// Canonical unit: fL, that is µm³
+    Volume,
+}
+
+impl Dimension {
+    pub const ALL: &'static [Dimension] = &[
+        Dimension::Concentration,
+        Dimension::Flux,
+        Dimension::Rate,
+        Dimension::BimolecularRate,
+        Dimension::Length,
+        Dimension::Diffusion,
+        Dimension::Time,
+        Dimension::Volume,
+    ];
+}
+
+impl Display for Dimension {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Dimension::Concentration => write!(f, "concentration"),
+            Dimension::Flux => write!(f, "flux"),
+            Dimension::Rate => write!(f, "rate"),
+            Dimension::BimolecularRate => write!(f, "bimolecular rate"),
+            Dimension::Length => write!(f, "length"),
+            Dimension::Diffusion => write!(f, "diffusion"),
+            Dimension::Time => write!(f, "time"),
+            Dimension::Volume => write!(f, "volume"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Unit {
+    pub symbol: &'static str,
+    pub dimension: Dimension,
+    /// Multiply a value in this unit by `factor` to get it in the canonical unit.
+    pub factor: f64,
+}
+
+const fn unit(symbol: &'static str, dimension: Dimension, factor: f64) -> Unit {
+    Unit {
+        symbol,
+        dimension,
+        factor,
+    }
+}
+
+// Longer symbols come first so that `µm²/s` is not read as `µm`.
+pub const UNITS: &[Unit] = &[
+    unit("/M/s", Dimension::BimolecularRate, 1e-6),
+    unit("M⁻¹s⁻¹", Dimension::BimolecularRate, 1e-6),
+    unit("M-1s-1", Dimension::BimolecularRate, 1e-6),
+    unit("/mM/s", Dimension::BimolecularRate, 1e-3),
+    unit("mM⁻¹s⁻¹", Dimension::BimolecularRate, 1e-3),
+    unit("mM-1s-1", Dimension::BimolecularRate, 1e-3),
+    unit("/µM/s", Dimension::BimolecularRate, 1.),
+    unit("µM⁻¹s⁻¹", Dimension::BimolecularRate, 1.),
+    unit("/uM/s", Dimension::BimolecularRate, 1.),
+    unit("uM-1s-1", Dimension::BimolecularRate, 1.),
+    unit("/nM/s", Dimension::BimolecularRate, 1e3),
+    unit("nM⁻¹s⁻¹", Dimension::BimolecularRate, 1e3),
+    unit("nM-1s-1", Dimension::BimolecularRate, 1e3),
+    unit("M/s", Dimension::Flux, 1e6),
+    unit("mM/s", Dimension::Flux, 1e3),
+    unit("µM/s", Dimension::Flux, 1.),
+    unit("uM/s", Dimension::Flux, 1.),
+    unit("nM/s", Dimension::Flux, 1e-3),
+    unit("µm²/s", Dimension::Diffusion, 1.),
+    unit("μm²/s", Dimension::Diffusion, 1.),
+    unit("µm^2/s", Dimension::Diffusion, 1.),
+    unit("um^2/s", Dimension::Diffusion, 1.),
+    unit("um2/s", Dimension::Diffusion, 1.),
+    unit("µm³", Dimension::Volume, 1.),
+    unit("μm³", Dimension::Volume, 1.),
+    unit("um^3", Dimension::Volume, 1.),
+    unit("um3", Dimension::Volume, 1.),
+    unit("min⁻¹", Dimension::Rate, 1. / 60.),
+    unit("min-1", Dimension::Rate, 1. / 60.),
+    unit("/min", Dimension::Rate, 1. / 60.),
+    unit("s⁻¹", Dimension::Rate, 1.),
+    unit("s-1", Dimension::Rate, 1.),
+    unit("/s", Dimension::Rate, 1.),
+    unit("min", Dimension::Time, 60.),
+    unit("ms", Dimension::Time, 1e-3),
+    unit("µs", Dimension::Time, 1e-6),
+    unit("μs", Dimension::Time, 1e-6),
+    unit("us", Dimension::Time, 1e-6),
+    unit("ns", Dimension::Time, 1e-9),
+    unit("h", Dimension::Time, 3600.),
+    unit("s", Dimension::Time, 1.),
+    unit("mM", Dimension::Concentration, 1e3),
+    unit("µM", Dimension::Concentration, 1.),
+    unit("μM", Dimension::Concentration, 1.),
+    unit("uM", Dimension::Concentration, 1.),
+    // Kept for models written before units were supported.
+    unit("uN", Dimension::Concentration, 1.),
+    unit("nM", Dimension::Concentration, 1e-3),
+    unit("M", Dimension::Concentration, 1e6),
+    unit("fL", Dimension::Volume, 1.),
+    unit("pL", Dimension::Volume, 1e3),
+    unit("nL", Dimension::Volume, 1e6),
+    unit("nm", Dimension::Length, 1.),
+    unit("µm", Dimension::Length, 1e3),
+    unit("μm", Dimension::Length, 1e3),
+    unit("um", Dimension::Length, 1e3),
+];
+
+/// The first of [`UNITS`] the input starts with, not followed by a letter or digit.
+pub fn any_unit<'a>() -> impl Parser<'a, Unit> {
+    move |input: ParserInput<'a>| {
+        UNITS
+            .iter()
+            .find(|u| {
+                input
+                    .rest()
+                    .strip_prefix(u.symbol)
+                    .is_some_and(|rest| !rest.starts_with(char::is_alphanumeric))
+            })
+            .map(|u| ParserSuccess {
+                content: *u,
+                next_input: input.at(input.offset() + u.symbol.len()),
+            })
+            .ok_or_else(|| input.expected("unit"))
+    }
+}
+
+/// A word that looks like a unit. Words with an underscore, such as the `inhibited_by`
+/// following the kinetics of a reaction, are not, nor are the `keywords` the caller
+/// expects next.
+fn unknown_unit<'a>(keywords: &'static [&'static str]) -> impl Parser<'a, String> {
+    anychar
+        .predicate(|c| c.is_alphabetic() || *c == '/', "Expected unit")
+        .chain(
+            anychar
+                .predicate(
+                    |c| !c.is_whitespace() && !";,()|".contains(*c),
+                    "Expected unit",
+                )
+                .zero_or_more(),
+        )
+        .map(|(head, tail)| std::iter::once(head).chain(tail).collect::<String>())
+        .predicate(
+            move |symbol| !symbol.contains('_') && !keywords.contains(&symbol.as_str()),
+            "Expected unit",
+        )
+}
+
+/// A formula optionally followed by a unit of one of the `accepted` dimensions.
+/// The value is converted into the canonical unit of that dimension; a bare formula is
+/// taken to already be in canonical units, in which case no dimension is returned.
+/// `subtraction` is forwarded to [`formula`].
+pub fn quantity<'a>(
+    accepted: &'static [Dimension],
+    subtraction: bool,
+) -> impl Parser<'a, (Value, Option<Dimension>)> {
+    quantity_before(accepted, subtraction, &[])
+}
+
+/// A [`quantity`] followed by one of `keywords`, which are not taken for unknown units.
+pub fn quantity_before<'a>(
+    accepted: &'static [Dimension],
+    subtraction: bool,
+    keywords: &'static [&'static str],
+) -> impl Parser<'a, (Value, Option<Dimension>)> {
+    move |input| {
+        let ParserSuccess {
+            content: value,
+            next_input,
+        } = formula(subtraction).parse(input)?;
+        match between_spaces(any_unit()).parse(next_input) {
+            Ok(ParserSuccess {
+                content: u,
+                next_input,
+            }) if accepted.contains(&u.dimension) => Ok(ParserSuccess {
+                content: (
+                    Value::new(Formula::Unit(Box::new(value), u)),
+                    Some(u.dimension),
+                ),
+                next_input,
+            }),
+            Ok(ParserSuccess { content: u, .. }) if accepted.is_empty() => Err(next_input
+                .generate_error(format!(
+                    "Expected a dimensionless number, found {} unit {}",
+                    u.dimension, u.symbol
+                ))),
+            Ok(ParserSuccess { content: u, .. }) => Err(next_input.generate_error(format!(
+                "Expected a {} unit, found {} unit {}",
+                accepted
+                    .iter()
+                    .map(|d| d.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" or "),
+                u.dimension,
+                u.symbol
+            ))),
+            Err(_) => match unknown_unit(keywords).parse(next_input) {
+                Ok(ParserSuccess {
+                    content: symbol, ..
+                }) => Err(next_input.generate_error(format!("Unknown unit {symbol}"))),
+                Err(_) => Ok(ParserSuccess {
+                    content: (Value::new(value), None),
+                    next_input,
+                }),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{DiameterDeclaration, Parsable, Reaction, Speed, SpeedDeclaration};
+
+    #[test]
+    fn t_quantity() {
+        let parse = |text: &str, accepted: &'static [Dimension], keywords| {
+            quantity_before(accepted, true, keywords)
+                .parse(text.into())
+                .map(|s| (s.content.0.value, s.content.1))
+                .map_err(|e| e.message())
+        };
+        let concentration = &[Dimension::Concentration];
+        assert_eq!(
+            Ok((200., Some(Dimension::Concentration))),
+            parse("0.2 mM", concentration, &[])
+        );
+        assert_eq!(Ok((4., None)), parse("2 * 2", concentration, &[]));
+        assert_eq!(
+            Err("Expected a dimensionless number, found time unit s".to_string()),
+            parse("3 s", &[], &[])
+        );
+        assert_eq!(
+            Err("Unknown unit step".to_string()),
+            parse("10 step 1", concentration, &[])
+        );
+        assert_eq!(
+            Ok((10., None)),
+            parse("10 step 1", concentration, &["step"])
+        );
+        assert_eq!(
+            Ok((10., None)),
+            parse("10 inhibited_by", concentration, &[])
+        );
+    }
+
+    #[test]
+    fn t_units() {
+        let reaction = Reaction::parse("E1 : s -> p | 0.2 mM - 6000 min-1;".into())
+            .unwrap()
+            .content;
+        assert_eq!((200., 100.), (reaction.km.value, reaction.kcat.value));
+        let reaction = Reaction::parse("E1 : s -> p | 50nM - 3 s⁻¹;".into())
+            .unwrap()
+            .content;
+        assert_eq!((0.05, 3.), (reaction.km.value, reaction.kcat.value));
+        assert_eq!(
+            10.,
+            match SpeedDeclaration::parse("vitesse(E1) = 10 µm²/s;".into())
+                .unwrap()
+                .content
+                .speed
+            {
+                Speed::Diffusion(d) => d.value,
+                Speed::Step(_) => panic!("expected a diffusion coefficient"),
+            }
+        );
+        assert_eq!(
+            2000.,
+            DiameterDeclaration::parse("diametre(E1) = 2 um;".into())
+                .unwrap()
+                .content
+                .diameter
+                .value
+        );
+
+        let error = Reaction::parse("E1 : s -> p | 200mM - 100mM;".into()).unwrap_err();
+        assert_eq!((0, 25), (error.line, error.col));
+        assert_eq!(
+            "Expected a rate unit, found concentration unit mM",
+            error.message()
+        );
+        let error = Reaction::parse("E1 : s -> p | 200 mol - 100;".into()).unwrap_err();
+        assert_eq!("Unknown unit mol", error.message());
+    }
+}